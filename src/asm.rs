@@ -0,0 +1,409 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::conf::{RAM_SIZE, START_ADDR};
+
+/// Where an emitted instruction or data directive came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub addr: u16,
+    pub file: PathBuf,
+    pub line: usize,
+    /// Emitted by `db`/`dw` rather than an instruction
+    pub data: bool,
+}
+
+/// Output of the assembler: a ROM loadable with `Chip8VM::load`, plus debugging metadata.
+#[derive(Debug, Default)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    /// Label addresses, sorted by address.
+    pub labels: Vec<(String, u16)>,
+    /// One entry per instruction or data directive, in address order.
+    pub source_map: Vec<SourceLocation>,
+}
+
+enum Item {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+struct Statement {
+    item: Item,
+    addr: u16,
+    file: PathBuf,
+    line: usize,
+}
+
+#[derive(Default)]
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, u16>,
+    include_stack: Vec<PathBuf>,
+    addr: u16,
+}
+
+/// Assembles the source file at `path`. `include` paths are resolved relative to the file
+/// containing the directive.
+pub fn assemble_file(path: &Path) -> Result<Assembly> {
+    let mut asm = Assembler {
+        addr: START_ADDR,
+        ..Default::default()
+    };
+    asm.parse_file(path)?;
+    asm.encode()
+}
+
+/// Assembles in-memory source, `name` being used in error messages. `include` paths are
+/// resolved relative to the working directory.
+pub fn assemble_str(source: &str, name: &str) -> Result<Assembly> {
+    let mut asm = Assembler {
+        addr: START_ADDR,
+        ..Default::default()
+    };
+    asm.parse_source(source, Path::new(name), Path::new("."))?;
+    asm.encode()
+}
+
+impl Assembler {
+    fn parse_file(&mut self, path: &Path) -> Result<()> {
+        let canonical = fs::canonicalize(path)
+            .with_context(|| format!("Failed to open source file: {}", path.display()))?;
+        if self.include_stack.contains(&canonical) {
+            bail!("Recursive include of {}", path.display());
+        }
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read source file: {}", path.display()))?;
+
+        self.include_stack.push(canonical);
+        let dir = path.parent().unwrap_or(Path::new("."));
+        self.parse_source(&source, path, dir)?;
+        self.include_stack.pop();
+        Ok(())
+    }
+
+    fn parse_source(&mut self, source: &str, file: &Path, dir: &Path) -> Result<()> {
+        for (idx, raw_line) in source.lines().enumerate() {
+            let line = idx + 1;
+            self.parse_line(raw_line, file, dir, line)
+                .map_err(|e| anyhow!("{}:{}: {}", file.display(), line, e))?;
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, raw_line: &str, file: &Path, dir: &Path, line: usize) -> Result<()> {
+        let mut text = raw_line.split(';').next().unwrap_or("").trim();
+
+        // Leading labels, possibly followed by a statement on the same line
+        while let Some((label, rest)) = split_label(text) {
+            if self.labels.contains_key(label) || self.constants.contains_key(label) {
+                bail!("Duplicate symbol: {}", label);
+            }
+            self.labels.insert(label.to_string(), self.addr);
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (head, rest) = match text.split_once(char::is_whitespace) {
+            Some((head, rest)) => (head, rest.trim()),
+            None => (text, ""),
+        };
+
+        // NAME equ VALUE
+        if let Some((op, value)) = rest.split_once(char::is_whitespace) {
+            if op.eq_ignore_ascii_case("equ") {
+                if self.labels.contains_key(head) || self.constants.contains_key(head) {
+                    bail!("Duplicate symbol: {}", head);
+                }
+                let value = self.eval(value.trim())?;
+                self.constants.insert(head.to_string(), value);
+                return Ok(());
+            }
+        }
+
+        let mnemonic = head.to_ascii_uppercase();
+        let operands = split_operands(rest);
+        let (item, size) = match mnemonic.as_str() {
+            "INCLUDE" => {
+                let name = rest.trim_matches('"');
+                if name.is_empty() {
+                    bail!("Usage: include \"file\"");
+                }
+                return self.parse_file(&dir.join(name));
+            }
+            "DB" => {
+                let size = operands.len();
+                (Item::Bytes(operands), size)
+            }
+            "DW" => {
+                let size = operands.len() * 2;
+                (Item::Words(operands), size)
+            }
+            _ => (Item::Instruction { mnemonic, operands }, 2),
+        };
+
+        self.statements.push(Statement {
+            item,
+            addr: self.addr,
+            file: file.to_path_buf(),
+            line,
+        });
+        self.addr = match self.addr.checked_add(size as u16) {
+            Some(addr) if (addr as usize) <= RAM_SIZE => addr,
+            _ => bail!("Program does not fit in memory"),
+        };
+        Ok(())
+    }
+
+    fn encode(self) -> Result<Assembly> {
+        let mut assembly = Assembly::default();
+
+        for statement in &self.statements {
+            let located = |e: anyhow::Error| {
+                anyhow!("{}:{}: {}", statement.file.display(), statement.line, e)
+            };
+            match &statement.item {
+                Item::Instruction { mnemonic, operands } => {
+                    let opcode = self
+                        .encode_instruction(mnemonic, operands)
+                        .map_err(located)?;
+                    assembly.rom.extend_from_slice(&opcode.to_be_bytes());
+                }
+                Item::Bytes(values) => {
+                    for value in values {
+                        let byte = self.eval_sized(value, 0xFF, "Byte").map_err(located)?;
+                        assembly.rom.push(byte as u8);
+                    }
+                }
+                Item::Words(values) => {
+                    for value in values {
+                        let word = self.eval(value).map_err(located)?;
+                        assembly.rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+            assembly.source_map.push(SourceLocation {
+                addr: statement.addr,
+                file: statement.file.clone(),
+                line: statement.line,
+                data: !matches!(statement.item, Item::Instruction { .. }),
+            });
+        }
+
+        assembly.labels = self.labels.into_iter().collect();
+        assembly
+            .labels
+            .sort_by_key(|(name, addr)| (*addr, name.clone()));
+        Ok(assembly)
+    }
+
+    fn encode_instruction(&self, mnemonic: &str, operands: &[String]) -> Result<u16> {
+        let ops: Vec<Operand> = operands.iter().map(|op| Operand::parse(op)).collect();
+        let addr = |expr: &str| self.eval_sized(expr, 0xFFF, "Address");
+        let byte = |expr: &str| self.eval_sized(expr, 0xFF, "Byte");
+        let nibble = |expr: &str| self.eval_sized(expr, 0xF, "Nibble");
+        let xy = |x: u16, y: u16| (x << 8) | (y << 4);
+
+        use Operand::*;
+        let opcode = match (mnemonic, ops.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("SCD", [Expr(n)]) => 0x00C0 | nibble(n)?,
+            ("SYS", [Expr(a)]) => addr(a)?,
+            ("JP", [Expr(a)]) => 0x1000 | addr(a)?,
+            ("JP", [V(0), Expr(a)]) => 0xB000 | addr(a)?,
+            ("CALL", [Expr(a)]) => 0x2000 | addr(a)?,
+            ("SE", [V(x), Expr(b)]) => 0x3000 | (x << 8) | byte(b)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+            ("SNE", [V(x), Expr(b)]) => 0x4000 | (x << 8) | byte(b)?,
+            ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+            ("LD", [V(x), Expr(b)]) => 0x6000 | (x << 8) | byte(b)?,
+            ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+            ("LD", [I, Expr(a)]) => 0xA000 | addr(a)?,
+            ("LD", [V(x), Dt]) => 0xF007 | (x << 8),
+            ("LD", [V(x), K]) => 0xF00A | (x << 8),
+            ("LD", [Dt, V(x)]) => 0xF015 | (x << 8),
+            ("LD", [St, V(x)]) => 0xF018 | (x << 8),
+            ("LD", [F, V(x)]) => 0xF029 | (x << 8),
+            ("LD", [Hf, V(x)]) => 0xF030 | (x << 8),
+            ("LD", [B, V(x)]) => 0xF033 | (x << 8),
+            ("LD", [IndirectI, V(x)]) => 0xF055 | (x << 8),
+            ("LD", [V(x), IndirectI]) => 0xF065 | (x << 8),
+            ("LD", [R, V(x)]) => 0xF075 | (x << 8),
+            ("LD", [V(x), R]) => 0xF085 | (x << 8),
+            ("ADD", [V(x), Expr(b)]) => 0x7000 | (x << 8) | byte(b)?,
+            ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+            ("ADD", [I, V(x)]) => 0xF01E | (x << 8),
+            ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+            ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+            ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+            ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+            ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+            // The source register is optional for shifts, defaulting to VX itself so the
+            // result does not depend on the shift quirk
+            ("SHR", [V(x)]) => 0x8006 | xy(*x, *x),
+            ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+            ("SHL", [V(x)]) => 0x800E | xy(*x, *x),
+            ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+            ("RND", [V(x), Expr(b)]) => 0xC000 | (x << 8) | byte(b)?,
+            ("DRW", [V(x), V(y), Expr(n)]) => 0xD000 | xy(*x, *y) | nibble(n)?,
+            ("SKP", [V(x)]) => 0xE09E | (x << 8),
+            ("SKNP", [V(x)]) => 0xE0A1 | (x << 8),
+            _ if !KNOWN_MNEMONICS.contains(&mnemonic) => bail!("Unknown mnemonic: {}", mnemonic),
+            _ => bail!("Invalid operands for {}: {}", mnemonic, operands.join(", ")),
+        };
+        Ok(opcode)
+    }
+
+    fn eval_sized(&self, expr: &str, max: u16, what: &str) -> Result<u16> {
+        let value = self.eval(expr)?;
+        if value > max {
+            bail!("{} out of range (max 0x{:X}): {}", what, max, expr);
+        }
+        Ok(value)
+    }
+
+    /// Evaluates `term (+|- term)*`, terms being numbers, constants or labels. Constants are
+    /// evaluated while parsing, so they can only refer to symbols defined above them.
+    fn eval(&self, expr: &str) -> Result<u16> {
+        let mut total: i32 = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+
+        for token in tokenize_expr(expr) {
+            match token {
+                "+" | "-" if !expect_term => {
+                    sign = if token == "+" { 1 } else { -1 };
+                    expect_term = true;
+                }
+                "-" if expect_term => sign = -sign,
+                _ if expect_term => {
+                    total += sign * self.eval_term(token)? as i32;
+                    sign = 1;
+                    expect_term = false;
+                }
+                _ => bail!("Invalid expression: {}", expr),
+            }
+        }
+        if expect_term {
+            bail!("Invalid expression: {}", expr);
+        }
+
+        u16::try_from(total).map_err(|_| anyhow!("Value out of range: {}", expr))
+    }
+
+    fn eval_term(&self, term: &str) -> Result<u16> {
+        if term.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(term);
+        }
+        self.constants
+            .get(term)
+            .or_else(|| self.labels.get(term))
+            .copied()
+            .with_context(|| format!("Undefined symbol: {}", term))
+    }
+}
+
+const KNOWN_MNEMONICS: [&str; 26] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SCD", "SYS", "JP", "CALL", "SE", "SNE",
+    "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP",
+];
+
+enum Operand<'a> {
+    V(u16),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Expr(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    fn parse(text: &'a str) -> Self {
+        let upper = text.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => return Operand::I,
+            "[I]" => return Operand::IndirectI,
+            "DT" => return Operand::Dt,
+            "ST" => return Operand::St,
+            "K" => return Operand::K,
+            "F" => return Operand::F,
+            "HF" => return Operand::Hf,
+            "B" => return Operand::B,
+            "R" => return Operand::R,
+            _ => {}
+        }
+        match upper.strip_prefix('V') {
+            Some(reg) if reg.len() == 1 => match u16::from_str_radix(reg, 16) {
+                Ok(x) => Operand::V(x),
+                Err(_) => Operand::Expr(text),
+            },
+            _ => Operand::Expr(text),
+        }
+    }
+}
+
+/// Splits `name:` off the start of a line.
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+    let valid = label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some((label, rest))
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(|op| op.trim().to_string()).collect()
+}
+
+fn tokenize_expr(expr: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for (idx, c) in expr.char_indices() {
+        if c == '+' || c == '-' {
+            tokens.push(expr[start..idx].trim());
+            tokens.push(&expr[idx..idx + 1]);
+            start = idx + 1;
+        }
+    }
+    tokens.push(expr[start..].trim());
+    tokens
+        .into_iter()
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+fn parse_number(text: &str) -> Result<u16> {
+    let lower = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u16::from_str_radix(bin, 2)
+    } else {
+        lower.parse()
+    };
+    parsed.map_err(|_| anyhow!("Invalid number: {}", text))
+}
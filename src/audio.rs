@@ -0,0 +1,161 @@
+use anyhow::{bail, Error};
+use std::{f32::consts::TAU, str::FromStr};
+
+use crate::{conf::AUDIO_PATTERN_SIZE, vm::Chip8VM};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => bail!(
+                "Unknown waveform: {} (expected square, triangle, sawtooth or sine)",
+                name
+            ),
+        }
+    }
+}
+
+impl Waveform {
+    /// Amplitude in [-1, 1] at `phase`, a fraction of the period in [0, 1).
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * TAU).sin(),
+        }
+    }
+}
+
+/// The buzzer tone played while the sound timer is non-zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Pitch in Hz
+    pub frequency: f32,
+    /// Amplitude from 0 (silent) to 1 (full scale)
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+            waveform: Waveform::default(),
+        }
+    }
+}
+
+/// What a frontend should play during a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sound {
+    Silent,
+    /// The buzzer tone
+    Tone,
+    /// An XO-CHIP audio pattern: 128 one-bit samples, most significant bit first, looped at
+    /// `pattern_rate(pitch)` samples per second
+    Pattern {
+        pattern: [u8; AUDIO_PATTERN_SIZE],
+        pitch: u8,
+    },
+}
+
+impl Sound {
+    /// The sound of `chip8` while its sound timer runs. XO-CHIP plays the loaded audio
+    /// pattern, or the tone as long as F002 has not loaded one.
+    pub fn of(chip8: &Chip8VM) -> Sound {
+        let state = chip8.get_state();
+        if state.sound_timer == 0 {
+            Sound::Silent
+        } else if chip8.active_extensions().xochip && state.audio_pattern != [0; AUDIO_PATTERN_SIZE]
+        {
+            Sound::Pattern {
+                pattern: state.audio_pattern,
+                pitch: state.pitch,
+            }
+        } else {
+            Sound::Tone
+        }
+    }
+}
+
+/// Playback rate of XO-CHIP audio patterns in bits per second: 4000 at the default pitch of
+/// 64, doubling every 48 steps.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// Generates the tone as 16-bit mono samples, for the frontend to stream to its audio device.
+/// The phase carries over between buffers so the sound sustains without clicks.
+#[derive(Debug, Clone)]
+pub struct Synth {
+    tone: Tone,
+    sample_rate: u32,
+    phase: f32,
+}
+
+impl Synth {
+    pub fn new(tone: Tone, sample_rate: u32) -> Self {
+        Synth {
+            tone,
+            sample_rate,
+            phase: 0.0,
+        }
+    }
+
+    /// Fills `buffer` with `sound`. Patterns are played as a square wave, 1 bits high and
+    /// 0 bits low, at the tone's volume.
+    pub fn fill(&mut self, buffer: &mut [i16], sound: Sound) {
+        let amplitude = self.tone.volume.clamp(0.0, 1.0) * i16::MAX as f32;
+        match sound {
+            Sound::Silent => {
+                buffer.fill(0);
+                // Restart on a rising edge next time rather than mid-period
+                self.phase = 0.0;
+            }
+            Sound::Tone => {
+                let step = self.tone.frequency / self.sample_rate as f32;
+                for sample in buffer.iter_mut() {
+                    *sample = (self.tone.waveform.sample(self.phase) * amplitude) as i16;
+                    self.phase = (self.phase + step).fract();
+                }
+            }
+            Sound::Pattern { pattern, pitch } => {
+                // The phase is the position in the whole pattern
+                let bits = (AUDIO_PATTERN_SIZE * 8) as f32;
+                let step = pattern_rate(pitch) / bits / self.sample_rate as f32;
+                for sample in buffer.iter_mut() {
+                    let bit = (self.phase * bits) as usize;
+                    let high = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                    *sample = if high { amplitude } else { -amplitude } as i16;
+                    self.phase = (self.phase + step).fract();
+                }
+            }
+        }
+    }
+}
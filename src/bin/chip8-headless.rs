@@ -0,0 +1,578 @@
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    net::TcpListener,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use chip8::asm;
+use chip8::conf::{DEFAULT_IPS, START_ADDR};
+use chip8::coverage::Coverage;
+use chip8::dap::DapServer;
+use chip8::debugger::{self, Debugger, PROFILE_LINES};
+use chip8::disasm;
+use chip8::extensions::ActiveExtensions;
+use chip8::gdbstub::GdbStub;
+use chip8::headless::{self, StopCondition, StopReason};
+use chip8::instruction::OpcodePattern;
+use chip8::movie::{self, Movie};
+use chip8::profile::Profiler;
+use chip8::quirks::Quirks;
+use chip8::symbols::SymbolTable;
+use chip8::timing::Timing;
+use chip8::trace::{self, TraceFilter, TraceFormat, Tracer};
+use chip8::vm::Chip8VM;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Headless CHIP-8 tools, no window or audio device needed.", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a ROM for a number of frames and dump the final framebuffer
+    Run(Box<RunArgs>),
+    /// Print an annotated listing of a ROM
+    Disasm(DisasmArgs),
+    /// Assemble mnemonic source into a ROM
+    Asm(AsmArgs),
+    /// Serve a ROM to GDB over the remote serial protocol
+    Gdb(GdbArgs),
+    /// Serve a ROM to an editor over the Debug Adapter Protocol
+    Dap(DapArgs),
+    /// Convert a binary trace to the text format
+    DumpTrace(DumpTraceArgs),
+    /// Replay a movie and dump the final framebuffer
+    Replay(ReplayArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Text,
+    Pbm,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CoverageFormat {
+    /// Disassembly of the ROM annotated with execution counts
+    Listing,
+    /// lcov tracefile of the `--source` the ROM was assembled from
+    Lcov,
+}
+
+#[derive(clap::Args, Debug)]
+struct MachineArgs {
+    /// Path to the CHIP-8 ROM file to load
+    rom_path: PathBuf,
+
+    #[arg(short = 's', long)]
+    enable_schip: bool,
+
+    /// Enable XO-CHIP (implies S-CHIP)
+    #[arg(short = 'x', long)]
+    enable_xochip: bool,
+
+    /// Interpreter quirks to emulate: vip, schip or xochip
+    /// [default: matches the enabled extension, vip otherwise]
+    #[arg(short = 'q', long)]
+    quirks: Option<Quirks>,
+
+    /// Seed for the CXNN random numbers, making runs reproducible [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    /// Maximum number of 60Hz frames to run
+    #[arg(short = 'f', long, default_value_t = 600)]
+    frames: u64,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+
+    /// Stop before executing the instruction at this address (repeatable)
+    #[arg(long, value_parser = debugger::parse_addr)]
+    until_pc: Vec<u16>,
+
+    /// Stop before executing an opcode matching this pattern, `?` matching any nibble (repeatable)
+    #[arg(long, value_parser = OpcodePattern::parse)]
+    until_opcode: Vec<OpcodePattern>,
+
+    /// Key input script with `<frame> <key> <down|up>` lines
+    #[arg(short = 'i', long)]
+    input: Option<PathBuf>,
+
+    /// Framebuffer output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Write the framebuffer to this file instead of stdout
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+
+    /// Record the input to this movie file, e.g. to turn an input script into a movie
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    #[command(flatten)]
+    trace: TraceArgs,
+
+    /// Count executed instructions and print a hot-spot report to stderr
+    #[arg(long)]
+    profile: bool,
+
+    /// Write a coverage report of the executed instructions and skip outcomes to this file
+    #[arg(long)]
+    coverage: Option<PathBuf>,
+
+    /// Coverage report format
+    #[arg(long, value_enum, default_value_t = CoverageFormat::Listing)]
+    coverage_format: CoverageFormat,
+
+    /// Assembly source the ROM was built from, needed for lcov coverage reports
+    #[arg(long)]
+    source: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct TimingArgs {
+    /// Instructions executed per second, the timers running at 60Hz
+    #[arg(long, default_value_t = DEFAULT_IPS, value_parser = clap::value_parser!(u32).range(1..))]
+    ips: u32,
+
+    /// Give every instruction its COSMAC VIP execution time instead of a fixed rate
+    #[arg(long, conflicts_with = "ips")]
+    vip_timing: bool,
+}
+
+impl TimingArgs {
+    fn timing(&self) -> Timing {
+        if self.vip_timing {
+            Timing::Vip
+        } else {
+            Timing::Fixed {
+                instructions_per_second: self.ips,
+            }
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct TraceArgs {
+    /// Log every executed instruction to this file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Trace file format: text or binary
+    #[arg(long, default_value = "text")]
+    trace_format: TraceFormat,
+
+    /// Only trace instructions in this hex PC range, e.g. `200-2FF` (repeatable)
+    #[arg(long, value_parser = TraceFilter::parse_range)]
+    trace_pc: Vec<RangeInclusive<u16>>,
+
+    /// Only trace opcodes matching this pattern, `?` matching any nibble (repeatable)
+    #[arg(long, value_parser = OpcodePattern::parse)]
+    trace_opcode: Vec<OpcodePattern>,
+}
+
+#[derive(clap::Args, Debug)]
+struct DisasmArgs {
+    /// Path to the CHIP-8 ROM file to disassemble
+    rom_path: PathBuf,
+
+    /// Decode S-CHIP opcodes
+    #[arg(short = 's', long)]
+    enable_schip: bool,
+
+    /// Decode XO-CHIP opcodes (implies S-CHIP)
+    #[arg(short = 'x', long)]
+    enable_xochip: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct AsmArgs {
+    /// Path to the assembly source file
+    source_path: PathBuf,
+
+    /// Output ROM path [default: the source path with a `.ch8` extension]
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+
+    /// Also write the labels to this symbol file, for the debugger's `--symbols`
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct GdbArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    /// Local TCP port to listen on
+    #[arg(short = 'p', long, default_value_t = 1234)]
+    port: u16,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DapArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    /// Assembly source the ROM was built from, enabling source breakpoints
+    #[arg(long)]
+    source: Option<PathBuf>,
+
+    /// Listen on this local TCP port instead of using stdin/stdout
+    #[arg(short = 'p', long)]
+    port: Option<u16>,
+
+    #[command(flatten)]
+    timing: TimingArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DumpTraceArgs {
+    /// Binary trace written with `--trace-format binary`
+    trace_path: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// Movie recorded with `--record`
+    movie_path: PathBuf,
+
+    /// The ROM the movie was recorded with
+    rom_path: PathBuf,
+
+    /// Framebuffer output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Write the framebuffer to this file instead of stdout
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Command::Run(args) => run(args),
+        Command::Disasm(args) => disassemble(args),
+        Command::Asm(args) => assemble(args),
+        Command::Gdb(args) => gdb(args),
+        Command::Dap(args) => dap(args),
+        Command::DumpTrace(args) => dump_trace(args),
+        Command::Replay(args) => replay(args),
+    };
+    if let Err(e) = result {
+        eprintln!("Application Error: {:?}", e);
+        std::process::exit(1);
+    }
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).context(format!("Failed to open ROM file: {}", path.display()))
+}
+
+fn write_frame(chip8: &Chip8VM, format: OutputFormat, output: Option<&Path>) -> Result<()> {
+    let frame = match format {
+        OutputFormat::Text => headless::render_text(chip8),
+        OutputFormat::Pbm => headless::render_pbm(chip8),
+    };
+    match output {
+        Some(path) => fs::write(path, frame)
+            .context(format!("Failed to write framebuffer: {}", path.display()))?,
+        None => print!("{}", frame),
+    }
+    Ok(())
+}
+
+fn build_vm(args: &MachineArgs) -> Result<Chip8VM> {
+    let enabled = ActiveExtensions {
+        schip: args.enable_schip,
+        xochip: args.enable_xochip,
+    };
+
+    let quirks = match args.quirks {
+        Some(quirks) => quirks,
+        None if args.enable_xochip => Quirks::xochip(),
+        None if args.enable_schip => Quirks::schip(),
+        None => Quirks::vip(),
+    };
+
+    let rom = read_rom(&args.rom_path)?;
+
+    let mut chip8 = Chip8VM::new(enabled.create(), quirks);
+    if let Some(seed) = args.seed {
+        chip8.set_seed(seed);
+    }
+    chip8
+        .load(&rom)
+        .context("Failed to load ROM data into VM memory")?;
+    Ok(chip8)
+}
+
+fn run(args: &RunArgs) -> Result<()> {
+    let mut chip8 = build_vm(&args.machine)?;
+
+    let conditions: Vec<StopCondition> = args
+        .until_pc
+        .iter()
+        .map(|&pc| StopCondition::Pc(pc))
+        .chain(args.until_opcode.iter().copied().map(StopCondition::Opcode))
+        .collect();
+
+    let input = match &args.input {
+        Some(path) => {
+            let script = fs::read_to_string(path)
+                .context(format!("Failed to read input script: {}", path.display()))?;
+            movie::parse_input_script(&script)?
+        }
+        None => Vec::new(),
+    };
+
+    if let Some(path) = &args.trace.trace {
+        let filter = TraceFilter {
+            ranges: args.trace.trace_pc.clone(),
+            opcodes: args.trace.trace_opcode.clone(),
+        };
+        let tracer = Tracer::create(
+            path,
+            args.trace.trace_format,
+            filter,
+            chip8.active_extensions(),
+        )?;
+        chip8.set_tracer(Some(tracer));
+    }
+    if args.profile {
+        chip8.set_profiler(Some(Profiler::new()));
+    }
+    let assembly = match (&args.source, args.coverage_format) {
+        (Some(path), _) => Some(asm::assemble_file(path)?),
+        (None, CoverageFormat::Lcov) if args.coverage.is_some() => {
+            bail!("lcov coverage reports need the ROM's assembly --source")
+        }
+        (None, _) => None,
+    };
+    if args.coverage.is_some() {
+        chip8.set_coverage(Some(Coverage::new()));
+    }
+    if args.record.is_some() {
+        chip8.set_input_log(Some(Vec::new()));
+    }
+
+    let outcome = headless::run(
+        &mut chip8,
+        args.frames,
+        args.timing.timing(),
+        &conditions,
+        &input,
+    );
+
+    write_frame(&chip8, args.format, args.output.as_deref())?;
+
+    if let Some(path) = &args.record {
+        let events = chip8.set_input_log(None).unwrap_or_default();
+        let rom = read_rom(&args.machine.rom_path)?;
+        Movie::new(&rom, &chip8, args.timing.timing(), events).save(path)?;
+        eprintln!("Recorded {} frames to {}", chip8.frame(), path.display());
+    }
+    if let Some(e) = chip8.take_trace_error() {
+        return Err(e);
+    }
+    if let Some(tracer) = chip8.set_tracer(None) {
+        let records = tracer.finish()?;
+        eprintln!("Traced {} instructions", records);
+    }
+    if let (Some(path), Some(coverage)) = (&args.coverage, chip8.set_coverage(None)) {
+        let ext = chip8.active_extensions();
+        let report = match (args.coverage_format, &assembly) {
+            (CoverageFormat::Lcov, Some(assembly)) => {
+                headless::render_lcov(&coverage, assembly, &ext)
+            }
+            _ => {
+                let rom = read_rom(&args.machine.rom_path)?;
+                headless::render_coverage_listing(&coverage, &rom, START_ADDR, &ext)
+            }
+        };
+        fs::write(path, report).context(format!("Failed to write coverage: {}", path.display()))?;
+
+        let (seen, outcomes) = coverage.skip_outcomes();
+        eprintln!(
+            "Executed {} addresses, {} of {} skip outcomes",
+            coverage.executed(),
+            seen,
+            outcomes
+        );
+    }
+    if let Some(profiler) = chip8.profiler() {
+        let memory = &chip8.get_state().memory;
+        let report = profiler.report(
+            memory,
+            &chip8.active_extensions(),
+            &SymbolTable::new(),
+            PROFILE_LINES,
+        );
+        eprint!("{}", report);
+    }
+
+    let pc = chip8.get_state().pc;
+    match outcome.reason {
+        StopReason::FrameLimit => {
+            eprintln!("Stopped after {} frames at 0x{:04X}", outcome.frames, pc)
+        }
+        StopReason::Condition(condition) => eprintln!(
+            "Stopped on {} after {} frames at 0x{:04X}",
+            condition, outcome.frames, pc
+        ),
+        StopReason::Error(e) => {
+            return Err(e.context(format!(
+                "VM error after {} frames at 0x{:04X}",
+                outcome.frames, pc
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+fn disassemble(args: &DisasmArgs) -> Result<()> {
+    let rom = read_rom(&args.rom_path)?;
+    let ext = ActiveExtensions {
+        schip: args.enable_schip,
+        xochip: args.enable_xochip,
+    };
+
+    let lines = disasm::disassemble(&rom, START_ADDR, &ext);
+    print!("{}", disasm::format_listing(&lines));
+    Ok(())
+}
+
+fn assemble(args: &AsmArgs) -> Result<()> {
+    let assembly = asm::assemble_file(&args.source_path)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.source_path.with_extension("ch8"));
+
+    fs::write(&output, &assembly.rom)
+        .context(format!("Failed to write ROM: {}", output.display()))?;
+    eprintln!(
+        "Assembled {} bytes to {}",
+        assembly.rom.len(),
+        output.display()
+    );
+
+    if let Some(path) = &args.symbols {
+        let symbols = SymbolTable::from_labels(&assembly.labels);
+        fs::write(path, symbols.to_string())
+            .context(format!("Failed to write symbols: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn gdb(args: &GdbArgs) -> Result<()> {
+    let mut chip8 = build_vm(&args.machine)?;
+    let mut debugger = Debugger::new();
+
+    let listener = TcpListener::bind(("127.0.0.1", args.port))
+        .context(format!("Failed to listen on port {}", args.port))?;
+    eprintln!("Waiting for GDB on {}", listener.local_addr()?);
+    let (stream, peer) = listener
+        .accept()
+        .context("Failed to accept GDB connection")?;
+    eprintln!("GDB connected from {}", peer);
+
+    GdbStub::new(&mut chip8, &mut debugger, args.timing.timing()).serve(stream)?;
+    eprintln!("GDB session ended at 0x{:04X}", chip8.get_state().pc);
+    Ok(())
+}
+
+fn dap(args: &DapArgs) -> Result<()> {
+    let mut chip8 = build_vm(&args.machine)?;
+    let mut debugger = Debugger::new();
+
+    let assembly = match &args.source {
+        Some(path) => {
+            let assembly = asm::assemble_file(path)?;
+            let rom = fs::read(&args.machine.rom_path)?;
+            if assembly.rom != rom {
+                eprintln!(
+                    "Warning: {} does not assemble to {}, source breakpoints may be off",
+                    path.display(),
+                    args.machine.rom_path.display()
+                );
+            }
+            Some(assembly)
+        }
+        None => None,
+    };
+    let mut server = DapServer::new(&mut chip8, &mut debugger, assembly, args.timing.timing());
+
+    match args.port {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port))
+                .context(format!("Failed to listen on port {}", port))?;
+            eprintln!("Waiting for a DAP client on {}", listener.local_addr()?);
+            let (stream, _) = listener
+                .accept()
+                .context("Failed to accept DAP connection")?;
+            stream.set_nodelay(true)?;
+            server.serve(stream.try_clone()?, stream)
+        }
+        None => server.serve(io::stdin(), io::stdout()),
+    }
+}
+
+fn dump_trace(args: &DumpTraceArgs) -> Result<()> {
+    let file = File::open(&args.trace_path).context(format!(
+        "Failed to open trace file: {}",
+        args.trace_path.display()
+    ))?;
+    trace::dump_binary(BufReader::new(file), io::stdout().lock())?;
+    Ok(())
+}
+
+fn replay(args: &ReplayArgs) -> Result<()> {
+    let movie = Movie::load(&args.movie_path)?;
+    let rom = read_rom(&args.rom_path)?;
+    movie.check_rom(&rom)?;
+
+    let mut chip8 = Chip8VM::new(movie.extensions.create(), movie.quirks);
+    chip8.set_seed(movie.seed);
+    chip8
+        .load(&rom)
+        .context("Failed to load ROM data into VM memory")?;
+
+    let outcome = headless::run(&mut chip8, movie.frames, movie.timing, &[], &movie.events);
+    write_frame(&chip8, args.format, args.output.as_deref())?;
+
+    let pc = chip8.get_state().pc;
+    if let StopReason::Error(e) = outcome.reason {
+        return Err(e.context(format!(
+            "VM error after {} frames at 0x{:04X}",
+            outcome.frames, pc
+        )));
+    }
+    eprintln!(
+        "Replayed {} frames and {} input events, ending at 0x{:04X}",
+        outcome.frames,
+        movie.events.len(),
+        pc
+    );
+    Ok(())
+}
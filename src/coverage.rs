@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use crate::instruction::Instruction;
+
+/// Outcomes of a skip instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SkipOutcomes {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Executed addresses and skip outcomes of a run. Installed with `Chip8VM::set_coverage`.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    executed: BTreeMap<u16, u64>,
+    skips: BTreeMap<u16, SkipOutcomes>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `instruction`, executed at `pc` and leaving PC at `next_pc`.
+    pub fn record(&mut self, pc: u16, instruction: Instruction, next_pc: u16) {
+        *self.executed.entry(pc).or_default() += 1;
+
+        if instruction.is_skip() {
+            let outcomes = self.skips.entry(pc).or_default();
+            if next_pc == pc.wrapping_add(2) {
+                outcomes.not_taken += 1;
+            } else {
+                outcomes.taken += 1;
+            }
+        }
+    }
+
+    /// Number of times the instruction at `addr` was executed.
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.executed.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Outcomes of the skip instruction at `addr`, if it was executed.
+    pub fn skip(&self, addr: u16) -> Option<SkipOutcomes> {
+        self.skips.get(&addr).copied()
+    }
+
+    /// Number of distinct executed addresses.
+    pub fn executed(&self) -> usize {
+        self.executed.len()
+    }
+
+    /// Number of skip outcomes seen, out of two for every executed skip instruction.
+    pub fn skip_outcomes(&self) -> (usize, usize) {
+        let seen = self
+            .skips
+            .values()
+            .map(|outcomes| (outcomes.taken > 0) as usize + (outcomes.not_taken > 0) as usize)
+            .sum();
+        (seen, self.skips.len() * 2)
+    }
+}
@@ -0,0 +1,733 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::{
+    asm::{Assembly, SourceLocation},
+    conf::REGISTER_COUNT,
+    debugger::{Condition, Debugger},
+    disasm,
+    timing::{Clock, Timing},
+    vm::Chip8VM,
+};
+
+// Instructions executed between checks for incoming requests (e.g. pause) while running
+const RUN_SLICE: usize = 1000;
+
+// Larger messages are skipped rather than buffered
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+const STACK_REF: u64 = 2;
+
+/// Why execution stopped, reported in the `stopped` event.
+struct Stop {
+    reason: &'static str,
+    text: Option<String>,
+}
+
+/// Debug Adapter Protocol server exposing a VM to editors. Breakpoints, stepping and the
+/// register and memory views are backed by the `Debugger` and the VM. Source breakpoints need
+/// the `Assembly` the ROM was built from, for its source map.
+pub struct DapServer<'a> {
+    chip8: &'a mut Chip8VM,
+    debugger: &'a mut Debugger,
+    assembly: Option<Assembly>,
+    // Ticks the timers when a frame's worth of instructions has run
+    clock: Clock,
+    seq: u64,
+    running: bool,
+    // The instruction we resume from is never a breakpoint
+    resuming: bool,
+    stop_on_entry: bool,
+    // Breakpoint addresses installed per source file, and from the disassembly view
+    source_breakpoints: HashMap<PathBuf, Vec<u16>>,
+    instruction_breakpoints: Vec<u16>,
+    // Events to send once the response to the current request is out
+    events: Vec<(&'static str, Value)>,
+}
+
+impl<'a> DapServer<'a> {
+    /// The VM timers are ticked whenever the instructions executed add up to a frame of
+    /// `timing`, as the session does not run in real time.
+    pub fn new(
+        chip8: &'a mut Chip8VM,
+        debugger: &'a mut Debugger,
+        assembly: Option<Assembly>,
+        timing: Timing,
+    ) -> Self {
+        let mut clock = Clock::new(timing);
+        clock.start_frame();
+        DapServer {
+            chip8,
+            debugger,
+            assembly,
+            clock,
+            seq: 0,
+            running: false,
+            resuming: false,
+            stop_on_entry: false,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Serves one client, e.g. over stdin/stdout or a TCP stream, until it disconnects.
+    /// Requests are read on a separate thread so that a running program can be paused.
+    pub fn serve<R, W>(&mut self, input: R, mut output: W) -> Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(input);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        while let Some(message) = self.next_message(&rx) {
+            if let Some(message) = message {
+                if !self.handle_request(&message, &mut output)? {
+                    break;
+                }
+            } else if let Some(stop) = self.run_slice() {
+                self.running = false;
+                self.send_stopped(&mut output, stop)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the next request while stopped, only polls while running. Returns `None`
+    /// once the client is gone.
+    fn next_message(&self, rx: &Receiver<Value>) -> Option<Option<Value>> {
+        if self.running {
+            match rx.try_recv() {
+                Ok(message) => Some(Some(message)),
+                Err(TryRecvError::Empty) => Some(None),
+                Err(TryRecvError::Disconnected) => None,
+            }
+        } else {
+            rx.recv().ok().map(Some)
+        }
+    }
+
+    /// Handles one request and sends its response, followed by any events it raised. Returns
+    /// false once the session is over.
+    fn handle_request<W: Write>(&mut self, message: &Value, output: &mut W) -> Result<bool> {
+        if message["type"] != "request" {
+            return Ok(true);
+        }
+        let command = message["command"].as_str().unwrap_or_default();
+        let args = &message["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(self.initialize()),
+            "launch" | "attach" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Ok(Value::Null)
+            }
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stop(Stop {
+                        reason: "entry",
+                        text: None,
+                    });
+                } else {
+                    self.resume();
+                }
+                Ok(Value::Null)
+            }
+            "setBreakpoints" => self.set_source_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REF },
+                    { "name": "Stack", "variablesReference": STACK_REF },
+                ]
+            })),
+            "variables" => Ok(self.variables(args)),
+            "setVariable" => self.set_variable(args),
+            "continue" => {
+                self.resume();
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                if self.debugger.step_over(self.chip8.get_state()) {
+                    self.resume();
+                } else {
+                    self.step();
+                }
+                Ok(Value::Null)
+            }
+            "stepIn" => {
+                self.step();
+                Ok(Value::Null)
+            }
+            "stepOut" => self.debugger.step_out(self.chip8.get_state()).map(|_| {
+                self.resume();
+                Value::Null
+            }),
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    self.debugger.clear_target();
+                    self.stop(Stop {
+                        reason: "pause",
+                        text: None,
+                    });
+                }
+                Ok(Value::Null)
+            }
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" | "terminate" => {
+                self.send_response(output, message, Ok(Value::Null))?;
+                return Ok(false);
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+
+        self.send_response(output, message, result)?;
+        for (event, body) in std::mem::take(&mut self.events) {
+            self.send_event(output, event, body)?;
+        }
+        Ok(true)
+    }
+
+    fn initialize(&mut self) -> Value {
+        self.events.push(("initialized", Value::Null));
+        json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsConditionalBreakpoints": true,
+            "supportsInstructionBreakpoints": true,
+            "supportsSetVariable": true,
+            "supportsReadMemoryRequest": true,
+            "supportsWriteMemoryRequest": true,
+            "supportsDisassembleRequest": true,
+            "supportsSteppingGranularity": false,
+        })
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.resuming = true;
+    }
+
+    fn stop(&mut self, stop: Stop) {
+        self.events.push(("stopped", stopped_body(stop)));
+    }
+
+    fn step(&mut self) {
+        let stop = match self.clock.step(self.chip8, self.debugger) {
+            Ok(()) => Stop {
+                reason: "step",
+                text: None,
+            },
+            Err(e) => exception(e),
+        };
+        self.stop(stop);
+    }
+
+    /// Runs a slice of instructions, returning why execution stopped, if it did.
+    fn run_slice(&mut self) -> Option<Stop> {
+        for _ in 0..RUN_SLICE {
+            let cpu = self.chip8.get_state();
+            if !self.resuming {
+                if self.debugger.should_break(cpu) {
+                    self.debugger.clear_target();
+                    return Some(Stop {
+                        reason: "breakpoint",
+                        text: None,
+                    });
+                }
+                if self.debugger.target_reached(cpu) {
+                    return Some(Stop {
+                        reason: "step",
+                        text: None,
+                    });
+                }
+            }
+            self.resuming = false;
+
+            if let Err(e) = self.clock.step(self.chip8, self.debugger) {
+                self.debugger.clear_target();
+                return Some(exception(e));
+            }
+        }
+        None
+    }
+
+    fn source_map(&self) -> &[SourceLocation] {
+        self.assembly
+            .as_ref()
+            .map_or(&[], |assembly| &assembly.source_map)
+    }
+
+    /// `setBreakpoints` replaces all breakpoints of a source file. Each line maps to the first
+    /// instruction assembled from it or a following line of the same file.
+    fn set_source_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(
+            args["source"]["path"]
+                .as_str()
+                .ok_or("Missing source path")?,
+        );
+        for addr in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            self.debugger.clear_breakpoint(addr);
+        }
+
+        let mut installed = Vec::new();
+        let mut replies = Vec::new();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = bp["line"].as_u64().unwrap_or(0) as usize;
+            let location = self
+                .source_map()
+                .iter()
+                .filter(|loc| same_file(&loc.file, &path) && loc.line >= line)
+                .min_by_key(|loc| (loc.line, loc.addr))
+                .map(|loc| (loc.addr, loc.line));
+
+            let reply = match location {
+                Some((addr, line)) => match self.install_breakpoint(addr, &bp["condition"]) {
+                    Ok(()) => {
+                        installed.push(addr);
+                        json!({
+                            "verified": true,
+                            "line": line,
+                            "instructionReference": format!("0x{:04X}", addr),
+                        })
+                    }
+                    Err(e) => json!({ "verified": false, "line": line, "message": e }),
+                },
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at or after this line",
+                }),
+            };
+            replies.push(reply);
+        }
+
+        self.source_breakpoints.insert(path, installed);
+        Ok(json!({ "breakpoints": replies }))
+    }
+
+    /// `setInstructionBreakpoints` replaces all breakpoints set from the disassembly view.
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        for addr in std::mem::take(&mut self.instruction_breakpoints) {
+            self.debugger.clear_breakpoint(addr);
+        }
+
+        let mut replies = Vec::new();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let addr = bp["instructionReference"]
+                .as_str()
+                .ok_or_else(|| "Missing instruction reference".to_string())
+                .and_then(parse_reference)
+                .map(|addr| addr.wrapping_add_signed(bp["offset"].as_i64().unwrap_or(0) as i16));
+
+            let reply = match addr {
+                Ok(addr) => match self.install_breakpoint(addr, &bp["condition"]) {
+                    Ok(()) => {
+                        self.instruction_breakpoints.push(addr);
+                        json!({
+                            "verified": true,
+                            "instructionReference": format!("0x{:04X}", addr),
+                        })
+                    }
+                    Err(e) => json!({ "verified": false, "message": e }),
+                },
+                Err(e) => json!({ "verified": false, "message": e }),
+            };
+            replies.push(reply);
+        }
+        Ok(json!({ "breakpoints": replies }))
+    }
+
+    /// Conditions use the debugger's syntax, e.g. `V3 == 0x10`.
+    fn install_breakpoint(&mut self, addr: u16, condition: &Value) -> Result<(), String> {
+        let condition = match condition.as_str() {
+            Some(condition) if !condition.trim().is_empty() => Some(Condition::parse(condition)?),
+            _ => None,
+        };
+        self.debugger.set_breakpoint(addr, condition);
+        Ok(())
+    }
+
+    fn stack_trace(&self) -> Value {
+        let cpu = self.chip8.get_state();
+        let labels = self
+            .assembly
+            .as_ref()
+            .map_or(&[][..], |assembly| &assembly.labels);
+
+        let frames: Vec<Value> = Debugger::frames(cpu)
+            .iter()
+            .enumerate()
+            .map(|(idx, frame)| {
+                let name = match frame.entry {
+                    Some(entry) => labels
+                        .iter()
+                        .find(|(_, addr)| *addr == entry)
+                        .map_or_else(|| format!("0x{:04X}", entry), |(name, _)| name.clone()),
+                    None => "main".to_string(),
+                };
+                let mut value = json!({
+                    "id": idx,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:04X}", frame.pc),
+                });
+                if let Some(loc) = self.source_map().iter().find(|loc| loc.addr == frame.pc) {
+                    value["source"] = source(&loc.file);
+                    value["line"] = json!(loc.line);
+                    value["column"] = json!(1);
+                }
+                value
+            })
+            .collect();
+
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn variables(&self, args: &Value) -> Value {
+        let cpu = self.chip8.get_state();
+        let mut variables = Vec::new();
+
+        match args["variablesReference"].as_u64() {
+            Some(REGISTERS_REF) => {
+                for (x, value) in cpu.registers.iter().enumerate() {
+                    variables.push(variable(&format!("V{:X}", x), format!("0x{:02X}", value)));
+                }
+                let mut i = variable("I", format!("0x{:04X}", cpu.i_register));
+                i["memoryReference"] = json!(format!("0x{:04X}", cpu.i_register));
+                variables.push(i);
+                variables.push(variable("PC", format!("0x{:04X}", cpu.pc)));
+                variables.push(variable("SP", cpu.sp.to_string()));
+                variables.push(variable("DT", cpu.delay_timer.to_string()));
+                variables.push(variable("ST", cpu.sound_timer.to_string()));
+            }
+            Some(STACK_REF) => {
+                for (idx, ret) in cpu.stack[..cpu.sp as usize].iter().enumerate() {
+                    variables.push(variable(&format!("[{}]", idx), format!("0x{:04X}", ret)));
+                }
+            }
+            _ => {}
+        }
+
+        json!({ "variables": variables })
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        if args["variablesReference"].as_u64() != Some(REGISTERS_REF) {
+            return Err("Only registers can be set".to_string());
+        }
+        let name = args["name"].as_str().unwrap_or_default();
+        let value = parse_number(args["value"].as_str().unwrap_or_default())?;
+
+        let result = match name {
+            "I" => {
+                self.chip8.set_i(value);
+                Ok(())
+            }
+            "PC" => self.chip8.set_pc(value),
+            "SP" => self.chip8.set_sp(value),
+            "DT" => {
+                self.chip8.set_timers(Some(value as u8), None);
+                Ok(())
+            }
+            "ST" => {
+                self.chip8.set_timers(None, Some(value as u8));
+                Ok(())
+            }
+            _ => match name
+                .strip_prefix('V')
+                .and_then(|x| usize::from_str_radix(x, 16).ok())
+            {
+                Some(x) if x < REGISTER_COUNT && name.len() == 2 => {
+                    self.chip8.set_register(x, value as u8)
+                }
+                _ => return Err(format!("Unknown register: {}", name)),
+            },
+        };
+        result.map_err(|e| e.to_string())?;
+        // Register edits are not recorded as undo deltas
+        self.debugger.clear_history();
+
+        let value = match name {
+            "SP" | "DT" | "ST" => value.to_string(),
+            _ if name.starts_with('V') => format!("0x{:02X}", value as u8),
+            _ => format!("0x{:04X}", value),
+        };
+        Ok(json!({ "value": value }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let addr = memory_address(args)?;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let memory = &self.chip8.get_state().memory;
+
+        let end = addr
+            .checked_add(count)
+            .ok_or("Memory range out of bounds")?
+            .min(memory.len());
+        let start = addr.min(memory.len());
+        Ok(json!({
+            "address": format!("0x{:04X}", addr),
+            "data": base64_encode(&memory[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = memory_address(args)?;
+        let data = base64_decode(args["data"].as_str().unwrap_or_default())?;
+        let addr = u16::try_from(addr).map_err(|_| "Address out of memory".to_string())?;
+
+        self.chip8
+            .write_memory(addr, &data)
+            .map_err(|e| e.to_string())?;
+        self.debugger.clear_history();
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+
+    /// Decodes forward from the reference. A negative instruction offset assumes 2-byte
+    /// instructions, as the stream cannot be decoded backwards.
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let base = memory_address(args)? as i64;
+        let offset = args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_u64().unwrap_or(0) as usize;
+        let memory = &self.chip8.get_state().memory;
+        let ext = self.chip8.active_extensions();
+
+        let mut addr = base + offset.min(0) * 2;
+        let mut instructions = Vec::new();
+        let mut skip = offset.max(0);
+        while instructions.len() < count {
+            if addr < 0 || addr + 1 >= memory.len() as i64 {
+                instructions.push(json!({
+                    "address": format!("0x{:04X}", addr.max(0)),
+                    "instruction": "",
+                    "presentationHint": "invalid",
+                }));
+                addr += 2;
+                continue;
+            }
+
+            let (mnemonic, size) = disasm::disassemble_at(memory, addr as u16, &ext);
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                let bytes: Vec<String> = memory[addr as usize..addr as usize + size]
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                let mut instruction = json!({
+                    "address": format!("0x{:04X}", addr),
+                    "instructionBytes": bytes.join(" "),
+                    "instruction": mnemonic,
+                });
+                if let Some(loc) = self.source_map().iter().find(|loc| loc.addr as i64 == addr) {
+                    instruction["location"] = source(&loc.file);
+                    instruction["line"] = json!(loc.line);
+                }
+                instructions.push(instruction);
+            }
+            addr += size as i64;
+        }
+
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    fn send_stopped<W: Write>(&mut self, output: &mut W, stop: Stop) -> Result<()> {
+        self.send_event(output, "stopped", stopped_body(stop))
+    }
+
+    fn send_response<W: Write>(
+        &mut self,
+        output: &mut W,
+        request: &Value,
+        result: Result<Value, String>,
+    ) -> Result<()> {
+        self.seq += 1;
+        let mut response = json!({
+            "seq": self.seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        write_message(output, &response)
+    }
+
+    fn send_event<W: Write>(&mut self, output: &mut W, event: &str, body: Value) -> Result<()> {
+        self.seq += 1;
+        let mut message = json!({ "seq": self.seq, "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        write_message(output, &message)
+    }
+}
+
+fn stopped_body(stop: Stop) -> Value {
+    let mut body = json!({
+        "reason": stop.reason,
+        "threadId": THREAD_ID,
+        "allThreadsStopped": true,
+    });
+    if let Some(text) = stop.text {
+        body["text"] = json!(text);
+    }
+    body
+}
+
+fn exception(e: anyhow::Error) -> Stop {
+    Stop {
+        reason: "exception",
+        text: Some(format!("{:#}", e)),
+    }
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn source(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|name| name.to_string_lossy()),
+        "path": path,
+    })
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (a.canonicalize(), b.canonicalize()),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+/// Memory and instruction references are `0x`-prefixed hex addresses.
+fn parse_reference(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("Invalid memory reference: {}", s))
+}
+
+fn memory_address(args: &Value) -> Result<usize, String> {
+    let base = parse_reference(args["memoryReference"].as_str().unwrap_or_default())?;
+    let addr = (base as i64).checked_add(args["offset"].as_i64().unwrap_or(0));
+    addr.and_then(|addr| usize::try_from(addr).ok())
+        .ok_or_else(|| "Address out of memory".to_string())
+}
+
+/// `0x` hex or decimal, as in the debugger's `set` command.
+fn parse_number(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("Invalid value: {}", s))
+}
+
+/// Reads one `Content-Length` framed message, `None` at the end of the input. Bodies which
+/// are not JSON or larger than `MAX_MESSAGE_SIZE` are skipped.
+fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Value>> {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let Some(length) = length else {
+            continue;
+        };
+        if length > MAX_MESSAGE_SIZE {
+            io::copy(&mut reader.by_ref().take(length as u64), &mut io::sink())?;
+            continue;
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Ok(Some(message));
+        }
+    }
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .context("Failed to write to the DAP client")
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |acc, (idx, byte)| {
+            acc | (*byte as u32) << (16 - idx * 8)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - idx * 6)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in s.bytes().filter(|c| *c != b'=') {
+        let value = BASE64
+            .iter()
+            .position(|b| *b == c)
+            .ok_or_else(|| format!("Invalid base64 data: {}", s))?;
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Ok(out)
+}
@@ -1,43 +1,463 @@
-use crate::vm::CpuState;
-use std::collections::HashSet;
+use crate::disasm;
+use crate::extensions::ActiveExtensions;
+use crate::history::ExecutionHistory;
+use crate::instruction::{Instruction, OpcodePattern};
+use crate::profile::Profiler;
+use crate::symbols::SymbolTable;
+use crate::trace::{TraceFilter, TraceFormat};
+use crate::vm::{AccessKind, Chip8VM, CpuState, MemoryAccess};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
 
 pub enum DebugAction {
     Quit,
     Step,
     Continue,
+    ReverseStep,
+    ReverseContinue,
     ShowRegisters,
     ShowMemory(u16, usize),
     ShowBreakpoints,
+    Backtrace,
+    ShowFrame(usize),
+    SetRegister(Register, u16),
+    Poke(u16, Vec<u8>),
+    Fill(u16, usize, u8),
+    LoadSymbols(PathBuf),
+    TraceOn(PathBuf, TraceFormat, TraceFilter),
+    TraceOff,
+    ShowProfile(usize),
+    StartProfile,
+    StopProfile,
     Help,
 }
 
+/// Register that can be edited with `set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+    Pc,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+        }
+    }
+}
+
+/// Value a breakpoint condition can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    V(usize),
+    I,
+    Dt,
+    St,
+    Sp,
+    Pc,
+    Value(u16),
+}
+
+impl Operand {
+    fn parse(s: &str) -> Result<Self, String> {
+        let upper = s.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => return Ok(Operand::I),
+            "DT" => return Ok(Operand::Dt),
+            "ST" => return Ok(Operand::St),
+            "SP" => return Ok(Operand::Sp),
+            "PC" => return Ok(Operand::Pc),
+            _ => {}
+        }
+        if let Some(reg) = parse_register(&upper) {
+            return Ok(Operand::V(reg));
+        }
+        parse_value(s).map(Operand::Value)
+    }
+
+    fn eval(&self, cpu: &CpuState) -> u16 {
+        match *self {
+            Operand::V(x) => cpu.registers[x] as u16,
+            Operand::I => cpu.i_register,
+            Operand::Dt => cpu.delay_timer as u16,
+            Operand::St => cpu.sound_timer as u16,
+            Operand::Sp => cpu.sp,
+            Operand::Pc => cpu.pc,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Operand::V(x) => write!(f, "V{:X}", x),
+            Operand::I => write!(f, "I"),
+            Operand::Dt => write!(f, "DT"),
+            Operand::St => write!(f, "ST"),
+            Operand::Sp => write!(f, "SP"),
+            Operand::Pc => write!(f, "PC"),
+            Operand::Value(value) => write!(f, "0x{:X}", value),
+        }
+    }
+}
+
+// Longer operators first so `<=` is not taken for `<`
+const COMPARISONS: [&str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+// Addresses and subroutines listed by a bare `profile`
+pub const PROFILE_LINES: usize = 20;
+
+/// Breakpoint condition, e.g. `V3 == 0x10`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    lhs: Operand,
+    op: &'static str,
+    rhs: Operand,
+}
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (idx, op) = COMPARISONS
+            .iter()
+            .find_map(|op| s.find(op).map(|idx| (idx, *op)))
+            .ok_or_else(|| format!("Missing comparison operator in condition: {}", s))?;
+
+        Ok(Condition {
+            lhs: Operand::parse(s[..idx].trim())?,
+            op,
+            rhs: Operand::parse(s[idx + op.len()..].trim())?,
+        })
+    }
+
+    pub fn eval(&self, cpu: &CpuState) -> bool {
+        let (lhs, rhs) = (self.lhs.eval(cpu), self.rhs.eval(cpu));
+        match self.op {
+            "==" => lhs == rhs,
+            "!=" => lhs != rhs,
+            "<=" => lhs <= rhs,
+            ">=" => lhs >= rhs,
+            "<" => lhs < rhs,
+            _ => lhs > rhs,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchedRegister {
+    V(usize),
+    I,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Watchpoint {
+    Memory {
+        addr: usize,
+        len: usize,
+        kind: WatchKind,
+    },
+    /// Fires when the register differs from `last`, the value seen by the previous check.
+    Register { reg: WatchedRegister, last: u16 },
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Watchpoint::Memory { addr, len, kind } => {
+                let kind = match kind {
+                    WatchKind::Read => "read",
+                    WatchKind::Write => "write",
+                    WatchKind::Access => "access",
+                };
+                write!(f, "{} 0x{:04X}", kind, addr)?;
+                if len > 1 {
+                    write!(f, "..0x{:04X}", addr + len - 1)?;
+                }
+                Ok(())
+            }
+            Watchpoint::Register { reg, .. } => match reg {
+                WatchedRegister::V(x) => write!(f, "change V{:X}", x),
+                WatchedRegister::I => write!(f, "change I"),
+            },
+        }
+    }
+}
+
+/// One level of the call stack.
+pub struct Frame {
+    /// Current PC for the innermost frame, the CALL site for the callers
+    pub pc: u16,
+    /// Address of the subroutine the frame executes, unknown for the outermost frame
+    pub entry: Option<u16>,
+    /// Return address pushed by the CALL, for the callers
+    pub ret: Option<u16>,
+}
+
+/// Temporary stop set by `next`, `finish` and `until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunTarget {
+    Pc(u16),
+    /// Stop once SP is back down to this depth, i.e. the frame above it returned
+    StackDepth(u16),
+}
+
+#[derive(Default)]
 pub struct Debugger {
-    breakpoints: HashSet<u16>,
+    // Unconditional breakpoints have no condition
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    watchpoints: Vec<Watchpoint>,
+    target: Option<RunTarget>,
+    history: ExecutionHistory,
+    recording: bool,
+    symbols: SymbolTable,
 }
 
 impl Debugger {
     pub fn new() -> Self {
-        Self {
-            breakpoints: HashSet::new(),
+        Debugger {
+            recording: true,
+            ..Default::default()
         }
     }
 
-    pub fn should_break(&self, pc: u16) -> bool {
-        self.breakpoints.contains(&pc)
+    /// Starts or stops recording the history `rstep` and `rcontinue` go back through. When
+    /// not recording, `execute` only ticks the VM, without diffing memory and the screen.
+    pub fn set_recording(&mut self, recording: bool) {
+        if !recording {
+            self.history.clear();
+        }
+        self.recording = recording;
     }
 
-    pub fn set_breakpoint(&mut self, addr: u16) {
-        self.breakpoints.insert(addr);
+    /// Replaces the labels shown in place of addresses and accepted by commands.
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Formats an address with the closest label, e.g. `0x02A8 <draw+0x4>`.
+    pub fn format_addr(&self, addr: u16) -> String {
+        match self.symbols.describe(addr) {
+            Some(label) => format!("0x{:04X} <{}>", addr, label),
+            None => format!("0x{:04X}", addr),
+        }
+    }
+
+    /// Disassembles the instruction at `addr`, naming the target of jumps, calls and `LD I`
+    /// when it is labelled.
+    pub fn format_instruction(&self, memory: &[u8], addr: u16, ext: &ActiveExtensions) -> String {
+        let (mnemonic, _) = disasm::disassemble_at(memory, addr, ext);
+        let opcode = match memory.get(addr as usize..addr as usize + 2) {
+            Some(&[hi, lo]) => u16::from_be_bytes([hi, lo]),
+            _ => return mnemonic,
+        };
+
+        let target = match Instruction::decode(opcode, ext) {
+            Instruction::Jump(nnn)
+            | Instruction::Call(nnn)
+            | Instruction::LoadI(nnn)
+            | Instruction::JumpOffset { nnn, .. } => self.symbols.name(nnn),
+            _ => None,
+        };
+        match target {
+            Some(name) => format!("{} <{}>", mnemonic, name),
+            None => mnemonic,
+        }
+    }
+
+    /// Whether a breakpoint at PC is hit, its condition (if any) holding.
+    pub fn should_break(&self, cpu: &CpuState) -> bool {
+        match self.breakpoints.get(&cpu.pc) {
+            Some(Some(condition)) => condition.eval(cpu),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    pub fn set_breakpoint(&mut self, addr: u16, condition: Option<Condition>) {
+        self.breakpoints.insert(addr, condition);
     }
 
     pub fn clear_breakpoint(&mut self, addr: u16) {
         self.breakpoints.remove(&addr);
     }
 
+    /// Executes one instruction, recording it for `rstep` and `rcontinue`.
+    pub fn execute(&mut self, chip8: &mut Chip8VM) -> anyhow::Result<()> {
+        if self.recording {
+            self.history.execute(chip8)
+        } else {
+            chip8.tick()
+        }
+    }
+
+    /// Forgets the recorded history, needed whenever the machine is changed by anything but
+    /// `execute`.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Undoes the last instruction. Returns the watchpoint it triggered, if any.
+    pub fn reverse_step(&mut self, chip8: &mut Chip8VM) -> Result<Option<String>, String> {
+        let entry = self
+            .history
+            .step_back(chip8)
+            .ok_or("No more reverse-execution history")?;
+        Ok(self.check_watchpoints(chip8.get_state(), &entry.accesses))
+    }
+
+    /// Runs backwards until a breakpoint or watchpoint is hit, or the history runs out.
+    /// Returns why execution stopped.
+    pub fn reverse_continue(&mut self, chip8: &mut Chip8VM) -> String {
+        let mut steps = 0;
+        while let Some(entry) = self.history.step_back(chip8) {
+            steps += 1;
+            if let Some(hit) = self.check_watchpoints(chip8.get_state(), &entry.accesses) {
+                return hit;
+            }
+            if self.should_break(chip8.get_state()) {
+                return format!(
+                    "Breakpoint hit at {}",
+                    self.format_addr(chip8.get_state().pc)
+                );
+            }
+        }
+
+        if steps == 0 {
+            "No more reverse-execution history".to_string()
+        } else {
+            format!("Reached the start of the history after {} steps", steps)
+        }
+    }
+
+    /// Whether the target of a pending `next`, `finish` or `until` is reached, clearing it
+    /// if so.
+    pub fn target_reached(&mut self, cpu: &CpuState) -> bool {
+        let reached = match self.target {
+            Some(RunTarget::Pc(addr)) => cpu.pc == addr,
+            Some(RunTarget::StackDepth(depth)) => cpu.sp <= depth,
+            None => false,
+        };
+        if reached {
+            self.target = None;
+        }
+        reached
+    }
+
+    /// Sets up stepping over the instruction at PC. Returns true if it is a CALL, which needs
+    /// a continue until the subroutine returns, false if a single step does.
+    pub fn step_over(&mut self, cpu: &CpuState) -> bool {
+        let pc = cpu.pc as usize;
+        let opcode = match cpu.memory.get(pc..pc + 2) {
+            Some(&[hi, lo]) => u16::from_be_bytes([hi, lo]),
+            _ => return false,
+        };
+
+        // CALL is a base instruction, extensions don't change its decoding
+        match Instruction::decode(opcode, &ActiveExtensions::default()) {
+            Instruction::Call(_) => {
+                self.target = Some(RunTarget::StackDepth(cpu.sp));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets up a continue until the current subroutine returns, returning its return address.
+    pub fn step_out(&mut self, cpu: &CpuState) -> Result<u16, String> {
+        if cpu.sp == 0 {
+            return Err("\"finish\" not meaningful in the outermost frame".to_string());
+        }
+
+        self.target = Some(RunTarget::StackDepth(cpu.sp - 1));
+        Ok(cpu.stack[cpu.sp as usize - 1])
+    }
+
+    /// Drops the pending `next`, `finish` or `until`, e.g. when a breakpoint stops execution
+    /// first.
+    pub fn clear_target(&mut self) {
+        self.target = None;
+    }
+
+    pub fn has_watchpoints(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    /// Checks the watchpoints against the state after an instruction and the memory accesses
+    /// it made, returning a description of the first one hit.
+    pub fn check_watchpoints(
+        &mut self,
+        cpu: &CpuState,
+        accesses: &[MemoryAccess],
+    ) -> Option<String> {
+        let mut hit = None;
+
+        for watchpoint in self.watchpoints.iter_mut() {
+            match watchpoint {
+                Watchpoint::Memory { addr, len, kind } => {
+                    let access = accesses.iter().find(|access| {
+                        access.overlaps(*addr, *len)
+                            && match kind {
+                                WatchKind::Read => access.kind == AccessKind::Read,
+                                WatchKind::Write => access.kind == AccessKind::Write,
+                                WatchKind::Access => true,
+                            }
+                    });
+                    if let (Some(access), None) = (access, &hit) {
+                        let verb = match access.kind {
+                            AccessKind::Read => "read",
+                            AccessKind::Write => "written",
+                        };
+                        hit = Some(format!(
+                            "Watchpoint: 0x{:04X}..0x{:04X} {}",
+                            access.addr,
+                            access.addr + access.len.max(1) - 1,
+                            verb
+                        ));
+                    }
+                }
+                Watchpoint::Register { reg, last } => {
+                    let (name, value) = match *reg {
+                        WatchedRegister::V(x) => (format!("V{:X}", x), cpu.registers[x] as u16),
+                        WatchedRegister::I => ("I".to_string(), cpu.i_register),
+                    };
+                    if value != *last {
+                        if hit.is_none() {
+                            hit = Some(format!(
+                                "Watchpoint: {} changed 0x{:X} -> 0x{:X}",
+                                name, last, value
+                            ));
+                        }
+                        *last = value;
+                    }
+                }
+            }
+        }
+
+        hit
+    }
+
     pub fn parse_and_execute(
         &mut self,
         input: &str,
-        _cpu: &CpuState,
+        cpu: &CpuState,
     ) -> Result<DebugAction, String> {
         let input = input.trim();
         if input.is_empty() {
@@ -46,26 +466,162 @@ impl Debugger {
 
         let parts: Vec<&str> = input.split_whitespace().collect();
 
-        match parts.get(0).map(|s| *s) {
+        match parts.first().copied() {
             Some("q") | Some("quit") => Ok(DebugAction::Quit),
             Some("s") | Some("step") => Ok(DebugAction::Step),
             Some("c") | Some("continue") => Ok(DebugAction::Continue),
+            Some("rs") | Some("rstep") => Ok(DebugAction::ReverseStep),
+            Some("rc") | Some("rcontinue") => Ok(DebugAction::ReverseContinue),
+            Some("n") | Some("next") => self.parse_next(cpu),
+            Some("f") | Some("finish") => self.parse_finish(cpu),
+            Some("u") | Some("until") => self.parse_until(&parts),
             Some("i") | Some("info") => self.parse_info(&parts),
+            Some("bt") | Some("backtrace") => Ok(DebugAction::Backtrace),
+            Some("frame") => match parts.get(1) {
+                Some(n) => n
+                    .parse()
+                    .map(DebugAction::ShowFrame)
+                    .map_err(|_| format!("Invalid frame number: {}", n)),
+                None => Ok(DebugAction::ShowFrame(0)),
+            },
             Some("b") | Some("break") => self.parse_breakpoint(&parts),
             Some("clear") => self.parse_clear(&parts),
+            Some("set") => Self::parse_set(&parts),
+            Some("poke") => self.parse_poke(&parts),
+            Some("fill") => self.parse_fill(&parts),
+            Some("w") | Some("watch") => self.parse_watch(&parts, WatchKind::Write, cpu),
+            Some("rwatch") => self.parse_watch(&parts, WatchKind::Read, cpu),
+            Some("awatch") => self.parse_watch(&parts, WatchKind::Access, cpu),
+            Some("unwatch") => self.parse_unwatch(&parts),
+            Some("symbols") => match parts[..] {
+                [_, path] => Ok(DebugAction::LoadSymbols(PathBuf::from(path))),
+                _ => Err("Usage: symbols <file>".to_string()),
+            },
+            Some("trace") => Self::parse_trace(&parts),
+            Some("profile") => match parts[..] {
+                [_] => Ok(DebugAction::ShowProfile(PROFILE_LINES)),
+                [_, "start"] => Ok(DebugAction::StartProfile),
+                [_, "stop"] => Ok(DebugAction::StopProfile),
+                [_, n] => parse_value(n).map(|n| DebugAction::ShowProfile(n as usize)),
+                _ => Err("Usage: profile [n | start | stop]".to_string()),
+            },
             Some("help") | Some("h") => self.show_help(),
             _ => Err(format!("Unknown command: {}", parts[0])),
         }
     }
 
     fn parse_breakpoint(&mut self, parts: &[&str]) -> Result<DebugAction, String> {
+        let condition = match parts {
+            [_, _] => None,
+            [_, _, "if", condition @ ..] if !condition.is_empty() => {
+                Some(Condition::parse(&condition.join(" "))?)
+            }
+            _ => return Err("Usage: break <addr> [if <condition>]".to_string()),
+        };
+
+        let addr = self.parse_location(parts[1])?;
+        self.set_breakpoint(addr, condition);
+        Ok(DebugAction::ShowBreakpoints)
+    }
+
+    fn parse_next(&mut self, cpu: &CpuState) -> Result<DebugAction, String> {
+        if self.step_over(cpu) {
+            Ok(DebugAction::Continue)
+        } else {
+            Ok(DebugAction::Step)
+        }
+    }
+
+    fn parse_finish(&mut self, cpu: &CpuState) -> Result<DebugAction, String> {
+        let ret = self.step_out(cpu)?;
+        println!("Run till exit to {}", self.format_addr(ret));
+        Ok(DebugAction::Continue)
+    }
+
+    fn parse_until(&mut self, parts: &[&str]) -> Result<DebugAction, String> {
         if parts.len() != 2 {
-            return Err("Usage: break <addr>".to_string());
+            return Err("Usage: until <addr>".to_string());
         }
 
-        let addr = parse_addr(parts[1])?;
-        self.set_breakpoint(addr);
-        Ok(DebugAction::ShowBreakpoints)
+        self.target = Some(RunTarget::Pc(self.parse_location(parts[1])?));
+        Ok(DebugAction::Continue)
+    }
+
+    fn parse_set(parts: &[&str]) -> Result<DebugAction, String> {
+        let [_, register, value] = parts else {
+            return Err("Usage: set <VX|I|pc> <value>".to_string());
+        };
+
+        let register = match parse_register(register) {
+            Some(x) => Register::V(x),
+            None if register.eq_ignore_ascii_case("I") => Register::I,
+            None if register.eq_ignore_ascii_case("pc") => Register::Pc,
+            None => return Err(format!("Unknown register: {}", register)),
+        };
+        let value = parse_value(value)?;
+        if matches!(register, Register::V(_)) && value > 0xFF {
+            return Err(format!("Value does not fit in {}: {}", register, value));
+        }
+        Ok(DebugAction::SetRegister(register, value))
+    }
+
+    fn parse_poke(&self, parts: &[&str]) -> Result<DebugAction, String> {
+        if parts.len() < 3 {
+            return Err("Usage: poke <addr> <bytes...>".to_string());
+        }
+
+        let addr = self.parse_location(parts[1])?;
+        let bytes = parts[2..]
+            .iter()
+            .map(|byte| parse_byte(byte))
+            .collect::<Result<Vec<u8>, String>>()?;
+        Ok(DebugAction::Poke(addr, bytes))
+    }
+
+    fn parse_fill(&self, parts: &[&str]) -> Result<DebugAction, String> {
+        let [_, addr, len, byte] = parts else {
+            return Err("Usage: fill <addr> <len> <byte>".to_string());
+        };
+
+        Ok(DebugAction::Fill(
+            self.parse_location(addr)?,
+            parse_value(len)? as usize,
+            parse_byte(byte)?,
+        ))
+    }
+
+    fn parse_trace(parts: &[&str]) -> Result<DebugAction, String> {
+        const USAGE: &str =
+            "Usage: trace on <file> [binary] [pc <range>]... [op <pattern>]... | trace off";
+
+        let path = match parts {
+            [_, "off"] => return Ok(DebugAction::TraceOff),
+            [_, "on", path, ..] => PathBuf::from(path),
+            _ => return Err(USAGE.to_string()),
+        };
+
+        let mut format = TraceFormat::Text;
+        let mut filter = TraceFilter::default();
+        let mut args = parts[3..].iter();
+        while let Some(&arg) = args.next() {
+            match arg {
+                "text" | "binary" => format = arg.parse().map_err(|e| format!("{}", e))?,
+                "pc" => {
+                    let range = args.next().ok_or(USAGE)?;
+                    filter
+                        .ranges
+                        .push(TraceFilter::parse_range(range).map_err(|e| format!("{}", e))?);
+                }
+                "op" => {
+                    let pattern = args.next().ok_or(USAGE)?;
+                    filter
+                        .opcodes
+                        .push(OpcodePattern::parse(pattern).map_err(|e| format!("{}", e))?);
+                }
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(DebugAction::TraceOn(path, format, filter))
     }
 
     fn parse_clear(&mut self, parts: &[&str]) -> Result<DebugAction, String> {
@@ -73,11 +629,80 @@ impl Debugger {
             return Err("Usage: clear <addr>".to_string());
         }
 
-        let addr = parse_addr(parts[1])?;
+        let addr = self.parse_location(parts[1])?;
         self.clear_breakpoint(addr);
         Ok(DebugAction::ShowBreakpoints)
     }
 
+    fn parse_watch(
+        &mut self,
+        parts: &[&str],
+        kind: WatchKind,
+        cpu: &CpuState,
+    ) -> Result<DebugAction, String> {
+        let register = match parts {
+            [_, target] if kind == WatchKind::Write => match parse_register(target) {
+                Some(x) => Some((WatchedRegister::V(x), cpu.registers[x] as u16)),
+                None if target.eq_ignore_ascii_case("I") => {
+                    Some((WatchedRegister::I, cpu.i_register))
+                }
+                None => None,
+            },
+            _ => None,
+        };
+
+        let watchpoint = match (register, parts) {
+            (Some((reg, last)), _) => Watchpoint::Register { reg, last },
+            (None, [_, addr]) | (None, [_, addr, _]) => {
+                let len = match parts.get(2) {
+                    Some(len) => parse_value(len)? as usize,
+                    None => 1,
+                };
+                if len == 0 {
+                    return Err("Invalid length".to_string());
+                }
+                Watchpoint::Memory {
+                    addr: self.parse_location(addr)? as usize,
+                    len,
+                    kind,
+                }
+            }
+            _ => {
+                return Err(
+                    "Usage: watch <addr> [len] | watch <VX|I> | rwatch/awatch <addr> [len]"
+                        .to_string(),
+                )
+            }
+        };
+
+        self.watchpoints.push(watchpoint);
+        Ok(DebugAction::ShowBreakpoints)
+    }
+
+    fn parse_unwatch(&mut self, parts: &[&str]) -> Result<DebugAction, String> {
+        let [_, index] = parts else {
+            return Err("Usage: unwatch <n>".to_string());
+        };
+        let index: usize = index
+            .parse()
+            .map_err(|_| format!("Invalid watchpoint number: {}", index))?;
+        if index >= self.watchpoints.len() {
+            return Err(format!("No watchpoint number {}", index));
+        }
+
+        self.watchpoints.remove(index);
+        Ok(DebugAction::ShowBreakpoints)
+    }
+
+    /// Parses a label name or a hex address. Labels take precedence, so a label such as
+    /// `add` is not read as 0xADD.
+    fn parse_location(&self, s: &str) -> Result<u16, String> {
+        match self.symbols.resolve(s) {
+            Some(addr) => Ok(addr),
+            None => parse_addr(s).map_err(|_| format!("Invalid address or unknown label: {}", s)),
+        }
+    }
+
     fn parse_info(&self, parts: &[&str]) -> Result<DebugAction, String> {
         if parts.len() < 2 {
             return Err("Usage: info <registers|memory|breakpoints>".to_string());
@@ -89,7 +714,7 @@ impl Debugger {
                 if parts.len() != 4 {
                     return Err("Usage: info memory <addr> <len>".to_string());
                 }
-                let addr = parse_addr(parts[2])?;
+                let addr = self.parse_location(parts[2])?;
                 let len: usize = parts[3].parse().map_err(|_| "Invalid length".to_string())?;
                 Ok(DebugAction::ShowMemory(addr, len))
             }
@@ -101,20 +726,56 @@ impl Debugger {
     fn show_help(&self) -> Result<DebugAction, String> {
         println!("Commands:");
         println!("  break <addr> | b <addr>      - Set breakpoint at address");
+        println!("  break <addr> if <cond>       - Conditional breakpoint, e.g. if V3 == 0x10");
         println!("  clear <addr>                 - Clear breakpoint at address");
+        println!("  watch <addr> [len]           - Pause when memory is written");
+        println!("  rwatch <addr> [len]          - Pause when memory is read");
+        println!("  awatch <addr> [len]          - Pause when memory is read or written");
+        println!("  watch <VX|I>                 - Pause when a register changes");
+        println!("  unwatch <n>                  - Delete watchpoint number n");
+        println!("  set <VX|I|pc> <value>        - Set a register");
+        println!("  poke <addr> <bytes...>       - Write bytes to memory");
+        println!("  fill <addr> <len> <byte>     - Fill a memory range with a byte");
         println!("  step | s                     - Single step");
+        println!("  next | n                     - Step, running over subroutine calls");
+        println!("  finish | f                   - Run until the current subroutine returns");
+        println!("  until <addr> | u <addr>      - Run until PC reaches the address");
         println!("  continue | c                 - Continue execution");
+        println!("  rstep | rs                   - Step back one instruction");
+        println!("  rcontinue | rc               - Run backwards to the previous breakpoint or");
+        println!("                                 watchpoint hit");
         println!("  info registers | i r         - Show registers");
         println!("  info memory <addr> <len>     - Dump memory");
-        println!("  info breakpoints | i b       - List breakpoints");
+        println!("  info breakpoints | i b       - List breakpoints and watchpoints");
+        println!("  backtrace | bt               - Show the call stack");
+        println!("  frame [n]                    - Show the code around frame n");
+        println!("  symbols <file>               - Load labels from a symbol file");
+        println!("  trace on <file> [binary]     - Log executed instructions, optionally only");
+        println!("    [pc <range>] [op <pattern>]  PCs in a hex range (200-2FF) or matching");
+        println!("                                 opcodes (D???), both repeatable");
+        println!("  trace off                    - Stop tracing");
+        println!("  profile start | stop         - Start counting executed instructions, or stop");
+        println!("  profile [n]                  - Show the n hottest addresses and subroutines");
         println!("  quit | q                     - Quit debugger");
+        println!();
+        println!("Conditions compare V0-VF, I, DT, ST, SP, PC or values (0x.. hex, decimal)");
+        println!("with ==, !=, <, <=, > or >=. Addresses are hex or label names.");
         Ok(DebugAction::Help)
     }
 
     pub fn show_registers(&self, cpu: &CpuState) {
         println!("PC:    0x{:04X}", cpu.pc);
         println!("I:     0x{:04X}", cpu.i_register);
-        println!("SP:    {}", cpu.sp);
+        print!("SP:    {}", cpu.sp);
+        if cpu.sp > 0 {
+            print!("  [");
+            for (idx, addr) in cpu.stack[..cpu.sp as usize].iter().enumerate() {
+                let sep = if idx == 0 { "" } else { " " };
+                print!("{}0x{:04X}", sep, addr);
+            }
+            print!("]");
+        }
+        println!();
         println!("DT:    {}", cpu.delay_timer);
         println!("ST:    {}", cpu.sound_timer);
         println!();
@@ -130,33 +791,188 @@ impl Debugger {
         }
     }
 
+    /// Walks the stack from the innermost frame outwards. Each return address on the stack
+    /// follows a 2-byte CALL, whose target gives the entry point of the frame below it.
+    pub fn frames(cpu: &CpuState) -> Vec<Frame> {
+        let mut frames = vec![Frame {
+            pc: cpu.pc,
+            entry: None,
+            ret: None,
+        }];
+
+        for &ret in cpu.stack[..cpu.sp as usize].iter().rev() {
+            let call_site = ret.wrapping_sub(2);
+            let addr = call_site as usize;
+            if let Some(&[hi, lo]) = cpu.memory.get(addr..addr + 2) {
+                let opcode = u16::from_be_bytes([hi, lo]);
+                if let Instruction::Call(target) =
+                    Instruction::decode(opcode, &ActiveExtensions::default())
+                {
+                    if let Some(callee) = frames.last_mut() {
+                        callee.entry = Some(target);
+                    }
+                }
+            }
+            frames.push(Frame {
+                pc: call_site,
+                entry: None,
+                ret: Some(ret),
+            });
+        }
+
+        frames
+    }
+
+    fn format_frame(
+        &self,
+        idx: usize,
+        frame: &Frame,
+        cpu: &CpuState,
+        ext: &ActiveExtensions,
+    ) -> String {
+        let mnemonic = self.format_instruction(&cpu.memory, frame.pc, ext);
+        let mut line = format!(
+            "#{:<2} {}  {:<16}",
+            idx,
+            self.format_addr(frame.pc),
+            mnemonic
+        );
+        if let Some(entry) = frame.entry {
+            line += &format!(" in {}", self.format_addr(entry));
+        }
+        if let Some(ret) = frame.ret {
+            line += &format!(" returns to {}", self.format_addr(ret));
+        }
+        line.trim_end().to_string()
+    }
+
+    pub fn show_backtrace(&self, cpu: &CpuState, ext: &ActiveExtensions) {
+        for (idx, frame) in Self::frames(cpu).iter().enumerate() {
+            println!("{}", self.format_frame(idx, frame, cpu, ext));
+        }
+    }
+
+    /// Prints frame `n` and the code around its PC (the CALL site for callers).
+    pub fn show_frame(&self, cpu: &CpuState, n: usize, ext: &ActiveExtensions) {
+        const CONTEXT: usize = 3;
+
+        let frames = Self::frames(cpu);
+        let Some(frame) = frames.get(n) else {
+            println!(
+                "Error: No frame {}, the stack has {} frames",
+                n,
+                frames.len()
+            );
+            return;
+        };
+        println!("{}", self.format_frame(n, frame, cpu, ext));
+
+        // In usize, the context reaching past 0xFFFF with XO-CHIP memory
+        let pc = frame.pc as usize;
+        let end = (pc + CONTEXT * 2 + 1).min(cpu.memory.len());
+        let mut addr = pc.saturating_sub(CONTEXT * 2);
+        while addr < end {
+            if let Some(name) = self.symbols.name(addr as u16) {
+                println!("   {}:", name);
+            }
+            let (_, size) = disasm::disassemble_at(&cpu.memory, addr as u16, ext);
+            let mnemonic = self.format_instruction(&cpu.memory, addr as u16, ext);
+            let marker = if addr == pc { "=>" } else { "  " };
+            println!("{} 0x{:04X}: {}", marker, addr, mnemonic);
+            addr += size;
+        }
+    }
+
+    /// Dumps memory 16 bytes per row, starting a new row under a heading at each label.
     pub fn show_memory(&self, cpu: &CpuState, addr: u16, len: usize) {
         let start = addr as usize;
         let end = std::cmp::min(start + len, cpu.memory.len());
 
-        for i in (start..end).step_by(16) {
+        let mut i = start;
+        while i < end {
+            if let Some(name) = self.symbols.name(i as u16) {
+                println!("{}:", name);
+            }
+            let mut row_end = std::cmp::min(i + 16, end);
+            if i + 1 < row_end {
+                let next_label = self
+                    .symbols
+                    .names_in(i as u16 + 1..=(row_end - 1) as u16)
+                    .next();
+                if let Some((label_addr, _)) = next_label {
+                    row_end = label_addr as usize;
+                }
+            }
+
             print!("{:04X}: ", i as u16);
-            let row_end = std::cmp::min(i + 16, end);
             for j in i..row_end {
                 print!("{:02X} ", cpu.memory[j]);
             }
             println!();
+            i = row_end;
         }
     }
 
+    pub fn show_profile(
+        &self,
+        profiler: &Profiler,
+        cpu: &CpuState,
+        ext: &ActiveExtensions,
+        limit: usize,
+    ) {
+        print!(
+            "{}",
+            profiler.report(&cpu.memory, ext, &self.symbols, limit)
+        );
+    }
+
     pub fn show_breakpoints(&self) {
         if self.breakpoints.is_empty() {
             println!("No breakpoints set");
         } else {
             println!("Breakpoints:");
-            for bp in &self.breakpoints {
-                println!("  0x{:04X}", bp);
+            for (bp, condition) in &self.breakpoints {
+                match condition {
+                    Some(condition) => println!("  {} if {}", self.format_addr(*bp), condition),
+                    None => println!("  {}", self.format_addr(*bp)),
+                }
+            }
+        }
+
+        if !self.watchpoints.is_empty() {
+            println!("Watchpoints:");
+            for (idx, watchpoint) in self.watchpoints.iter().enumerate() {
+                println!("  {}: {}", idx, watchpoint);
             }
         }
     }
 }
 
-fn parse_addr(s: &str) -> Result<u16, String> {
+/// Parses a hex address, with or without `0x`, as taken by the debugger and the command line.
+pub fn parse_addr(s: &str) -> Result<u16, String> {
     let s = s.trim_start_matches("0x");
     u16::from_str_radix(s, 16).map_err(|_| format!("Invalid address: {}", s))
 }
+
+/// Parses `V0`..`VF` (case-insensitive) into a register index.
+fn parse_register(s: &str) -> Option<usize> {
+    let digit = s.strip_prefix(['V', 'v'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    usize::from_str_radix(digit, 16).ok()
+}
+
+/// Parses a condition value: `0x` prefixed hex or decimal.
+fn parse_value(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid value: {}", s))
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    let value = parse_value(s)?;
+    u8::try_from(value).map_err(|_| format!("Not a byte: {}", s))
+}
@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use crate::{extensions::ActiveExtensions, instruction::Instruction};
+
+/// One decoded instruction (or data word) of a listing.
+pub struct ListingLine {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+/// Decodes a single opcode into its mnemonic. `next` is the word following the opcode, which
+/// XO-CHIP's 4-byte `F000 NNNN` needs. Returns the mnemonic and the instruction size in bytes.
+/// Opcodes that are unknown for the active extensions are rendered as `DW` data.
+pub fn disassemble_op(opcode: u16, next: Option<u16>, ext: &ActiveExtensions) -> (String, usize) {
+    match (Instruction::decode(opcode, ext), next) {
+        (Instruction::LoadILong, Some(addr)) => (format!("LD I, 0x{:04X}", addr), 4),
+        (Instruction::LoadILong, None) => (format!("DW 0x{:04X}", opcode), 2),
+        (instruction, _) => (instruction.to_string(), 2),
+    }
+}
+
+/// Decodes the instruction stored at `addr` in `memory`.
+pub fn disassemble_at(memory: &[u8], addr: u16, ext: &ActiveExtensions) -> (String, usize) {
+    let read_word = |addr: usize| {
+        memory
+            .get(addr..addr + 2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]))
+    };
+    let addr = addr as usize;
+    match read_word(addr) {
+        Some(opcode) => disassemble_op(opcode, read_word(addr + 2), ext),
+        None => ("??".to_string(), 2),
+    }
+}
+
+/// Linear sweep disassembly of `rom`, loaded at `base`. Data embedded in the ROM is decoded
+/// as instructions too, unknown words showing up as `DW`.
+pub fn disassemble(rom: &[u8], base: u16, ext: &ActiveExtensions) -> Vec<ListingLine> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let addr = base.wrapping_add(offset as u16);
+        if offset + 1 >= rom.len() {
+            lines.push(ListingLine {
+                addr,
+                bytes: vec![rom[offset]],
+                text: format!("DB 0x{:02X}", rom[offset]),
+            });
+            break;
+        }
+
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let next = rom
+            .get(offset + 2..offset + 4)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]));
+        let (text, size) = disassemble_op(opcode, next, ext);
+
+        lines.push(ListingLine {
+            addr,
+            bytes: rom[offset..offset + size].to_vec(),
+            text,
+        });
+        offset += size;
+    }
+
+    lines
+}
+
+/// Formats a listing with addresses and raw bytes, e.g. `0200: 00 E0        CLS`.
+pub fn format_listing(lines: &[ListingLine]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let _ = writeln!(
+            out,
+            "{:04X}: {:<12} {}",
+            line.addr,
+            bytes.join(" "),
+            line.text
+        );
+    }
+    out
+}
@@ -1,6 +1,12 @@
 use crate::conf::{
-    FLAG_COUNT, HI_RES_HEIGHT, HI_RES_WIDTH, KEYS_COUNT, RAM_SIZE, REGISTER_COUNT, STACK_SIZE,
+    AUDIO_PATTERN_SIZE, FLAG_COUNT, HI_RES_HEIGHT, HI_RES_WIDTH, KEYS_COUNT, REGISTER_COUNT,
+    STACK_SIZE,
 };
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use crate::superchip::SuperChip8;
+use crate::vm::{AccessKind, MemoryAccess};
+use crate::xochip::XoChip;
 use anyhow::Result;
 
 pub struct VmContext<'a> {
@@ -9,9 +15,10 @@ pub struct VmContext<'a> {
     pub i_register: &'a mut u16,
     pub stack: &'a mut [u16; STACK_SIZE],
     pub sp: &'a mut u16,
-    pub memory: &'a mut [u8; RAM_SIZE],
+    pub memory: &'a mut Vec<u8>,
 
-    pub screen: &'a mut [bool; HI_RES_HEIGHT * HI_RES_WIDTH],
+    // One bit per bitplane, plane 1 being the least significant bit
+    pub screen: &'a mut [u8; HI_RES_HEIGHT * HI_RES_WIDTH],
     pub keys: &'a [bool; KEYS_COUNT],
     pub delay_timer: &'a mut u8,
     pub sound_timer: &'a mut u8,
@@ -20,6 +27,41 @@ pub struct VmContext<'a> {
     pub current_height: &'a mut usize,
     // S-CHIP specific
     pub rpl_flags: &'a mut [u8; FLAG_COUNT],
+    // XO-CHIP specific
+    pub audio_pattern: &'a mut [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: &'a mut u8,
+
+    pub quirks: &'a Quirks,
+    // Data accesses of the current instruction, reported through `record_access`
+    pub accesses: &'a mut Vec<MemoryAccess>,
+}
+
+impl VmContext<'_> {
+    /// Reports a memory read or write to the VM, which exposes them to the debugger.
+    pub fn record_access(&mut self, addr: usize, len: usize, kind: AccessKind) {
+        self.accesses.push(MemoryAccess { addr, len, kind });
+    }
+}
+
+/// The instruction set extensions enabled on a VM, used to decode extension opcodes.
+/// XO-CHIP being a superset of S-CHIP, `xochip` implies the S-CHIP opcodes too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ActiveExtensions {
+    pub schip: bool,
+    pub xochip: bool,
+}
+
+impl ActiveExtensions {
+    /// The extensions to register on a VM for this set, `XoChip` covering S-CHIP by itself.
+    pub fn create(&self) -> Vec<Box<dyn Extension>> {
+        if self.xochip {
+            vec![Box::new(XoChip::new(true))]
+        } else if self.schip {
+            vec![Box::new(SuperChip8::new(true))]
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 pub trait Extension {
@@ -29,12 +71,23 @@ pub trait Extension {
     /// Checks if the extension is currently enabled.
     fn is_active(&self) -> bool;
 
-    /// Attemps to execute an instruction.
-    /// Returns `Ok(true)` if the opcode was handled and the execution should stop.
-    /// Returns `Ok(false)` if the opcode was not handled(falls through to the base CHIP 8 or next
-    /// extension)
-    fn handle_instruction(&mut self, ctx: &mut VmContext, opcode: u16) -> Result<bool>;
+    /// Attemps to execute an instruction, decoded with every active extension's opcodes.
+    /// Returns `Ok(true)` if the instruction was handled and the execution should stop.
+    /// Returns `Ok(false)` if the instruction was not handled(falls through to the base CHIP 8 or
+    /// next extension)
+    fn handle_instruction(&mut self, ctx: &mut VmContext, instruction: Instruction)
+        -> Result<bool>;
 
     /// Hook for initialization, called once after the VM creation
     fn initialize(&mut self, ctx: &mut VmContext);
+
+    /// Serializes extension specific state (anything not held in `VmContext`) for save states.
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restores the state produced by `save_state`.
+    fn load_state(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,30 @@
+use anyhow::Result;
+
+use crate::{audio::Sound, conf::KEYS_COUNT, vm::Chip8VM};
+
+/// The display, audio and input of an emulator window, or whatever else a VM runs in.
+/// Implemented by `gui::RaylibFrontend` with the `raylib` feature.
+pub trait Frontend {
+    /// Whether the user asked to quit, e.g. by closing the window.
+    fn should_close(&self) -> bool;
+
+    /// Real time spent on the last frame, in seconds, for pacing the emulation.
+    fn frame_time(&self) -> f32;
+
+    /// Whether the key mapped to CHIP-8 key `key` (0x0-0xF) is held down.
+    fn is_key_down(&self, key: u8) -> bool;
+
+    /// Shows the framebuffer of `chip8`.
+    fn present(&mut self, chip8: &Chip8VM);
+
+    /// Keeps `sound` going until the next call, once per frame.
+    fn update_audio(&mut self, sound: Sound);
+
+    /// Copies the state of every mapped key to the keypad of `chip8`.
+    fn update_keypad(&self, chip8: &mut Chip8VM) -> Result<()> {
+        for key in 0..KEYS_COUNT {
+            chip8.keypress(key, self.is_key_down(key as u8))?;
+        }
+        Ok(())
+    }
+}
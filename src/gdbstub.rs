@@ -0,0 +1,509 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use crate::{
+    conf::REGISTER_COUNT,
+    debugger::Debugger,
+    timing::{Clock, Timing},
+    vm::Chip8VM,
+};
+
+// Register numbers following V0-VF, `g`/`G` send all registers in this order. Multi-byte
+// registers are little-endian, as GDB expects from any target.
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REG_COUNT: usize = 21;
+
+// Instructions executed between checks for a GDB interrupt (Ctrl-C) while running
+const INTERRUPT_CHECK_INTERVAL: usize = 1000;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="16"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// GDB Remote Serial Protocol server exposing a VM: registers, memory, software breakpoints,
+/// single step and continue. Breakpoints are stored in the `Debugger`, so watchpoints set there
+/// stop a `continue` as well.
+pub struct GdbStub<'a> {
+    chip8: &'a mut Chip8VM,
+    debugger: &'a mut Debugger,
+    // Ticks the timers when a frame's worth of instructions has run
+    clock: Clock,
+}
+
+/// Outcome of handling one packet.
+enum Reply {
+    Packet(String),
+    /// Detach or kill: reply (if any) and end the session
+    Close(Option<String>),
+}
+
+impl<'a> GdbStub<'a> {
+    /// The VM timers are ticked whenever the instructions executed add up to a frame of
+    /// `timing`, as the session does not run in real time.
+    pub fn new(chip8: &'a mut Chip8VM, debugger: &'a mut Debugger, timing: Timing) -> Self {
+        let mut clock = Clock::new(timing);
+        clock.start_frame();
+        GdbStub {
+            chip8,
+            debugger,
+            clock,
+        }
+    }
+
+    /// Serves one GDB connection until it detaches, kills the target or disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> Result<()> {
+        // Acks and replies are tiny writes, don't let Nagle's algorithm hold them back
+        stream.set_nodelay(true)?;
+        let mut conn = Connection::new(stream);
+
+        while let Some(packet) = conn.read_packet()? {
+            match self.handle_packet(&packet, &mut conn)? {
+                Reply::Packet(reply) => conn.send_packet(&reply)?,
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        conn.send_packet(&reply)?;
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, packet: &str, conn: &mut Connection) -> Result<Reply> {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => stop_reply(SIGTRAP),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => self.write_registers(&packet[1..]),
+            Some(b'p') => self.read_register(&packet[1..]),
+            Some(b'P') => self.write_register(&packet[1..]),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b'Z') | Some(b'z') => self.breakpoint(packet),
+            Some(b's') => self.step(&packet[1..]),
+            Some(b'c') => self.resume(&packet[1..], conn)?,
+            Some(b'H') => "OK".to_string(),
+            Some(b'D') => return Ok(Reply::Close(Some("OK".to_string()))),
+            Some(b'k') => return Ok(Reply::Close(None)),
+            Some(b'q') => self.query(packet),
+            _ => String::new(),
+        };
+        Ok(Reply::Packet(reply))
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+".to_string();
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_pair(args, ',') {
+                Some((offset, len)) => xfer_chunk(TARGET_XML, offset, len),
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn register_value(&self, reg: usize) -> Option<(u16, usize)> {
+        let cpu = self.chip8.get_state();
+        let value = match reg {
+            _ if reg < REGISTER_COUNT => (cpu.registers[reg] as u16, 1),
+            REG_I => (cpu.i_register, 2),
+            REG_PC => (cpu.pc, 2),
+            REG_SP => (cpu.sp, 2),
+            REG_DT => (cpu.delay_timer as u16, 1),
+            REG_ST => (cpu.sound_timer as u16, 1),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn set_register_value(&mut self, reg: usize, value: u16) -> Result<()> {
+        match reg {
+            _ if reg < REGISTER_COUNT => self.chip8.set_register(reg, value as u8)?,
+            REG_I => self.chip8.set_i(value),
+            REG_PC => self.chip8.set_pc(value)?,
+            REG_SP => self.chip8.set_sp(value)?,
+            REG_DT => self.chip8.set_timers(Some(value as u8), None),
+            REG_ST => self.chip8.set_timers(None, Some(value as u8)),
+            _ => bail!("Invalid register number: {}", reg),
+        }
+        // Register edits are not recorded as undo deltas
+        self.debugger.clear_history();
+        Ok(())
+    }
+
+    fn read_registers(&self) -> String {
+        let mut out = String::new();
+        for reg in 0..REG_COUNT {
+            if let Some((value, size)) = self.register_value(reg) {
+                out += &encode_le(value, size);
+            }
+        }
+        out
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        let Some(bytes) = decode_hex(hex) else {
+            return "E01".to_string();
+        };
+
+        let mut offset = 0;
+        for reg in 0..REG_COUNT {
+            let Some((_, size)) = self.register_value(reg) else {
+                continue;
+            };
+            let Some(chunk) = bytes.get(offset..offset + size) else {
+                return "E01".to_string();
+            };
+            let value = chunk
+                .iter()
+                .rev()
+                .fold(0u16, |acc, byte| (acc << 8) | *byte as u16);
+            if self.set_register_value(reg, value).is_err() {
+                return "E01".to_string();
+            }
+            offset += size;
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        let value = usize::from_str_radix(args, 16)
+            .ok()
+            .and_then(|reg| self.register_value(reg));
+        match value {
+            Some((value, size)) => encode_le(value, size),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(reg, value)| {
+            let reg = usize::from_str_radix(reg, 16).ok()?;
+            let bytes = decode_hex(value)?;
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0u16, |acc, byte| (acc << 8) | *byte as u16);
+            Some((reg, value))
+        });
+
+        match parsed.map(|(reg, value)| self.set_register_value(reg, value)) {
+            Some(Ok(())) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let memory = &self.chip8.get_state().memory;
+        let range =
+            parse_pair(args, ',').and_then(|(addr, len)| Some(addr..addr.checked_add(len)?));
+        match range.and_then(|range| memory.get(range)) {
+            Some(bytes) => encode_hex(bytes),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        let (Some((addr, len)), Some(bytes)) = (parse_pair(range, ','), decode_hex(data)) else {
+            return "E01".to_string();
+        };
+        if bytes.len() != len || addr > u16::MAX as usize {
+            return "E01".to_string();
+        }
+
+        match self.chip8.write_memory(addr as u16, &bytes) {
+            Ok(()) => {
+                self.debugger.clear_history();
+                "OK".to_string()
+            }
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    /// `Z0,addr,kind` / `z0,addr,kind`, only software breakpoints are supported.
+    fn breakpoint(&mut self, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+        let mut fields = packet[1..].split(',');
+        if fields.next() != Some("0") {
+            return String::new();
+        }
+
+        match fields.next().map(|addr| u16::from_str_radix(addr, 16)) {
+            Some(Ok(addr)) => {
+                if insert {
+                    self.debugger.set_breakpoint(addr, None);
+                } else {
+                    self.debugger.clear_breakpoint(addr);
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// `s [addr]`: executes one instruction.
+    fn step(&mut self, args: &str) -> String {
+        if let Err(reply) = self.resume_at(args) {
+            return reply;
+        }
+        match self.clock.step(self.chip8, self.debugger) {
+            Ok(()) => {
+                let state = self.chip8.get_state();
+                let _ = self
+                    .debugger
+                    .check_watchpoints(state, self.chip8.last_accesses());
+                stop_reply(SIGTRAP)
+            }
+            Err(_) => stop_reply(SIGILL),
+        }
+    }
+
+    /// `c [addr]`: runs until a breakpoint or watchpoint is hit, the VM fails or GDB sends an
+    /// interrupt.
+    fn resume(&mut self, args: &str, conn: &mut Connection) -> Result<String> {
+        if let Err(reply) = self.resume_at(args) {
+            return Ok(reply);
+        }
+
+        let mut executed = 0;
+        loop {
+            // The instruction we are resuming from is never a breakpoint
+            if executed > 0 && self.debugger.should_break(self.chip8.get_state()) {
+                return Ok(stop_reply(SIGTRAP));
+            }
+            if self.clock.step(self.chip8, self.debugger).is_err() {
+                return Ok(stop_reply(SIGILL));
+            }
+            executed += 1;
+
+            let state = self.chip8.get_state();
+            if self.debugger.has_watchpoints()
+                && self
+                    .debugger
+                    .check_watchpoints(state, self.chip8.last_accesses())
+                    .is_some()
+            {
+                return Ok(stop_reply(SIGTRAP));
+            }
+            if executed % INTERRUPT_CHECK_INTERVAL == 0 && conn.interrupt_pending()? {
+                return Ok(stop_reply(SIGINT));
+            }
+        }
+    }
+
+    /// Handles the optional resume address of `s` and `c`.
+    fn resume_at(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            return Ok(());
+        }
+        let addr = u16::from_str_radix(args, 16).map_err(|_| "E01".to_string())?;
+        self.chip8.set_pc(addr).map_err(|_| "E01".to_string())
+    }
+}
+
+/// Packet framing over the TCP stream: `$<data>#<checksum>`, acknowledged with `+`.
+struct Connection {
+    stream: TcpStream,
+    buf: [u8; 4096],
+    len: usize,
+    pos: usize,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Connection {
+            stream,
+            buf: [0; 4096],
+            len: 0,
+            pos: 0,
+        }
+    }
+
+    /// Returns `None` once the client disconnects.
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        if self.pos == self.len {
+            self.len = self
+                .stream
+                .read(&mut self.buf)
+                .context("Failed to read from GDB")?;
+            self.pos = 0;
+            if self.len == 0 {
+                return Ok(None);
+            }
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    /// Reads the next packet, skipping acks and stray interrupts, and acknowledges it.
+    fn read_packet(&mut self) -> Result<Option<String>> {
+        loop {
+            // Wait for the start of a packet
+            loop {
+                match self.read_byte()? {
+                    Some(b'$') => break,
+                    Some(_) => continue,
+                    None => return Ok(None),
+                }
+            }
+
+            let mut data = Vec::new();
+            let mut checksum: u8 = 0;
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => {
+                        checksum = checksum.wrapping_add(byte);
+                        data.push(byte);
+                    }
+                    None => return Ok(None),
+                }
+            }
+
+            let (Some(hi), Some(lo)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let expected = std::str::from_utf8(&[hi, lo])
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if expected != Some(checksum) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+
+            self.stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> Result<()> {
+        let checksum = data.bytes().fold(0u8, |acc, byte| acc.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).context("Failed to write to GDB")?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Checks, without blocking, whether GDB sent an interrupt (0x03).
+    fn interrupt_pending(&mut self) -> Result<bool> {
+        if self.pos < self.len {
+            return Ok(self.take_interrupt());
+        }
+
+        self.stream.set_nonblocking(true)?;
+        let read = self.stream.read(&mut self.buf);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(0) => Ok(false),
+            Ok(len) => {
+                self.len = len;
+                self.pos = 0;
+                Ok(self.take_interrupt())
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e).context("Failed to read from GDB"),
+        }
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        if self.buf[self.pos] == 0x03 {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(out, "{:02x}", byte);
+    }
+    out
+}
+
+fn encode_le(value: u16, size: usize) -> String {
+    encode_hex(&value.to_le_bytes()[..size])
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `<hex><sep><hex>`, e.g. `addr,len`.
+fn parse_pair(s: &str, sep: char) -> Option<(usize, usize)> {
+    let (a, b) = s.split_once(sep)?;
+    Some((
+        usize::from_str_radix(a, 16).ok()?,
+        usize::from_str_radix(b, 16).ok()?,
+    ))
+}
+
+/// `qXfer` reply: `m` followed by the chunk if more data follows, `l` for the last one.
+fn xfer_chunk(data: &str, offset: usize, len: usize) -> String {
+    let Some(end) = offset.checked_add(len) else {
+        return "E01".to_string();
+    };
+    let start = offset.min(data.len());
+    let end = end.min(data.len());
+    let marker = if end < data.len() { 'm' } else { 'l' };
+    format!("{}{}", marker, &data[start..end])
+}
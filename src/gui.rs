@@ -0,0 +1,152 @@
+use raylib::prelude::*;
+
+use crate::{
+    audio::{Sound, Synth, Tone, SAMPLE_RATE},
+    conf::{HI_RES_HEIGHT, HI_RES_WIDTH, TIMER_HZ},
+    frontend::Frontend,
+    vm::Chip8VM,
+};
+
+const SCALE: i32 = 10;
+const WINDOW_WIDTH: i32 = HI_RES_WIDTH as i32 * SCALE;
+const WINDOW_HEIGHT: i32 = HI_RES_HEIGHT as i32 * SCALE;
+
+// Samples per audio stream buffer, about 23ms at 44.1kHz
+const AUDIO_BUFFER: usize = 1024;
+
+// Indexed by the pixel's bitplanes: off, plane 1, plane 2, both planes
+const PALETTE: [Color; 4] = [
+    Color::BLACK,
+    Color::GREEN,
+    Color::new(255, 102, 0, 255),
+    Color::new(255, 204, 0, 255),
+];
+
+// The 4x4 block under 1 on a QWERTY keyboard, laid out like the COSMAC VIP keypad
+const KEYMAP: [(KeyboardKey, u8); 16] = [
+    (KeyboardKey::KEY_ONE, 0x1),
+    (KeyboardKey::KEY_TWO, 0x2),
+    (KeyboardKey::KEY_THREE, 0x3),
+    (KeyboardKey::KEY_FOUR, 0xC),
+    (KeyboardKey::KEY_Q, 0x4),
+    (KeyboardKey::KEY_W, 0x5),
+    (KeyboardKey::KEY_E, 0x6),
+    (KeyboardKey::KEY_R, 0xD),
+    (KeyboardKey::KEY_A, 0x7),
+    (KeyboardKey::KEY_S, 0x8),
+    (KeyboardKey::KEY_D, 0x9),
+    (KeyboardKey::KEY_F, 0xE),
+    (KeyboardKey::KEY_Z, 0xA),
+    (KeyboardKey::KEY_X, 0x0),
+    (KeyboardKey::KEY_C, 0xB),
+    (KeyboardKey::KEY_V, 0xF),
+];
+
+/// A raylib window with the synthesized tone streamed to the default audio device.
+pub struct RaylibFrontend<'a> {
+    rl: RaylibHandle,
+    thread: RaylibThread,
+    stream: AudioStream<'a>,
+    synth: Synth,
+    buffer: Vec<i16>,
+}
+
+impl<'a> RaylibFrontend<'a> {
+    /// Opens the window. The tone is streamed through `audio`, which outlives the frontend.
+    pub fn new(audio: &'a RaylibAudio, tone: Tone) -> Self {
+        let (mut rl, thread) = raylib::init()
+            .size(WINDOW_WIDTH, WINDOW_HEIGHT)
+            .title("Chip 8 EMU(Extensible)")
+            .build();
+        rl.set_target_fps(TIMER_HZ);
+
+        audio.set_audio_stream_buffer_size_default(AUDIO_BUFFER as i32);
+        let stream = audio.new_audio_stream(SAMPLE_RATE, 16, 1);
+        stream.play();
+
+        RaylibFrontend {
+            rl,
+            thread,
+            stream,
+            synth: Synth::new(tone, SAMPLE_RATE),
+            buffer: vec![0; AUDIO_BUFFER],
+        }
+    }
+
+    /// Whether `key` was pressed since the last frame, for the emulator's own shortcuts.
+    pub fn is_hotkey_pressed(&self, key: KeyboardKey) -> bool {
+        self.rl.is_key_pressed(key)
+    }
+
+    pub fn is_hotkey_down(&self, key: KeyboardKey) -> bool {
+        self.rl.is_key_down(key)
+    }
+
+    /// Stops the audio stream, e.g. while the debugger waits for a command.
+    pub fn pause_audio(&self) {
+        self.stream.pause();
+    }
+
+    pub fn resume_audio(&self) {
+        self.stream.resume();
+    }
+}
+
+impl Frontend for RaylibFrontend<'_> {
+    fn should_close(&self) -> bool {
+        self.rl.window_should_close()
+    }
+
+    fn frame_time(&self) -> f32 {
+        self.rl.get_frame_time()
+    }
+
+    fn is_key_down(&self, key: u8) -> bool {
+        KEYMAP
+            .iter()
+            .any(|&(keyboard_key, chip8_key)| chip8_key == key && self.rl.is_key_down(keyboard_key))
+    }
+
+    fn present(&mut self, chip8: &Chip8VM) {
+        let mut d = self.rl.begin_drawing(&self.thread);
+        d.clear_background(Color::BLACK);
+
+        let (screen_width, screen_height, screen_buf) = chip8.get_display_config();
+
+        let x_offset = (WINDOW_WIDTH - (screen_width as i32) * SCALE) / 2;
+        let y_offset = (WINDOW_HEIGHT - (screen_height as i32) * SCALE) / 2;
+
+        for y in 0..screen_height {
+            for x in 0..screen_width {
+                let idx = x + y * HI_RES_WIDTH;
+
+                if screen_buf[idx] != 0 {
+                    d.draw_rectangle(
+                        x_offset + (x as i32) * SCALE,
+                        y_offset + (y as i32) * SCALE,
+                        SCALE,
+                        SCALE,
+                        PALETTE[(screen_buf[idx] & 0b11) as usize],
+                    );
+                }
+            }
+        }
+
+        let screen_rect = Rectangle::new(
+            x_offset as f32,
+            y_offset as f32,
+            (screen_width as i32 * SCALE) as f32,
+            (screen_height as i32 * SCALE) as f32,
+        );
+
+        d.draw_rectangle_lines_ex(screen_rect, 2.0, Color::GRAY);
+    }
+
+    /// Refills the buffers the device is done with.
+    fn update_audio(&mut self, sound: Sound) {
+        while self.stream.is_processed() {
+            self.synth.fill(&mut self.buffer, sound);
+            self.stream.update(&self.buffer);
+        }
+    }
+}
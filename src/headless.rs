@@ -0,0 +1,273 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    asm::Assembly,
+    conf::{HI_RES_WIDTH, START_ADDR},
+    coverage::Coverage,
+    disasm,
+    extensions::ActiveExtensions,
+    instruction::{Instruction, OpcodePattern},
+    movie::InputEvent,
+    timing::{Clock, Timing},
+    vm::Chip8VM,
+};
+
+/// Condition that ends a headless run before the frame limit, checked before every instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    Pc(u16),
+    Opcode(OpcodePattern),
+}
+
+impl StopCondition {
+    fn matches(&self, pc: u16, opcode: u16) -> bool {
+        match self {
+            StopCondition::Pc(addr) => pc == *addr,
+            StopCondition::Opcode(pattern) => pattern.matches(opcode),
+        }
+    }
+}
+
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopCondition::Pc(addr) => write!(f, "PC 0x{:04X}", addr),
+            StopCondition::Opcode(pattern) => write!(f, "opcode {}", pattern),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StopReason {
+    FrameLimit,
+    Condition(StopCondition),
+    Error(anyhow::Error),
+}
+
+pub struct RunOutcome {
+    pub frames: u64,
+    pub reason: StopReason,
+}
+
+/// Runs the VM without any frontend: the instructions `timing` fits in a frame followed by a
+/// timer tick, for at most `max_frames` frames.
+pub fn run(
+    chip8: &mut Chip8VM,
+    max_frames: u64,
+    timing: Timing,
+    conditions: &[StopCondition],
+    input: &[InputEvent],
+) -> RunOutcome {
+    let mut pending = input.iter().peekable();
+    let mut clock = Clock::new(timing);
+
+    for frame in 0..max_frames {
+        while let Some(event) = pending.next_if(|event| event.frame <= frame) {
+            if let Err(e) = chip8.keypress(event.key as usize, event.pressed) {
+                return RunOutcome {
+                    frames: frame,
+                    reason: StopReason::Error(e),
+                };
+            }
+        }
+
+        clock.start_frame();
+        while clock.can_run() {
+            let pc = chip8.get_state().pc;
+            let opcode = chip8.peek_opcode();
+            if let Some(condition) = conditions.iter().find(|c| c.matches(pc, opcode)) {
+                return RunOutcome {
+                    frames: frame,
+                    reason: StopReason::Condition(*condition),
+                };
+            }
+
+            let instruction = chip8.peek_instruction();
+            if let Err(e) = chip8.tick() {
+                return RunOutcome {
+                    frames: frame,
+                    reason: StopReason::Error(e),
+                };
+            }
+            clock.charge(chip8, instruction);
+        }
+        chip8.tick_timers();
+    }
+
+    RunOutcome {
+        frames: max_frames,
+        reason: StopReason::FrameLimit,
+    }
+}
+
+/// Renders the active part of the framebuffer as text, one character per pixel:
+/// `.` for off, `#` for plane 1, `+` for plane 2 and `*` for both.
+pub fn render_text(chip8: &Chip8VM) -> String {
+    const PIXELS: [char; 4] = ['.', '#', '+', '*'];
+    let (width, height, screen) = chip8.get_display_config();
+    let mut out = String::with_capacity((width + 1) * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            out.push(PIXELS[(screen[x + y * HI_RES_WIDTH] & 0b11) as usize]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders the active part of the framebuffer as a plain (P1) PBM image, any lit plane
+/// counting as a black pixel. Rows are split every 64 pixels to respect the format's
+/// 70 characters per line limit.
+pub fn render_pbm(chip8: &Chip8VM) -> String {
+    const PIXELS_PER_LINE: usize = 64;
+    let (width, height, screen) = chip8.get_display_config();
+    let mut out = String::new();
+
+    let _ = writeln!(out, "P1\n{} {}", width, height);
+    for y in 0..height {
+        for (x, pixel) in screen[y * HI_RES_WIDTH..y * HI_RES_WIDTH + width]
+            .iter()
+            .enumerate()
+        {
+            out.push(if *pixel != 0 { '1' } else { '0' });
+            if (x + 1) % PIXELS_PER_LINE == 0 || x + 1 == width {
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// Renders an annotated listing of `rom`, loaded at `base`, with the execution count of
+/// every instruction and the outcomes of skips. Instructions that never ran are marked
+/// `#####`, words that do not decode as `-`.
+pub fn render_coverage_listing(
+    coverage: &Coverage,
+    rom: &[u8],
+    base: u16,
+    ext: &ActiveExtensions,
+) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let addr = base.wrapping_add(offset as u16);
+        let word = rom.get(offset..offset + 2);
+        // Decode a lone byte when the next one starts an executed instruction, so that
+        // code following odd-sized data lines up
+        let (text, size) = match word {
+            Some(&[hi, lo])
+                if coverage.hits(addr) > 0 || coverage.hits(addr.wrapping_add(1)) == 0 =>
+            {
+                let next = rom
+                    .get(offset + 2..offset + 4)
+                    .map(|word| u16::from_be_bytes([word[0], word[1]]));
+                disasm::disassemble_op(u16::from_be_bytes([hi, lo]), next, ext)
+            }
+            _ => (format!("DB 0x{:02X}", rom[offset]), 1),
+        };
+        let size = size.min(rom.len() - offset);
+
+        let count = match coverage.hits(addr) {
+            0 if text.starts_with("DB") || text.starts_with("DW") => "-".to_string(),
+            0 => "#####".to_string(),
+            hits => hits.to_string(),
+        };
+        let bytes: Vec<String> = rom[offset..offset + size]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let _ = write!(
+            out,
+            "{:>8}  {:04X}: {:<12} {}",
+            count,
+            addr,
+            bytes.join(" "),
+            text
+        );
+        if let Some(outcomes) = coverage.skip(addr) {
+            let _ = write!(
+                out,
+                "  [taken {}, not taken {}]",
+                outcomes.taken, outcomes.not_taken
+            );
+        }
+        out.push('\n');
+
+        offset += size;
+    }
+
+    out
+}
+
+/// Renders an lcov tracefile for the source `assembly` was built from, with a line record
+/// for every instruction and a pair of branch records (taken, not taken) for every skip.
+pub fn render_lcov(coverage: &Coverage, assembly: &Assembly, ext: &ActiveExtensions) -> String {
+    let mut files: BTreeMap<&PathBuf, BTreeMap<usize, Vec<u16>>> = BTreeMap::new();
+    for loc in assembly.source_map.iter().filter(|loc| !loc.data) {
+        files
+            .entry(&loc.file)
+            .or_default()
+            .entry(loc.line)
+            .or_default()
+            .push(loc.addr);
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "TN:");
+    for (file, lines) in files {
+        let _ = writeln!(out, "SF:{}", file.display());
+
+        let (mut found, mut hit) = (0, 0);
+        let (mut branches_found, mut branches_hit) = (0, 0);
+        for (&line, addrs) in &lines {
+            let hits: u64 = addrs.iter().map(|&addr| coverage.hits(addr)).sum();
+            let _ = writeln!(out, "DA:{},{}", line, hits);
+            found += 1;
+            hit += (hits > 0) as usize;
+
+            for (block, &addr) in addrs.iter().enumerate() {
+                let offset = addr.wrapping_sub(START_ADDR) as usize;
+                let Some(&[hi, lo]) = assembly.rom.get(offset..offset + 2) else {
+                    continue;
+                };
+                if !Instruction::decode(u16::from_be_bytes([hi, lo]), ext).is_skip() {
+                    continue;
+                }
+
+                // Branch 0 is the skip being taken, `-` meaning the skip never ran
+                let outcomes = coverage.skip(addr);
+                for (branch, count) in [
+                    (0, outcomes.map(|o| o.taken)),
+                    (1, outcomes.map(|o| o.not_taken)),
+                ] {
+                    match count {
+                        Some(count) => {
+                            let _ = writeln!(out, "BRDA:{},{},{},{}", line, block, branch, count);
+                            branches_hit += (count > 0) as usize;
+                        }
+                        None => {
+                            let _ = writeln!(out, "BRDA:{},{},{},-", line, block, branch);
+                        }
+                    }
+                    branches_found += 1;
+                }
+            }
+        }
+
+        if branches_found > 0 {
+            let _ = writeln!(out, "BRF:{}", branches_found);
+            let _ = writeln!(out, "BRH:{}", branches_hit);
+        }
+        let _ = writeln!(out, "LF:{}", found);
+        let _ = writeln!(out, "LH:{}", hit);
+        let _ = writeln!(out, "end_of_record");
+    }
+
+    out
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+
+use crate::{
+    conf::HISTORY_SIZE,
+    vm::{AccessKind, Chip8VM, MemoryAccess, UndoDelta},
+};
+
+/// One executed instruction: how to undo it, and the memory accesses it made.
+pub struct HistoryEntry {
+    pub delta: UndoDelta,
+    pub accesses: Vec<MemoryAccess>,
+}
+
+/// Bounded per-instruction undo log for reverse debugging. Changed memory and pixels are found
+/// by diffing against shadow copies of the machine, so the history has to be cleared whenever
+/// the machine is modified outside of `execute` (state loads, rewinding, memory edits).
+pub struct ExecutionHistory {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    shadow_memory: Vec<u8>,
+    shadow_screen: Vec<u8>,
+}
+
+impl Default for ExecutionHistory {
+    fn default() -> Self {
+        Self::new(HISTORY_SIZE)
+    }
+}
+
+impl ExecutionHistory {
+    pub fn new(capacity: usize) -> Self {
+        ExecutionHistory {
+            entries: VecDeque::new(),
+            capacity,
+            shadow_memory: Vec::new(),
+            shadow_screen: Vec::new(),
+        }
+    }
+
+    /// Executes one instruction, recording how to undo it.
+    pub fn execute(&mut self, chip8: &mut Chip8VM) -> Result<()> {
+        if self.capacity == 0 {
+            return chip8.tick();
+        }
+        if self.shadow_memory.is_empty() {
+            self.shadow_memory = chip8.get_state().memory.clone();
+            self.shadow_screen = chip8.get_display_config().2.to_vec();
+        }
+
+        let registers = chip8.cpu_registers();
+        let extensions = chip8.extension_states();
+        if let Err(e) = chip8.tick() {
+            // The failed instruction may have changed the machine half way
+            self.clear();
+            return Err(e);
+        }
+
+        let mut memory = Vec::new();
+        let current_memory = &chip8.get_state().memory;
+        for access in chip8.last_accesses() {
+            if access.kind != AccessKind::Write {
+                continue;
+            }
+            let range = access.addr..access.addr + access.len;
+            let shadow = &mut self.shadow_memory[range.clone()];
+            for ((addr, old), new) in range.zip(shadow).zip(&current_memory[access.addr..]) {
+                if old != new {
+                    memory.push((addr, *old));
+                    *old = *new;
+                }
+            }
+        }
+
+        let mut pixels = Vec::new();
+        let (_, _, screen) = chip8.get_display_config();
+        for (idx, (old, new)) in self.shadow_screen.iter_mut().zip(screen).enumerate() {
+            if old != new {
+                pixels.push((idx, *old));
+                *old = *new;
+            }
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry {
+            delta: UndoDelta {
+                registers,
+                memory,
+                pixels,
+                extensions: (chip8.extension_states() != extensions).then_some(extensions),
+            },
+            accesses: chip8.last_accesses().to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Undoes the most recent instruction, returning its entry.
+    pub fn step_back(&mut self, chip8: &mut Chip8VM) -> Option<HistoryEntry> {
+        let entry = self.entries.pop_back()?;
+        chip8.apply_undo(&entry.delta);
+
+        for &(addr, value) in &entry.delta.memory {
+            self.shadow_memory[addr] = value;
+        }
+        for &(idx, value) in &entry.delta.pixels {
+            self.shadow_screen[idx] = value;
+        }
+        Some(entry)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.shadow_memory.clear();
+        self.shadow_screen.clear();
+    }
+}
@@ -0,0 +1,375 @@
+use anyhow::{bail, Context, Result};
+use std::fmt;
+
+use crate::extensions::ActiveExtensions;
+
+/// A decoded opcode. Register operands are indices into V0..VF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // CHIP-8
+    /// 0NNN: machine code routine, only `0000` is executed (as a no-op)
+    Sys(u16),
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipEqImm { x: usize, nn: u8 },
+    /// 4XNN
+    SkipNeImm { x: usize, nn: u8 },
+    /// 5XY0
+    SkipEqReg { x: usize, y: usize },
+    /// 6XNN
+    LoadImm { x: usize, nn: u8 },
+    /// 7XNN
+    AddImm { x: usize, nn: u8 },
+    /// 8XY0
+    Move { x: usize, y: usize },
+    /// 8XY1
+    Or { x: usize, y: usize },
+    /// 8XY2
+    And { x: usize, y: usize },
+    /// 8XY3
+    Xor { x: usize, y: usize },
+    /// 8XY4
+    AddReg { x: usize, y: usize },
+    /// 8XY5
+    Sub { x: usize, y: usize },
+    /// 8XY6
+    ShiftRight { x: usize, y: usize },
+    /// 8XY7
+    SubN { x: usize, y: usize },
+    /// 8XYE
+    ShiftLeft { x: usize, y: usize },
+    /// 9XY0
+    SkipNeReg { x: usize, y: usize },
+    /// ANNN
+    LoadI(u16),
+    /// BNNN, `x` only matters with the jump quirk
+    JumpOffset { x: usize, nnn: u16 },
+    /// CXNN
+    Random { x: usize, nn: u8 },
+    /// DXYN
+    Draw { x: usize, y: usize, n: u8 },
+    /// EX9E
+    SkipKey(usize),
+    /// EXA1
+    SkipNotKey(usize),
+    /// FX07
+    LoadDelay(usize),
+    /// FX0A
+    WaitKey(usize),
+    /// FX15
+    SetDelay(usize),
+    /// FX18
+    SetSound(usize),
+    /// FX1E
+    AddI(usize),
+    /// FX29
+    LoadFont(usize),
+    /// FX33
+    Bcd(usize),
+    /// FX55
+    Store(usize),
+    /// FX65
+    Load(usize),
+
+    // S-CHIP
+    /// 00CN
+    ScrollDown(u8),
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    LowRes,
+    /// 00FF
+    HighRes,
+    /// FX30
+    LoadBigFont(usize),
+    /// FX75
+    SaveFlags(usize),
+    /// FX85
+    LoadFlags(usize),
+
+    // XO-CHIP
+    /// 00DN
+    ScrollUp(u8),
+    /// 5XY2
+    StoreRange { x: usize, y: usize },
+    /// 5XY3
+    LoadRange { x: usize, y: usize },
+    /// F000 NNNN, the address being the word following the opcode
+    LoadILong,
+    /// FN01
+    SelectPlanes(u8),
+    /// F002
+    LoadAudio,
+    /// FX3A
+    SetPitch(usize),
+
+    /// Not a valid opcode for the active extensions
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// Decodes `opcode`, extension opcodes only being recognized when their extension is
+    /// active.
+    pub fn decode(opcode: u16, ext: &ActiveExtensions) -> Self {
+        use Instruction::*;
+
+        let d1 = (opcode & 0xF000) >> 12;
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let schip = ext.schip || ext.xochip;
+
+        match (d1, x, y, n) {
+            (0, 0, 0xE, 0) => ClearScreen,
+            (0, 0, 0xE, 0xE) => Return,
+            (0, 0, 0xC, _) if schip => ScrollDown(n),
+            (0, 0, 0xD, _) if ext.xochip => ScrollUp(n),
+            (0, 0, 0xF, 0xB) if schip => ScrollRight,
+            (0, 0, 0xF, 0xC) if schip => ScrollLeft,
+            (0, 0, 0xF, 0xD) if schip => Exit,
+            (0, 0, 0xF, 0xE) if schip => LowRes,
+            (0, 0, 0xF, 0xF) if schip => HighRes,
+            (0, _, _, _) => Sys(nnn),
+            (1, _, _, _) => Jump(nnn),
+            (2, _, _, _) => Call(nnn),
+            (3, _, _, _) => SkipEqImm { x, nn },
+            (4, _, _, _) => SkipNeImm { x, nn },
+            (5, _, _, 0) => SkipEqReg { x, y },
+            (5, _, _, 2) if ext.xochip => StoreRange { x, y },
+            (5, _, _, 3) if ext.xochip => LoadRange { x, y },
+            (6, _, _, _) => LoadImm { x, nn },
+            (7, _, _, _) => AddImm { x, nn },
+            (8, _, _, 0) => Move { x, y },
+            (8, _, _, 1) => Or { x, y },
+            (8, _, _, 2) => And { x, y },
+            (8, _, _, 3) => Xor { x, y },
+            (8, _, _, 4) => AddReg { x, y },
+            (8, _, _, 5) => Sub { x, y },
+            (8, _, _, 6) => ShiftRight { x, y },
+            (8, _, _, 7) => SubN { x, y },
+            (8, _, _, 0xE) => ShiftLeft { x, y },
+            (9, _, _, 0) => SkipNeReg { x, y },
+            (0xA, _, _, _) => LoadI(nnn),
+            (0xB, _, _, _) => JumpOffset { x, nnn },
+            (0xC, _, _, _) => Random { x, nn },
+            (0xD, _, _, _) => Draw { x, y, n },
+            (0xE, _, 9, 0xE) => SkipKey(x),
+            (0xE, _, 0xA, 1) => SkipNotKey(x),
+            (0xF, 0, 0, 0) if ext.xochip => LoadILong,
+            (0xF, _, 0, 1) if ext.xochip => SelectPlanes(x as u8),
+            (0xF, 0, 0, 2) if ext.xochip => LoadAudio,
+            (0xF, _, 0, 7) => LoadDelay(x),
+            (0xF, _, 0, 0xA) => WaitKey(x),
+            (0xF, _, 1, 5) => SetDelay(x),
+            (0xF, _, 1, 8) => SetSound(x),
+            (0xF, _, 1, 0xE) => AddI(x),
+            (0xF, _, 2, 9) => LoadFont(x),
+            (0xF, _, 3, 0) if schip => LoadBigFont(x),
+            (0xF, _, 3, 3) => Bcd(x),
+            (0xF, _, 3, 0xA) if ext.xochip => SetPitch(x),
+            (0xF, _, 5, 5) => Store(x),
+            (0xF, _, 6, 5) => Load(x),
+            (0xF, _, 7, 5) if schip => SaveFlags(x),
+            (0xF, _, 8, 5) if schip => LoadFlags(x),
+            _ => Unknown(opcode),
+        }
+    }
+
+    /// Whether the instruction is one of the conditional skips.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipEqImm { .. }
+                | Instruction::SkipNeImm { .. }
+                | Instruction::SkipEqReg { .. }
+                | Instruction::SkipNeReg { .. }
+                | Instruction::SkipKey(_)
+                | Instruction::SkipNotKey(_)
+        )
+    }
+
+    /// The opcode pattern the instruction was decoded from, e.g. `8XY4`, identifying its class
+    /// for statistics.
+    pub fn pattern(&self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            Sys(_) => "0NNN",
+            ClearScreen => "00E0",
+            Return => "00EE",
+            Jump(_) => "1NNN",
+            Call(_) => "2NNN",
+            SkipEqImm { .. } => "3XNN",
+            SkipNeImm { .. } => "4XNN",
+            SkipEqReg { .. } => "5XY0",
+            LoadImm { .. } => "6XNN",
+            AddImm { .. } => "7XNN",
+            Move { .. } => "8XY0",
+            Or { .. } => "8XY1",
+            And { .. } => "8XY2",
+            Xor { .. } => "8XY3",
+            AddReg { .. } => "8XY4",
+            Sub { .. } => "8XY5",
+            ShiftRight { .. } => "8XY6",
+            SubN { .. } => "8XY7",
+            ShiftLeft { .. } => "8XYE",
+            SkipNeReg { .. } => "9XY0",
+            LoadI(_) => "ANNN",
+            JumpOffset { .. } => "BNNN",
+            Random { .. } => "CXNN",
+            Draw { .. } => "DXYN",
+            SkipKey(_) => "EX9E",
+            SkipNotKey(_) => "EXA1",
+            LoadDelay(_) => "FX07",
+            WaitKey(_) => "FX0A",
+            SetDelay(_) => "FX15",
+            SetSound(_) => "FX18",
+            AddI(_) => "FX1E",
+            LoadFont(_) => "FX29",
+            Bcd(_) => "FX33",
+            Store(_) => "FX55",
+            Load(_) => "FX65",
+            ScrollDown(_) => "00CN",
+            ScrollRight => "00FB",
+            ScrollLeft => "00FC",
+            Exit => "00FD",
+            LowRes => "00FE",
+            HighRes => "00FF",
+            LoadBigFont(_) => "FX30",
+            SaveFlags(_) => "FX75",
+            LoadFlags(_) => "FX85",
+            ScrollUp(_) => "00DN",
+            StoreRange { .. } => "5XY2",
+            LoadRange { .. } => "5XY3",
+            LoadILong => "F000",
+            SelectPlanes(_) => "FN01",
+            LoadAudio => "F002",
+            SetPitch(_) => "FX3A",
+            Unknown(_) => "????",
+        }
+    }
+}
+
+/// Formats the instruction with Cowgod's mnemonics, e.g. `LD V3, 0x2A`. `LoadILong` cannot
+/// show its operand, which lives outside the opcode.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            ClearScreen => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            LoadImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            JumpOffset { nnn, .. } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Random { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey(x) => write!(f, "SKP V{:X}", x),
+            SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            LoadFont(x) => write!(f, "LD F, V{:X}", x),
+            Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Store(x) => write!(f, "LD [I], V{:X}", x),
+            Load(x) => write!(f, "LD V{:X}, [I]", x),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            LoadBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            StoreRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            LoadILong => write!(f, "LD I, LONG"),
+            SelectPlanes(n) => write!(f, "PLANE {}", n),
+            LoadAudio => write!(f, "AUDIO"),
+            SetPitch(x) => write!(f, "PITCH V{:X}", x),
+            Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
+
+/// An opcode pattern where `?` matches any nibble, e.g. `00FD` or `D???`. Only the bits set in
+/// `mask` are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodePattern {
+    pub value: u16,
+    pub mask: u16,
+}
+
+impl OpcodePattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let digits = pattern.trim_start_matches("0x");
+        if digits.len() != 4 {
+            bail!("Opcode pattern must have 4 nibbles: {}", pattern);
+        }
+
+        let (mut value, mut mask) = (0u16, 0u16);
+        for c in digits.chars() {
+            value <<= 4;
+            mask <<= 4;
+            if c != '?' {
+                let nibble = c
+                    .to_digit(16)
+                    .with_context(|| format!("Invalid opcode pattern: {}", pattern))?;
+                value |= nibble as u16;
+                mask |= 0xF;
+            }
+        }
+        Ok(OpcodePattern { value, mask })
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for shift in [12, 8, 4, 0] {
+            if (self.mask >> shift) & 0xF == 0 {
+                write!(f, "?")?;
+            } else {
+                write!(f, "{:X}", (self.value >> shift) & 0xF)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,27 @@
+pub mod asm;
+pub mod audio;
 pub mod conf;
+pub mod coverage;
+pub mod dap;
 pub mod debugger;
+pub mod disasm;
 pub mod extensions;
+pub mod frontend;
+pub mod gdbstub;
+#[cfg(feature = "raylib")]
+pub mod gui;
+pub mod headless;
+pub mod history;
+pub mod instruction;
+pub mod movie;
+pub mod profile;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod savestate;
 pub mod superchip;
+pub mod symbols;
+pub mod timing;
+pub mod trace;
 pub mod vm;
+pub mod xochip;
@@ -1,27 +1,30 @@
-mod conf;
-mod debugger;
-mod extensions;
-mod superchip;
-mod vm;
-
 use anyhow::{Context, Result};
 use clap::Parser;
-use raylib::prelude::*;
+use raylib::prelude::{KeyboardKey, RaylibAudio};
 use std::{
-    collections::HashMap,
-    fs::File,
+    fs::{self, File},
     io::{BufRead, Read, Write},
-    path::PathBuf,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
 };
 
-use crate::conf::{HI_RES_HEIGHT, HI_RES_WIDTH};
-use crate::debugger::{DebugAction, Debugger};
-use crate::extensions::Extension;
-use crate::superchip::SuperChip8;
-use crate::vm::Chip8VM;
-
-const SCALE: i32 = 10;
-const TICK_PER_FRAME: usize = 10;
+use chip8::audio::{Sound, Tone, Waveform, DEFAULT_FREQUENCY, DEFAULT_VOLUME};
+use chip8::conf::{DEFAULT_IPS, REWIND_MAX_BYTES};
+use chip8::debugger::{DebugAction, Debugger, Register, PROFILE_LINES};
+use chip8::extensions::ActiveExtensions;
+use chip8::frontend::Frontend;
+use chip8::gui::RaylibFrontend;
+use chip8::instruction::OpcodePattern;
+use chip8::movie::{Movie, Replay};
+use chip8::profile::Profiler;
+use chip8::quirks::Quirks;
+use chip8::rewind::RewindBuffer;
+use chip8::symbols::SymbolTable;
+use chip8::timing::{Clock, FramePacer, Timing};
+use chip8::trace::{TraceFilter, TraceFormat, Tracer};
+use chip8::vm::Chip8VM;
+
+const SAVE_SLOTS: u8 = 10;
 
 // This struct defines the command-line arguments using clap's derive API.
 #[derive(Parser, Debug)]
@@ -32,12 +35,80 @@ struct Cli {
 
     #[arg(short = 's', long)]
     enable_schip: bool,
-    /*
+
+    /// Enable XO-CHIP (implies S-CHIP)
     #[arg(short = 'x', long)]
     enable_xochip: bool,
-    */
-    #[arg(short = 'd', long)]
+
+    #[arg(short = 'd', long, conflicts_with_all = ["record", "replay"])]
     debug: bool,
+
+    /// Symbol file with `addr name` lines or Octo-style `: name addr` labels for the debugger
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+
+    /// Number of frames kept for rewinding (hold Backspace), 0 disables rewinding. A frame
+    /// takes about 12KB (74KB with XO-CHIP), and at most 32MB are kept.
+    #[arg(long, default_value_t = 600)]
+    rewind_frames: usize,
+
+    /// Interpreter quirks to emulate: vip, schip or xochip
+    /// [default: matches the enabled extension, vip otherwise]
+    #[arg(short = 'q', long)]
+    quirks: Option<Quirks>,
+
+    /// Seed for the CXNN random numbers, making runs reproducible [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Instructions executed per second, the timers and display running at 60Hz
+    #[arg(long, default_value_t = DEFAULT_IPS, value_parser = clap::value_parser!(u32).range(1..))]
+    ips: u32,
+
+    /// Give every instruction its COSMAC VIP execution time instead of a fixed rate
+    #[arg(long, conflicts_with = "ips")]
+    vip_timing: bool,
+
+    /// Pitch of the tone played while the sound timer runs, in Hz
+    #[arg(long, default_value_t = DEFAULT_FREQUENCY)]
+    tone_frequency: f32,
+
+    /// Tone volume, from 0 (mute) to 1
+    #[arg(long, default_value_t = DEFAULT_VOLUME)]
+    volume: f32,
+
+    /// Tone waveform: square, triangle, sawtooth or sine
+    #[arg(long, default_value = "square")]
+    waveform: Waveform,
+
+    /// Log every executed instruction to this file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Trace file format: text or binary
+    #[arg(long, default_value = "text")]
+    trace_format: TraceFormat,
+
+    /// Only trace instructions in this hex PC range, e.g. `200-2FF` (repeatable)
+    #[arg(long, value_parser = TraceFilter::parse_range)]
+    trace_pc: Vec<RangeInclusive<u16>>,
+
+    /// Only trace opcodes matching this pattern, `?` matching any nibble (repeatable)
+    #[arg(long, value_parser = OpcodePattern::parse)]
+    trace_opcode: Vec<OpcodePattern>,
+
+    /// Count executed instructions and print a hot-spot report on exit
+    #[arg(long)]
+    profile: bool,
+
+    /// Record the keypad input to this movie file, written on exit
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Play back a movie recorded with --record, its extensions, quirks and seed overriding
+    /// the command line. The keyboard takes over once it ends.
+    #[arg(long)]
+    replay: Option<PathBuf>,
 }
 
 fn main() {
@@ -50,15 +121,12 @@ fn main() {
 }
 
 fn run_debugger_loop(
-    rl: &mut RaylibHandle,
-    thread: &RaylibThread,
+    frontend: &mut impl Frontend,
     chip8: &mut Chip8VM,
     debugger: &mut Debugger,
+    clock: &mut Clock,
     paused: &mut bool,
-    beep: &raylib::core::audio::Sound,
-    _keytobtn: &HashMap<KeyboardKey, u8>,
-    window_dims: (i32, i32, i32),
-) -> Result<()> {
+) -> Result<bool> {
     let stdin = std::io::stdin();
     let mut line = String::new();
 
@@ -72,26 +140,38 @@ fn run_debugger_loop(
         match debugger.parse_and_execute(&line, chip8.get_state()) {
             Ok(DebugAction::Quit) => {
                 println!("Exiting debugger...");
-                std::process::exit(0);
+                return Ok(true);
             }
             Ok(DebugAction::Step) => {
-                chip8.tick()?;
-                let (_, st) = chip8.tick_timers();
-                if st == 1 {
-                    beep.play();
+                clock.step(chip8, debugger)?;
+                report_trace_error(chip8);
+                if let Some(hit) =
+                    debugger.check_watchpoints(chip8.get_state(), chip8.last_accesses())
+                {
+                    println!("{}", hit);
                 }
-                let state = chip8.get_state();
-                println!(
-                    "PC: 0x{:04X}, Opcode: 0x{:04X}",
-                    state.pc,
-                    fetch_op(chip8, state)
-                );
-                render_screen(rl, thread, chip8, window_dims);
+                print_position(chip8, debugger);
+                frontend.present(chip8);
             }
             Ok(DebugAction::Continue) => {
                 *paused = false;
                 break;
             }
+            Ok(DebugAction::ReverseStep) => match debugger.reverse_step(chip8) {
+                Ok(hit) => {
+                    if let Some(hit) = hit {
+                        println!("{}", hit);
+                    }
+                    print_position(chip8, debugger);
+                    frontend.present(chip8);
+                }
+                Err(e) => println!("Error: {}", e),
+            },
+            Ok(DebugAction::ReverseContinue) => {
+                println!("{}", debugger.reverse_continue(chip8));
+                print_position(chip8, debugger);
+                frontend.present(chip8);
+            }
             Ok(DebugAction::ShowRegisters) => {
                 debugger.show_registers(chip8.get_state());
             }
@@ -101,6 +181,82 @@ fn run_debugger_loop(
             Ok(DebugAction::ShowBreakpoints) => {
                 debugger.show_breakpoints();
             }
+            Ok(DebugAction::Backtrace) => {
+                debugger.show_backtrace(chip8.get_state(), &chip8.active_extensions());
+            }
+            Ok(DebugAction::ShowFrame(n)) => {
+                debugger.show_frame(chip8.get_state(), n, &chip8.active_extensions());
+            }
+            Ok(DebugAction::SetRegister(register, value)) => {
+                let result = match register {
+                    Register::V(x) => chip8.set_register(x, value as u8),
+                    Register::I => {
+                        chip8.set_i(value);
+                        Ok(())
+                    }
+                    Register::Pc => chip8.set_pc(value),
+                };
+                match result {
+                    Ok(()) => println!("{} = 0x{:X}", register, value),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            Ok(DebugAction::Poke(addr, bytes)) => match chip8.write_memory(addr, &bytes) {
+                Ok(()) => {
+                    debugger.clear_history();
+                    debugger.show_memory(chip8.get_state(), addr, bytes.len());
+                }
+                Err(e) => println!("Error: {}", e),
+            },
+            Ok(DebugAction::Fill(addr, len, byte)) => {
+                match chip8.write_memory(addr, &vec![byte; len]) {
+                    Ok(()) => {
+                        debugger.clear_history();
+                        println!("Filled {} bytes at 0x{:04X} with 0x{:02X}", len, addr, byte)
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            Ok(DebugAction::LoadSymbols(path)) => match SymbolTable::load(&path) {
+                Ok(symbols) => {
+                    println!("Loaded {} symbols from {}", symbols.len(), path.display());
+                    debugger.set_symbols(symbols);
+                }
+                Err(e) => println!("Error: {:#}", e),
+            },
+            Ok(DebugAction::TraceOn(path, format, filter)) => {
+                match Tracer::create(&path, format, filter, chip8.active_extensions()) {
+                    Ok(tracer) => {
+                        stop_trace(chip8);
+                        chip8.set_tracer(Some(tracer));
+                        println!("Tracing to {}", path.display());
+                    }
+                    Err(e) => println!("Error: {:#}", e),
+                }
+            }
+            Ok(DebugAction::TraceOff) => {
+                if !stop_trace(chip8) {
+                    println!("Not tracing");
+                }
+            }
+            Ok(DebugAction::ShowProfile(limit)) => match chip8.profiler() {
+                Some(profiler) => debugger.show_profile(
+                    profiler,
+                    chip8.get_state(),
+                    &chip8.active_extensions(),
+                    limit,
+                ),
+                None => println!("Not profiling, use `profile start`"),
+            },
+            Ok(DebugAction::StartProfile) => {
+                chip8.set_profiler(Some(Profiler::new()));
+                println!("Profiling started");
+            }
+            Ok(DebugAction::StopProfile) => {
+                if !stop_profile(chip8, debugger) {
+                    println!("Not profiling");
+                }
+            }
             Ok(DebugAction::Help) => {}
             Err(e) => {
                 println!("Error: {}", e);
@@ -108,83 +264,98 @@ fn run_debugger_loop(
         }
     }
 
-    Ok(())
+    Ok(false)
 }
 
-fn fetch_op(_chip8: &Chip8VM, state: &crate::vm::CpuState) -> u16 {
-    let hi = state.memory[state.pc as usize] as u16;
-    let lo = state.memory[(state.pc + 1) as usize] as u16;
-    (hi << 8) | lo
+/// Finishes the active trace, if any, and reports how much was logged.
+fn stop_trace(chip8: &mut Chip8VM) -> bool {
+    let Some(tracer) = chip8.set_tracer(None) else {
+        return false;
+    };
+    match tracer.finish() {
+        Ok(records) => println!("Traced {} instructions", records),
+        Err(e) => eprintln!("Trace error: {:?}", e),
+    }
+    true
 }
 
-fn render_screen(
-    rl: &mut RaylibHandle,
-    thread: &RaylibThread,
-    chip8: &Chip8VM,
-    window_dims: (i32, i32, i32),
-) {
-    let (window_width, window_height, _scale) = window_dims;
-    let mut d = rl.begin_drawing(&thread);
-    d.clear_background(Color::BLACK);
-
-    let (screen_width, screen_height, screen_buf) = chip8.get_display_config();
-
-    let x_offset = (window_width - (screen_width as i32) * SCALE) / 2;
-    let y_offset = (window_height - (screen_height as i32) * SCALE) / 2;
-
-    for y in 0..screen_height {
-        for x in 0..screen_width {
-            let idx = x + y * HI_RES_WIDTH;
-
-            if screen_buf[idx] {
-                d.draw_rectangle(
-                    x_offset + (x as i32) * SCALE,
-                    y_offset + (y as i32) * SCALE,
-                    SCALE,
-                    SCALE,
-                    Color::GREEN,
-                );
-            }
-        }
+/// Reports a trace write failure, which made the VM stop tracing.
+fn report_trace_error(chip8: &mut Chip8VM) {
+    if let Some(e) = chip8.take_trace_error() {
+        eprintln!("Trace error, tracing stopped: {:?}", e);
     }
+}
 
-    let screen_rect = Rectangle::new(
-        x_offset as f32,
-        y_offset as f32,
-        (screen_width as i32 * SCALE) as f32,
-        (screen_height as i32 * SCALE) as f32,
+/// Removes the active profiler, if any, and prints its report.
+fn stop_profile(chip8: &mut Chip8VM, debugger: &Debugger) -> bool {
+    let Some(profiler) = chip8.set_profiler(None) else {
+        return false;
+    };
+    debugger.show_profile(
+        &profiler,
+        chip8.get_state(),
+        &chip8.active_extensions(),
+        PROFILE_LINES,
     );
+    true
+}
 
-    d.draw_rectangle_lines_ex(screen_rect, 2.0, Color::GRAY);
+/// Writes the movie of the session recorded so far.
+fn save_movie(chip8: &mut Chip8VM, rom: &[u8], timing: Timing, path: &Path) {
+    let events = chip8.set_input_log(None).unwrap_or_default();
+    let movie = Movie::new(rom, chip8, timing, events);
+    match movie.save(path) {
+        Ok(()) => println!("Recorded {} frames to {}", movie.frames, path.display()),
+        Err(e) => eprintln!("Movie error: {:?}", e),
+    }
+}
+
+fn print_position(chip8: &Chip8VM, debugger: &Debugger) {
+    let state = chip8.get_state();
+    println!(
+        "PC: {}, Opcode: 0x{:04X}  {}",
+        debugger.format_addr(state.pc),
+        chip8.peek_opcode(),
+        debugger.format_instruction(&state.memory, state.pc, &chip8.active_extensions())
+    );
+}
+
+/// The address and instruction about to be executed, e.g. `0x0208 <draw>: DRW V0, V1, 5`.
+fn describe_pc(chip8: &Chip8VM, debugger: &Debugger) -> String {
+    let state = chip8.get_state();
+    format!(
+        "{}: {}",
+        debugger.format_addr(state.pc),
+        debugger.format_instruction(&state.memory, state.pc, &chip8.active_extensions())
+    )
+}
+
+// Save states live next to the ROM, e.g. `pong.ch8` -> `pong.ss0`
+fn state_path(rom_path: &Path, slot: u8) -> PathBuf {
+    rom_path.with_extension(format!("ss{}", slot))
+}
+
+fn save_to_slot(chip8: &Chip8VM, rom_path: &Path, slot: u8) -> Result<()> {
+    let path = state_path(rom_path, slot);
+    fs::write(&path, chip8.save_state())
+        .context(format!("Failed to write save state: {}", path.display()))
+}
+
+fn load_from_slot(chip8: &mut Chip8VM, rom_path: &Path, slot: u8) -> Result<()> {
+    let path = state_path(rom_path, slot);
+    let data = fs::read(&path).context(format!("Failed to read save state: {}", path.display()))?;
+    chip8.load_state(&data)
 }
 
 // The run function now accepts the validated ROM path as an argument.
 fn run(cli: &Cli) -> Result<()> {
     let mut debugger = Debugger::new();
-    let mut paused = cli.debug;
-
-    let keytobtn: HashMap<KeyboardKey, u8> = HashMap::from([
-        (KeyboardKey::KEY_ONE, 0x1),
-        (KeyboardKey::KEY_TWO, 0x2),
-        (KeyboardKey::KEY_THREE, 0x3),
-        (KeyboardKey::KEY_FOUR, 0xC),
-        (KeyboardKey::KEY_Q, 0x4),
-        (KeyboardKey::KEY_W, 0x5),
-        (KeyboardKey::KEY_E, 0x6),
-        (KeyboardKey::KEY_R, 0xD),
-        (KeyboardKey::KEY_A, 0x7),
-        (KeyboardKey::KEY_S, 0x8),
-        (KeyboardKey::KEY_D, 0x9),
-        (KeyboardKey::KEY_F, 0xE),
-        (KeyboardKey::KEY_Z, 0xA),
-        (KeyboardKey::KEY_X, 0x0),
-        (KeyboardKey::KEY_C, 0xB),
-        (KeyboardKey::KEY_V, 0xF),
-    ]);
-    let mut extensions = Vec::new();
-    if cli.enable_schip {
-        extensions.push(Box::new(SuperChip8::new(true)) as Box<dyn Extension>);
+    if let Some(path) = &cli.symbols {
+        debugger.set_symbols(SymbolTable::load(path)?);
     }
+    let mut paused = cli.debug;
+    // History for reverse debugging is only recorded once debugging starts
+    debugger.set_recording(cli.debug);
 
     let mut rom = File::open(&cli.rom_path).context(format!(
         "Failed to open ROM file: {}",
@@ -195,93 +366,229 @@ fn run(cli: &Cli) -> Result<()> {
     rom.read_to_end(&mut buffer)
         .context("Failed to read ROM file content")?;
 
-    let mut chip8 = Chip8VM::new(extensions);
+    let movie = match &cli.replay {
+        Some(path) => {
+            let movie = Movie::load(path)?;
+            movie.check_rom(&buffer)?;
+            Some(movie)
+        }
+        None => None,
+    };
+    let enabled = match &movie {
+        Some(movie) => movie.extensions,
+        None => ActiveExtensions {
+            schip: cli.enable_schip,
+            xochip: cli.enable_xochip,
+        },
+    };
+
+    let quirks = match (&movie, cli.quirks) {
+        (Some(movie), _) => movie.quirks,
+        (None, Some(quirks)) => quirks,
+        (None, None) if enabled.xochip => Quirks::xochip(),
+        (None, None) if enabled.schip => Quirks::schip(),
+        (None, None) => Quirks::vip(),
+    };
+
+    let mut chip8 = Chip8VM::new(enabled.create(), quirks);
+    if let Some(seed) = movie.as_ref().map(|movie| movie.seed).or(cli.seed) {
+        chip8.set_seed(seed);
+    }
+    if cli.record.is_some() {
+        chip8.set_input_log(Some(Vec::new()));
+    }
+    let timing = match &movie {
+        Some(movie) => movie.timing,
+        None if cli.vip_timing => Timing::Vip,
+        None => Timing::Fixed {
+            instructions_per_second: cli.ips,
+        },
+    };
+    let mut replay = movie.as_ref().map(Replay::new);
 
     chip8
         .load(&buffer)
         .context("Failed to load ROM data into VM memory")?;
 
-    let window_width = (HI_RES_WIDTH as i32) * SCALE;
-    let window_height = (HI_RES_HEIGHT as i32) * SCALE;
-
-    let (mut rl, thread) = raylib::init()
-        .size(window_width, window_height)
-        .title("Chip 8 EMU(Extensible)")
-        .build();
-
-    rl.set_target_fps(120);
+    if let Some(path) = &cli.trace {
+        let filter = TraceFilter {
+            ranges: cli.trace_pc.clone(),
+            opcodes: cli.trace_opcode.clone(),
+        };
+        let tracer = Tracer::create(path, cli.trace_format, filter, chip8.active_extensions())?;
+        chip8.set_tracer(Some(tracer));
+    }
+    if cli.profile {
+        chip8.set_profiler(Some(Profiler::new()));
+    }
 
-    let audio = raylib::core::audio::RaylibAudio::init_audio_device()?;
-    let beep = audio.new_sound("resources/beep.mp3")?;
+    let audio = RaylibAudio::init_audio_device()?;
+    let tone = Tone {
+        frequency: cli.tone_frequency,
+        volume: cli.volume,
+        waveform: cli.waveform,
+    };
+    let mut frontend = RaylibFrontend::new(&audio, tone);
+
+    let mut save_slot = 0;
+    // Rewinding, loading states and debugging would desynchronize the movie's frame numbers
+    // from the machine
+    let in_movie = cli.record.is_some() || replay.is_some();
+    let rewind_frames = if in_movie { 0 } else { cli.rewind_frames };
+    let mut rewind = RewindBuffer::new(rewind_frames, REWIND_MAX_BYTES);
+    let mut resuming = false;
+    let mut clock = Clock::new(timing);
+    clock.start_frame();
+    let mut pacer = FramePacer::new();
 
     // Main emulation loop
-    while !rl.window_should_close() {
-        if rl.is_key_pressed(KeyboardKey::KEY_F1) {
-            paused = !paused;
-            if paused {
-                println!("Debugger paused. Type 'help' for commands.");
+    while !frontend.should_close() {
+        if frontend.is_hotkey_pressed(KeyboardKey::KEY_F1) {
+            if in_movie {
+                println!("The debugger is disabled while recording or replaying a movie");
+            } else {
+                paused = !paused;
+                if paused {
+                    println!("Debugger paused. Type 'help' for commands.");
+                }
             }
         }
 
-        if paused {
-            let state = chip8.get_state();
-            if debugger.should_break(state.pc) {
-                println!("Breakpoint hit at 0x{:04X}", state.pc);
+        // Save states: F5 saves, F9 loads, F6/F7 select the slot
+        if frontend.is_hotkey_pressed(KeyboardKey::KEY_F6) {
+            save_slot = (save_slot + SAVE_SLOTS - 1) % SAVE_SLOTS;
+            println!("Save slot {}", save_slot);
+        }
+        if frontend.is_hotkey_pressed(KeyboardKey::KEY_F7) {
+            save_slot = (save_slot + 1) % SAVE_SLOTS;
+            println!("Save slot {}", save_slot);
+        }
+        if frontend.is_hotkey_pressed(KeyboardKey::KEY_F5) {
+            match save_to_slot(&chip8, &cli.rom_path, save_slot) {
+                Ok(()) => println!("Saved state to slot {}", save_slot),
+                Err(e) => eprintln!("Save state error: {:?}", e),
+            }
+        }
+        if frontend.is_hotkey_pressed(KeyboardKey::KEY_F9) {
+            if in_movie {
+                println!("Loading states is disabled while recording or replaying a movie");
+            } else {
+                match load_from_slot(&mut chip8, &cli.rom_path, save_slot) {
+                    Ok(()) => {
+                        rewind.clear();
+                        debugger.clear_history();
+                        println!("Loaded state from slot {}", save_slot);
+                    }
+                    Err(e) => eprintln!("Load state error: {:?}", e),
+                }
             }
+        }
+
+        // The sound sustains as long as the sound timer is non-zero
+        frontend.update_audio(if paused {
+            Sound::Silent
+        } else {
+            Sound::of(&chip8)
+        });
 
-            run_debugger_loop(
-                &mut rl,
-                &thread,
+        if paused {
+            debugger.set_recording(true);
+            frontend.pause_audio();
+            let quit = run_debugger_loop(
+                &mut frontend,
                 &mut chip8,
                 &mut debugger,
+                &mut clock,
                 &mut paused,
-                &beep,
-                &keytobtn,
-                (window_width, window_height, SCALE),
             )?;
+            if quit {
+                break;
+            }
+            frontend.resume_audio();
+            // Don't stop again on the breakpoint we are resuming from
+            resuming = true;
             continue;
         }
 
-        let state = chip8.get_state();
-        if debugger.should_break(state.pc) {
-            paused = true;
-            println!("Breakpoint hit at 0x{:04X}", state.pc);
+        // Rewind: step back one recorded frame per rendered frame while Backspace is held
+        if rewind.capacity() > 0 && frontend.is_hotkey_down(KeyboardKey::KEY_BACKSPACE) {
+            if let Some(snapshot) = rewind.pop() {
+                chip8.restore(snapshot);
+                debugger.clear_history();
+            }
+            frontend.present(&chip8);
             continue;
         }
 
-        // Input handling
-        for (keyboard_key, chip8_key) in &keytobtn {
-            let key_index = *chip8_key as usize;
+        // Emulate the 60Hz frames due since the last rendered one, whatever the frame rate
+        for _ in 0..pacer.advance(frontend.frame_time()) {
+            // Input handling, from the movie until it ends
+            if let Some(movie) = &mut replay {
+                if movie.is_finished(&chip8) {
+                    println!("Replay finished after {} frames", chip8.frame());
+                    replay = None;
+                } else if let Err(e) = movie.apply(&mut chip8) {
+                    eprintln!("Replay error: {}", e);
+                }
+            }
+            if replay.is_none() {
+                if let Err(e) = frontend.update_keypad(&mut chip8) {
+                    eprintln!("Input error: {}", e);
+                }
+            }
 
-            if rl.is_key_down(*keyboard_key) {
-                if let Err(e) = chip8.keypress(key_index, true) {
-                    eprintln!("Input error (down): {}", e);
+            // VM Ticks, breakpoints and watchpoints being checked around every instruction.
+            // A frame cut short by one resumes where it stopped.
+            while clock.can_run() {
+                let pc = chip8.get_state().pc;
+                if !resuming {
+                    if debugger.should_break(chip8.get_state()) {
+                        paused = true;
+                        debugger.clear_target();
+                        println!("Breakpoint hit at {}", describe_pc(&chip8, &debugger));
+                        break;
+                    }
+                    if debugger.target_reached(chip8.get_state()) {
+                        paused = true;
+                        println!("Stopped at {}", describe_pc(&chip8, &debugger));
+                        break;
+                    }
                 }
-            } else if rl.is_key_up(*keyboard_key) {
-                if let Err(e) = chip8.keypress(key_index, false) {
-                    eprintln!("Input error (up): {}", e);
+                resuming = false;
+
+                let instruction = chip8.peek_instruction();
+                debugger.execute(&mut chip8)?;
+                clock.charge(&chip8, instruction);
+                if debugger.has_watchpoints() {
+                    if let Some(hit) =
+                        debugger.check_watchpoints(chip8.get_state(), chip8.last_accesses())
+                    {
+                        paused = true;
+                        debugger.clear_target();
+                        println!("{} at {}", hit, debugger.format_addr(pc));
+                        break;
+                    }
                 }
             }
-        }
+            if paused {
+                break;
+            }
 
-        // VM Ticks
-        for _ in 0..TICK_PER_FRAME {
-            chip8.tick()?;
-        }
+            // Timer update
+            chip8.tick_timers();
+            clock.start_frame();
 
-        // Timer update
-        let (_, st) = chip8.tick_timers();
-        if st == 1 {
-            beep.play();
+            rewind.push(chip8.snapshot());
         }
+        report_trace_error(&mut chip8);
 
-        render_screen(
-            &mut rl,
-            &thread,
-            &chip8,
-            (window_width, window_height, SCALE),
-        );
+        frontend.present(&chip8);
     }
 
+    stop_trace(&mut chip8);
+    stop_profile(&mut chip8, &debugger);
+    if let Some(path) = &cli.record {
+        save_movie(&mut chip8, &buffer, timing, path);
+    }
     Ok(())
 }
@@ -0,0 +1,259 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::{fmt, fs, path::Path};
+
+use crate::{extensions::ActiveExtensions, quirks::Quirks, timing::Timing, vm::Chip8VM};
+
+const HEADER: &str = "# CHIP-8 movie";
+
+/// A keypad change, applied at the start of `frame`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.pressed { "down" } else { "up" };
+        write!(f, "{} {:X} {}", self.frame, self.key, state)
+    }
+}
+
+/// Parses an input script made of `<frame> <key> <down|up>` lines, keys being hex digits.
+/// Blank lines and `#` comments are ignored.
+pub fn parse_input_script(script: &str) -> Result<Vec<InputEvent>> {
+    let mut events = Vec::new();
+
+    for (line_no, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        events.push(parse_event(line, line_no + 1)?);
+    }
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+fn parse_event(line: &str, line_no: usize) -> Result<InputEvent> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let [frame, key, state] = parts[..] else {
+        bail!("Line {}: expected `<frame> <key> <down|up>`", line_no);
+    };
+    let frame = frame
+        .parse()
+        .with_context(|| format!("Line {}: invalid frame number", line_no))?;
+    let key = u8::from_str_radix(key.trim_start_matches("0x"), 16)
+        .with_context(|| format!("Line {}: invalid key", line_no))?;
+    let pressed = match state {
+        "down" => true,
+        "up" => false,
+        _ => bail!("Line {}: key state must be `down` or `up`", line_no),
+    };
+    Ok(InputEvent {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+/// FNV-1a hash identifying the ROM a movie was recorded with.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// A recorded play session: everything needed to replay it frame by frame on a fresh VM.
+///
+/// Movies are text files, a few `name value` header lines followed by the input events in
+/// the input script format:
+///
+/// ```text
+/// # CHIP-8 movie
+/// rom 3f0a5c2e9b1d4478
+/// seed 42
+/// extensions schip
+/// quirks jump_uses_vx clip_sprites
+/// timing 600
+/// frames 1800
+/// 120 5 down
+/// 131 5 up
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub extensions: ActiveExtensions,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    /// Length of the recording, which may go on after the last event
+    pub frames: u64,
+    pub events: Vec<InputEvent>,
+}
+
+impl Movie {
+    /// Builds a movie of `rom` running on `chip8` up to now, from the events it logged since
+    /// it was created (see `Chip8VM::set_input_log`).
+    pub fn new(rom: &[u8], chip8: &Chip8VM, timing: Timing, events: Vec<InputEvent>) -> Self {
+        Movie {
+            rom_hash: rom_hash(rom),
+            seed: chip8.seed(),
+            extensions: chip8.active_extensions(),
+            quirks: chip8.quirks(),
+            timing,
+            frames: chip8.frame(),
+            events,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .context(format!("Failed to read movie: {}", path.display()))?;
+        Self::parse(&text).context(format!("Invalid movie: {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_string())
+            .context(format!("Failed to write movie: {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut extensions = ActiveExtensions::default();
+        let mut quirks = None;
+        let mut timing = None;
+        let mut frames = None;
+        let mut events = Vec::new();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let line_no = line_no + 1;
+            let invalid = |what: &str| format!("Line {}: invalid {}", line_no, what);
+
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match name {
+                "rom" => {
+                    rom_hash =
+                        Some(u64::from_str_radix(value, 16).with_context(|| invalid("ROM hash"))?)
+                }
+                "seed" => seed = Some(value.parse().with_context(|| invalid("seed"))?),
+                "extensions" => {
+                    for ext in value.split_whitespace() {
+                        match ext {
+                            "schip" => extensions.schip = true,
+                            "xochip" => extensions.xochip = true,
+                            _ => bail!("Line {}: unknown extension: {}", line_no, ext),
+                        }
+                    }
+                }
+                "quirks" => {
+                    quirks = Some(
+                        Quirks::from_flags(value.split_whitespace())
+                            .map_err(|e| anyhow!("Line {}: {}", line_no, e))?,
+                    )
+                }
+                "timing" => timing = Some(value.parse().with_context(|| invalid("timing"))?),
+                "frames" => frames = Some(value.parse().with_context(|| invalid("frame count"))?),
+                _ => events.push(parse_event(line, line_no)?),
+            }
+        }
+
+        events.sort_by_key(|event| event.frame);
+        Ok(Movie {
+            rom_hash: rom_hash.context("Missing ROM hash")?,
+            seed: seed.context("Missing seed")?,
+            extensions,
+            quirks: quirks.context("Missing quirks")?,
+            timing: timing.context("Missing timing")?,
+            frames: frames.context("Missing frame count")?,
+            events,
+        })
+    }
+
+    /// Fails if `rom` is not the ROM the movie was recorded with.
+    pub fn check_rom(&self, rom: &[u8]) -> Result<()> {
+        let hash = rom_hash(rom);
+        if hash != self.rom_hash {
+            bail!(
+                "The movie was recorded with another ROM (hash {:016x}, this one is {:016x})",
+                self.rom_hash,
+                hash
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+
+        write!(f, "extensions")?;
+        if self.extensions.schip {
+            write!(f, " schip")?;
+        }
+        if self.extensions.xochip {
+            write!(f, " xochip")?;
+        }
+        writeln!(f)?;
+
+        write!(f, "quirks")?;
+        for (name, _) in self.quirks.flags().iter().filter(|(_, on)| *on) {
+            write!(f, " {}", name)?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "timing {}", self.timing)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Feeds the events of a movie back into a VM as its frames go by.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    events: Vec<InputEvent>,
+    next: usize,
+    frames: u64,
+}
+
+impl Replay {
+    pub fn new(movie: &Movie) -> Self {
+        Replay {
+            events: movie.events.clone(),
+            next: 0,
+            frames: movie.frames,
+        }
+    }
+
+    /// Presses and releases the keys due by the current frame of `chip8`. Call it at the start
+    /// of every frame, before running its instructions.
+    pub fn apply(&mut self, chip8: &mut Chip8VM) -> Result<()> {
+        while let Some(event) = self.events.get(self.next) {
+            if event.frame > chip8.frame() {
+                break;
+            }
+            chip8.keypress(event.key as usize, event.pressed)?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    /// Whether `chip8` has played every frame of the movie.
+    pub fn is_finished(&self, chip8: &Chip8VM) -> bool {
+        chip8.frame() >= self.frames
+    }
+}
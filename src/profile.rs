@@ -0,0 +1,161 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{disasm, extensions::ActiveExtensions, instruction::Instruction, symbols::SymbolTable};
+
+/// Call statistics of a subroutine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    /// Instructions executed between entering the subroutine and returning from it, the
+    /// subroutines it calls included
+    pub inclusive: u64,
+}
+
+/// Counts executed instructions per PC and per opcode class, and subroutine calls derived
+/// from CALL/RET. Installed with `Chip8VM::set_profiler`.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    instructions: u64,
+    pcs: HashMap<u16, u64>,
+    classes: HashMap<&'static str, u64>,
+    subroutines: HashMap<u16, SubroutineStats>,
+    // Subroutines being run, with the instruction count when they were entered
+    calls: Vec<(u16, u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `instruction`, executed at `pc`.
+    pub fn record(&mut self, pc: u16, instruction: Instruction) {
+        self.instructions += 1;
+        *self.pcs.entry(pc).or_default() += 1;
+        *self.classes.entry(instruction.pattern()).or_default() += 1;
+
+        match instruction {
+            Instruction::Call(addr) => {
+                self.subroutines.entry(addr).or_default().calls += 1;
+                self.calls.push((addr, self.instructions));
+            }
+            // Returns from calls made before profiling started have nothing to match
+            Instruction::Return => {
+                if let Some((addr, entered)) = self.calls.pop() {
+                    if !self.is_running(addr) {
+                        self.subroutines.entry(addr).or_default().inclusive +=
+                            self.instructions - entered;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Execution count of every executed address, hottest first.
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut pcs: Vec<(u16, u64)> = self.pcs.iter().map(|(&pc, &count)| (pc, count)).collect();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        pcs
+    }
+
+    /// Execution count of every opcode class (see `Instruction::pattern`), hottest first.
+    pub fn classes(&self) -> Vec<(&'static str, u64)> {
+        let mut classes: Vec<(&'static str, u64)> = self
+            .classes
+            .iter()
+            .map(|(&class, &count)| (class, count))
+            .collect();
+        classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        classes
+    }
+
+    /// Statistics of every called subroutine, by decreasing inclusive time. Subroutines that
+    /// have not returned yet are timed up to now.
+    pub fn subroutines(&self) -> Vec<(u16, SubroutineStats)> {
+        let mut subroutines = self.subroutines.clone();
+        for (idx, &(addr, entered)) in self.calls.iter().enumerate() {
+            // Recursive calls are only timed by their outermost frame
+            if !self.calls[..idx].iter().any(|&(outer, _)| outer == addr) {
+                subroutines.entry(addr).or_default().inclusive += self.instructions - entered;
+            }
+        }
+
+        let mut subroutines: Vec<(u16, SubroutineStats)> = subroutines.into_iter().collect();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+        subroutines
+    }
+
+    /// Formats the hottest `limit` addresses, annotated with the disassembly of `memory`, and
+    /// subroutines, followed by every opcode class.
+    pub fn report(
+        &self,
+        memory: &[u8],
+        ext: &ActiveExtensions,
+        symbols: &SymbolTable,
+        limit: usize,
+    ) -> String {
+        let percent = |count: u64| count as f64 * 100.0 / self.instructions.max(1) as f64;
+        let describe = |addr: u16| match symbols.describe(addr) {
+            Some(label) => format!("0x{:04X} <{}>", addr, label),
+            None => format!("0x{:04X}", addr),
+        };
+
+        let mut report = String::new();
+        let _ = writeln!(report, "Profiled {} instructions", self.instructions);
+
+        let _ = writeln!(report, "\nHot spots:");
+        let _ = writeln!(
+            report,
+            "{:>10} {:>7}  {:<24} instruction",
+            "count", "%", "address"
+        );
+        for (pc, count) in self.hot_spots().into_iter().take(limit) {
+            let (mnemonic, _) = disasm::disassemble_at(memory, pc, ext);
+            let _ = writeln!(
+                report,
+                "{:>10} {:>6.2}%  {:<24} {}",
+                count,
+                percent(count),
+                describe(pc),
+                mnemonic
+            );
+        }
+
+        let subroutines = self.subroutines();
+        if !subroutines.is_empty() {
+            let _ = writeln!(report, "\nSubroutines:");
+            let _ = writeln!(
+                report,
+                "{:>10} {:>10} {:>7}  address",
+                "calls", "inclusive", "%"
+            );
+            for (addr, stats) in subroutines.into_iter().take(limit) {
+                let _ = writeln!(
+                    report,
+                    "{:>10} {:>10} {:>6.2}%  {}",
+                    stats.calls,
+                    stats.inclusive,
+                    percent(stats.inclusive),
+                    describe(addr)
+                );
+            }
+        }
+
+        let _ = writeln!(report, "\nOpcode classes:");
+        let _ = writeln!(report, "{:>10} {:>7}  class", "count", "%");
+        for (class, count) in self.classes() {
+            let _ = writeln!(report, "{:>10} {:>6.2}%  {}", count, percent(count), class);
+        }
+
+        report
+    }
+
+    fn is_running(&self, addr: u16) -> bool {
+        self.calls.iter().any(|&(running, _)| running == addr)
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::{bail, Error, Result};
+use std::str::FromStr;
+
+/// Behavioral differences between CHIP-8 interpreters.
+/// See https://chip8.gulrak.net/#quirks for how each platform behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE: shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65: increment I by X + 1 after the transfer.
+    pub load_store_increments_i: bool,
+    /// BNNN: jump to VX + NNN (X being the high nibble of NNN) instead of V0 + NNN.
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3: reset VF to 0 after the logic operation.
+    pub vf_reset: bool,
+    /// DXYN: clip sprites at the screen edges instead of wrapping them around.
+    pub clip_sprites: bool,
+    /// DXYN: wait for the next display refresh (timer tick) before drawing.
+    pub display_wait: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::vip()
+    }
+}
+
+impl Quirks {
+    /// Original COSMAC VIP interpreter.
+    pub fn vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1, which is also what most CHIP-48 programs expect.
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    /// Every quirk with its field name, in declaration order.
+    pub fn flags(&self) -> [(&'static str, bool); 6] {
+        [
+            ("shift_uses_vy", self.shift_uses_vy),
+            ("load_store_increments_i", self.load_store_increments_i),
+            ("jump_uses_vx", self.jump_uses_vx),
+            ("vf_reset", self.vf_reset),
+            ("clip_sprites", self.clip_sprites),
+            ("display_wait", self.display_wait),
+        ]
+    }
+
+    /// Builds quirks from the names of the enabled ones, as listed by `flags`.
+    pub fn from_flags<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut quirks = Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        };
+        for name in names {
+            let flag = match name {
+                "shift_uses_vy" => &mut quirks.shift_uses_vy,
+                "load_store_increments_i" => &mut quirks.load_store_increments_i,
+                "jump_uses_vx" => &mut quirks.jump_uses_vx,
+                "vf_reset" => &mut quirks.vf_reset,
+                "clip_sprites" => &mut quirks.clip_sprites,
+                "display_wait" => &mut quirks.display_wait,
+                _ => bail!("Unknown quirk: {}", name),
+            };
+            *flag = true;
+        }
+        Ok(quirks)
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = Error;
+
+    /// Parses a preset name: `vip`, `schip` or `xochip`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" => Ok(Quirks::vip()),
+            "schip" => Ok(Quirks::schip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => bail!(
+                "Unknown quirks preset: {} (expected vip, schip or xochip)",
+                name
+            ),
+        }
+    }
+}
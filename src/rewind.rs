@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+
+use crate::vm::Snapshot;
+
+/// Ring buffer of per-frame machine snapshots. Once full, recording a new frame drops the
+/// oldest one, so memory use is bounded by both `capacity` snapshots and `max_bytes`.
+pub struct RewindBuffer {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+    max_bytes: usize,
+    // Total size of the snapshots held
+    bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        RewindBuffer {
+            snapshots: VecDeque::new(),
+            capacity,
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    /// Records a snapshot, evicting the oldest ones to make room for it.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        let size = snapshot.size();
+        if self.capacity == 0 || size > self.max_bytes {
+            return;
+        }
+        while self.snapshots.len() == self.capacity || self.bytes + size > self.max_bytes {
+            self.pop_front();
+        }
+        self.bytes += size;
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Takes the most recent snapshot, stepping one frame back in time.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let snapshot = self.snapshots.pop_back()?;
+        self.bytes -= snapshot.size();
+        Some(snapshot)
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.bytes = 0;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Memory taken by the snapshots held, see `Snapshot::size`.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    fn pop_front(&mut self) {
+        if let Some(snapshot) = self.snapshots.pop_front() {
+            self.bytes -= snapshot.size();
+        }
+    }
+}
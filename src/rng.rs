@@ -0,0 +1,38 @@
+/// Seedable xorshift64* generator used by CXNN. Its whole state is one word, so it is saved
+/// along with the machine and replays give the same numbers every time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    // Never 0, which xorshift would stay stuck on
+    state: u64,
+}
+
+impl Rng {
+    /// Seeds the generator. Any seed is valid, nearby seeds giving unrelated sequences.
+    pub fn new(seed: u64) -> Self {
+        // SplitMix64 scrambles the seed into the initial state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    /// Restores a generator from `state`, as returned by `Rng::state`.
+    pub fn from_state(state: u64) -> Option<Self> {
+        (state != 0).then_some(Rng { state })
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        // The high bits of the product are the best distributed ones
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
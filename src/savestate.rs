@@ -0,0 +1,113 @@
+use anyhow::{bail, Result};
+
+// Save state layout (all integers little-endian):
+//   magic "C8SS", version u16
+//   pc u16, i u16, sp u16, registers [u8; 16], stack [u16; 16]
+//   delay timer u8, sound timer u8, width u16, height u16
+//   rpl flags [u8; 16], audio pattern [u8; 16], pitch u8, RNG state u64
+//   memory (u32 length + bytes), screen (u32 length + bytes)
+//   extension count u16, then per extension: name (u8 length + bytes), data (u32 length + bytes)
+pub const MAGIC: &[u8; 4] = b"C8SS";
+pub const VERSION: u16 = 1;
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut writer = StateWriter::default();
+        writer.bytes(MAGIC);
+        writer.u16(VERSION);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Writes a u32 length prefix followed by the data.
+    pub fn blob(&mut self, data: &[u8]) {
+        self.u32(data.len() as u32);
+        self.bytes(data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates the header and returns a reader positioned right after it.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        let mut reader = StateReader { data, pos: 0 };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            bail!("Not a CHIP-8 save state");
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            bail!("Unsupported save state version: {}", version);
+        }
+        Ok(reader)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        let bytes = self.bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            bail!("Save state is truncated");
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Reads data written by `StateWriter::blob`.
+    pub fn blob(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
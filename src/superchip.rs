@@ -1,10 +1,10 @@
 use anyhow::{bail, Ok, Result};
 
 use crate::{
-    conf::{
-        HI_RES_HEIGHT, HI_RES_WIDTH, LARGE_FONT_BASE_ADDR, RAM_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH,
-    },
+    conf::{HI_RES_HEIGHT, HI_RES_WIDTH, LARGE_FONT_BASE_ADDR, SCREEN_HEIGHT, SCREEN_WIDTH},
     extensions::{Extension, VmContext},
+    instruction::Instruction,
+    vm::AccessKind,
 };
 
 pub struct SuperChip8 {
@@ -12,59 +12,81 @@ pub struct SuperChip8 {
 }
 
 impl SuperChip8 {
+    pub const NAME: &'static str = "Super-CHIP";
+
     pub fn new(active: bool) -> Self {
         SuperChip8 { active }
     }
+}
 
-    /// Implements the S-CHIP DXY0 instruction (Draw 16x16 sprite)
-    fn draw_16x16_sprite(&mut self, ctx: &mut VmContext, x_reg: usize, y_reg: usize) -> Result<()> {
-        const SPRITE_SIZE: usize = 16;
-        ctx.registers[0xF] = 0;
-
-        let x_coord = ctx.registers[x_reg] as usize;
-        let y_coord = ctx.registers[y_reg] as usize;
-
-        let screen_width = *ctx.current_width;
-        let screen_height = *ctx.current_height;
-
-        for row in 0..SPRITE_SIZE {
-            let addr = *ctx.i_register as usize + (row * 2);
-
-            if addr + 1 >= RAM_SIZE {
-                bail!("Memory access out of bounds for 16x16 sprite draw");
+/// Draws a `width`x`height` sprite read from `addr` onto the bitplanes in `plane_mask`, honoring
+/// the sprite clipping quirk. Returns whether any lit pixel was erased.
+pub(crate) fn draw_sprite(
+    ctx: &mut VmContext,
+    (x_coord, y_coord): (usize, usize),
+    addr: usize,
+    (width, height): (usize, usize),
+    plane_mask: u8,
+) -> Result<bool> {
+    let bytes_per_row = width / 8;
+    if addr + height * bytes_per_row > ctx.memory.len() {
+        bail!("Memory access out of bounds for sprite draw");
+    }
+    ctx.record_access(addr, height * bytes_per_row, AccessKind::Read);
+
+    let screen_width = *ctx.current_width;
+    let screen_height = *ctx.current_height;
+    let x_coord = x_coord % screen_width;
+    let y_coord = y_coord % screen_height;
+    let mut collision = false;
+
+    for row in 0..height {
+        for col in 0..width {
+            let byte = ctx.memory[addr + row * bytes_per_row + col / 8];
+            if byte & (0b1000_0000 >> (col % 8)) == 0 {
+                continue;
             }
 
-            let pixels_hi = ctx.memory[addr];
-            let pixels_lo = ctx.memory[addr + 1];
-
-            for col in 0..SPRITE_SIZE {
-                let pixel_bit = if col < 8 {
-                    (pixels_hi & (0b1000_0000 >> col)) != 0
-                } else {
-                    (pixels_lo & (0b1000_0000 >> (col - 8))) != 0
-                };
-
-                if pixel_bit {
-                    let px = (x_coord + col) % screen_width;
-                    let py = (y_coord + col) % screen_height;
-
-                    let idx_in_buffer = px + py * HI_RES_WIDTH;
+            let (px, py) = (x_coord + col, y_coord + row);
+            if ctx.quirks.clip_sprites && (px >= screen_width || py >= screen_height) {
+                continue;
+            }
+            let idx = (px % screen_width) + (py % screen_height) * HI_RES_WIDTH;
 
-                    if ctx.screen[idx_in_buffer] {
-                        ctx.registers[0xF] = 1;
-                    }
-                    ctx.screen[idx_in_buffer] ^= true;
-                }
+            if ctx.screen[idx] & plane_mask != 0 {
+                collision = true;
             }
+            ctx.screen[idx] ^= plane_mask;
         }
+    }
+
+    Ok(collision)
+}
 
-        Ok(())
+/// Scrolls the bitplanes in `plane_mask` by (`dx`, `dy`) pixels of the current resolution,
+/// filling the uncovered area with blank pixels.
+pub(crate) fn scroll(ctx: &mut VmContext, dx: isize, dy: isize, plane_mask: u8) {
+    let width = *ctx.current_width as isize;
+    let height = *ctx.current_height as isize;
+    let old_screen = *ctx.screen;
+
+    for y in 0..height {
+        for x in 0..width {
+            let (src_x, src_y) = (x - dx, y - dy);
+            let src = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                old_screen[(src_x + src_y * HI_RES_WIDTH as isize) as usize] & plane_mask
+            } else {
+                0
+            };
+            let idx = (x + y * HI_RES_WIDTH as isize) as usize;
+            ctx.screen[idx] = (ctx.screen[idx] & !plane_mask) | src;
+        }
     }
 }
 
 impl Extension for SuperChip8 {
     fn name(&self) -> &'static str {
-        "Super-CHIP"
+        Self::NAME
     }
 
     fn is_active(&self) -> bool {
@@ -75,102 +97,64 @@ impl Extension for SuperChip8 {
         // NoOp
     }
 
-    fn handle_instruction(&mut self, ctx: &mut VmContext, opcode: u16) -> Result<bool> {
+    fn handle_instruction(
+        &mut self,
+        ctx: &mut VmContext,
+        instruction: Instruction,
+    ) -> Result<bool> {
         if !self.active {
             return Ok(false);
         }
 
-        let d1 = (opcode & 0xF000) >> 12;
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let n = (opcode & 0x000F) as u8;
-
-        match (d1, x, y, n) {
+        match instruction {
             // 00FD: Exit interpreter
-            (0, 0, 0xF, 0xD) => {
+            Instruction::Exit => {
                 bail!("S-CHIP Exit instruction (00FD) encountered.");
             }
 
             // 00FE: Disable extended screen (64x32 mode)
-            (0, 0, 0xF, 0xE) => {
+            Instruction::LowRes => {
                 *ctx.current_width = SCREEN_WIDTH;
                 *ctx.current_height = SCREEN_HEIGHT;
                 Ok(true)
             }
             // 00FF: Enable extended screen (128x64 mode)
-            (0, 0, 0xF, 0xF) => {
+            Instruction::HighRes => {
                 *ctx.current_width = HI_RES_WIDTH;
                 *ctx.current_height = HI_RES_HEIGHT;
                 Ok(true)
             }
-            // 0DXY0:
-            (0xD, _, _, 0) => {
-                self.draw_16x16_sprite(ctx, x, y)?;
+            // DXY0: Draw 16x16 sprite
+            Instruction::Draw { x, y, n: 0 } => {
+                let coords = (ctx.registers[x] as usize, ctx.registers[y] as usize);
+                let addr = *ctx.i_register as usize;
+                let collision = draw_sprite(ctx, coords, addr, (16, 16), 1)?;
+                ctx.registers[0xF] = collision as u8;
                 Ok(true)
             }
-            // TODO:  see https://chip-8.github.io/extensions/#super-chip-10
             // 00CN: scroll down n
-            (0, 0, 0xC, _) => {
-                let n = n as usize;
-                if n >= SCREEN_HEIGHT {
-                    ctx.screen.fill(false);
-                    return Ok(true);
-                }
-
-                let mut new_screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-
-                for row in 0..(SCREEN_HEIGHT - n) {
-                    let src_start = row * SCREEN_WIDTH;
-                    let dst_start = (row + n) * SCREEN_WIDTH;
-
-                    new_screen[dst_start..dst_start + SCREEN_WIDTH]
-                        .copy_from_slice(&ctx.screen[src_start..src_start + SCREEN_WIDTH]);
-                }
-                ctx.screen.copy_from_slice(&new_screen);
+            Instruction::ScrollDown(n) => {
+                scroll(ctx, 0, n as isize, 1);
                 Ok(true)
             }
             // 00FB: scroll right 4 pixels
-            (0, 0, 0xF, 0xB) => {
-                const SHIFT: usize = 4;
-                let mut new_screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-
-                for row in 0..SCREEN_HEIGHT {
-                    let src_row_start = row * SCREEN_WIDTH;
-                    let dst_row_start = (row + 4) * SCREEN_HEIGHT;
-
-                    for col in 0..(SCREEN_WIDTH - SHIFT) {
-                        let src = src_row_start + col;
-                        let dst = dst_row_start + col + SHIFT;
-                        new_screen[dst] = ctx.screen[src];
-                    }
-                }
+            Instruction::ScrollRight => {
+                scroll(ctx, 4, 0, 1);
                 Ok(true)
             }
             // 00FC: scroll left 4 pixels
-            (0, 0, 0xF, 0xC) => {
-                const SHIFT: usize = 4;
-                let mut new_screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-
-                for row in 0..SCREEN_HEIGHT {
-                    let src_row_start = row * SCREEN_WIDTH;
-                    let dst_row_start = src_row_start;
-
-                    for col in 0..(SCREEN_WIDTH + SHIFT) {
-                        let src = src_row_start + col;
-                        let dst = dst_row_start + col - SHIFT;
-                        new_screen[dst] = ctx.screen[src];
-                    }
-                }
+            Instruction::ScrollLeft => {
+                scroll(ctx, -4, 0, 1);
                 Ok(true)
             }
             // FX30: I = bighex based on VX
-            (0xF, _, 3, 0) => {
+            Instruction::LoadBigFont(x) => {
                 let c = ctx.registers[x] as u16;
                 *ctx.i_register = LARGE_FONT_BASE_ADDR + c * 10;
                 Ok(true)
             }
             // FX75:
-            (0xF, _, 7, 5) => {
+            Instruction::SaveFlags(x) => {
                 for i in 0..=x {
                     ctx.rpl_flags[i] = ctx.registers[i];
                 }
@@ -179,7 +163,7 @@ impl Extension for SuperChip8 {
             }
 
             // FX85:
-            (0xF, _, 8, 5) => {
+            Instruction::LoadFlags(x) => {
                 for i in 0..=x {
                     ctx.registers[i] = ctx.rpl_flags[i];
                 }
@@ -0,0 +1,130 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    ops::RangeBounds,
+    path::Path,
+};
+
+/// Label names for ROM addresses, used by the debugger to show and accept labels instead of
+/// raw addresses.
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    // The first name defined for an address is the one displayed
+    names: BTreeMap<u16, String>,
+    addrs: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .context(format!("Failed to read symbol file: {}", path.display()))?;
+        Self::parse(&text).context(format!("Invalid symbol file: {}", path.display()))
+    }
+
+    /// Parses a symbol file. Each line is either `addr name`, the address being hex with an
+    /// optional `0x` prefix (e.g. `2A4 draw_player`), or an Octo-style definition
+    /// `: name addr` / `:const name addr` with an Octo number (decimal, `0x` hex or `0b`
+    /// binary). Blank lines and lines starting with `#` or `;` are ignored.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut symbols = SymbolTable::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let (name, addr) = match parts[..] {
+                [":" | ":const", name, addr] => (name, parse_octo_number(addr)),
+                [addr, name] => (name, parse_hex(addr)),
+                _ => bail!("line {}: expected `addr name` or `: name addr`", idx + 1),
+            };
+            let addr = addr.map_err(|e| anyhow!("line {}: {}", idx + 1, e))?;
+            symbols.insert(name, addr);
+        }
+
+        Ok(symbols)
+    }
+
+    /// Builds a table from the labels of an `Assembly`.
+    pub fn from_labels(labels: &[(String, u16)]) -> Self {
+        let mut symbols = SymbolTable::new();
+        for (name, addr) in labels {
+            symbols.insert(name, *addr);
+        }
+        symbols
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addrs.insert(name.to_string(), addr);
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    /// Address of the label `name`.
+    pub fn resolve(&self, name: &str) -> Option<u16> {
+        self.addrs.get(name).copied()
+    }
+
+    /// Label defined exactly at `addr`.
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// Labels defined in `range`, in address order.
+    pub fn names_in(&self, range: impl RangeBounds<u16>) -> impl Iterator<Item = (u16, &str)> {
+        self.names
+            .range(range)
+            .map(|(addr, name)| (*addr, name.as_str()))
+    }
+
+    /// Describes `addr` relative to the closest label at or before it, e.g. `draw+0x4`.
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        let (label_addr, name) = self.names.range(..=addr).next_back()?;
+        match addr - label_addr {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+0x{:X}", name, offset)),
+        }
+    }
+}
+
+/// Writes the table in the `addr name` format, in address order.
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut symbols: Vec<(&u16, &String)> =
+            self.addrs.iter().map(|(name, addr)| (addr, name)).collect();
+        symbols.sort();
+        for (addr, name) in symbols {
+            writeln!(f, "0x{:04X} {}", addr, name)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).context(format!("Invalid address: {}", s))
+}
+
+fn parse_octo_number(s: &str) -> Result<u16> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        u16::from_str_radix(bin, 2)
+    } else {
+        s.parse()
+    };
+    parsed.context(format!("Invalid address: {}", s))
+}
@@ -0,0 +1,172 @@
+use anyhow::{bail, Context, Error, Result};
+use std::{fmt, str::FromStr};
+
+use crate::{
+    conf::{DEFAULT_IPS, TIMER_HZ},
+    debugger::Debugger,
+    instruction::Instruction,
+    vm::Chip8VM,
+};
+
+// Length of a timer frame in microseconds, the unit of the VIP cost table
+const FRAME_US: i64 = 1_000_000 / TIMER_HZ as i64;
+
+// Frames emulated at most per rendered frame, the rest of a longer stall being dropped
+const MAX_CATCH_UP: u32 = 4;
+
+/// How fast the CPU runs relative to the 60 Hz timer and display clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time, this many running per second
+    Fixed { instructions_per_second: u32 },
+    /// Every instruction takes as long as on the COSMAC VIP's interpreter
+    Vip,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Fixed {
+            instructions_per_second: DEFAULT_IPS,
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = Error;
+
+    /// Parses `vip` or an instruction rate per second.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("vip") {
+            return Ok(Timing::Vip);
+        }
+        let instructions_per_second: u32 = s
+            .parse()
+            .context(format!("Invalid timing: {} (expected vip or a rate)", s))?;
+        if instructions_per_second == 0 {
+            bail!("The instruction rate must be positive");
+        }
+        Ok(Timing::Fixed {
+            instructions_per_second,
+        })
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timing::Fixed {
+                instructions_per_second,
+            } => write!(f, "{}", instructions_per_second),
+            Timing::Vip => write!(f, "vip"),
+        }
+    }
+}
+
+/// Approximate execution time of `instruction` on the COSMAC VIP in microseconds, fetch and
+/// decode included. Opcodes the VIP does not have cost as much as an ALU operation.
+pub fn vip_cost(instruction: Instruction) -> i64 {
+    use Instruction::*;
+
+    match instruction {
+        ClearScreen => 109,
+        Return | Jump(_) | Call(_) | JumpOffset { .. } => 105,
+        SkipEqImm { .. } | SkipNeImm { .. } | LoadI(_) => 55,
+        SkipEqReg { .. } | SkipNeReg { .. } | SkipKey(_) | SkipNotKey(_) => 73,
+        LoadImm { .. } => 27,
+        AddImm { .. } | LoadDelay(_) | SetDelay(_) | SetSound(_) => 45,
+        Random { .. } => 164,
+        // Waiting for the display interrupt beforehand is the `display_wait` quirk
+        Draw { n, .. } => 340 + 210 * n.max(1) as i64,
+        AddI(_) => 86,
+        LoadFont(_) => 91,
+        Bcd(_) => 927,
+        Store(x) | Load(x) => 130 + 73 * (x as i64 + 1),
+        _ => 200,
+    }
+}
+
+/// Spreads the instructions of a `Timing` over 60 Hz frames. Each frame grants a budget,
+/// executed instructions are charged against it, and whatever is left over or overspent
+/// carries to the next frame so the long-run rate is exact.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    timing: Timing,
+    // In 1/60ths of an instruction for fixed timing, microseconds for VIP timing
+    budget: i64,
+}
+
+impl Clock {
+    pub fn new(timing: Timing) -> Self {
+        Clock { timing, budget: 0 }
+    }
+
+    /// Grants the budget of a new frame.
+    pub fn start_frame(&mut self) {
+        self.budget += match self.timing {
+            Timing::Fixed {
+                instructions_per_second,
+            } => instructions_per_second as i64,
+            Timing::Vip => FRAME_US,
+        };
+    }
+
+    /// Whether the frame has time left for another instruction.
+    pub fn can_run(&self) -> bool {
+        self.budget > 0
+    }
+
+    /// Charges `instruction`, just executed by `chip8`. A draw held back by `display_wait`
+    /// uses up the rest of the frame instead.
+    pub fn charge(&mut self, chip8: &Chip8VM, instruction: Instruction) {
+        if chip8.waiting_for_display() {
+            self.budget = self.budget.min(0);
+            return;
+        }
+        self.budget -= match self.timing {
+            Timing::Fixed { .. } => TIMER_HZ as i64,
+            Timing::Vip => vip_cost(instruction),
+        };
+    }
+
+    /// Executes one instruction through `debugger` and charges it, ticking the timers and
+    /// starting the next frame once this one is spent. Single-stepping thus advances the
+    /// timers at the same rate as running freely.
+    pub fn step(&mut self, chip8: &mut Chip8VM, debugger: &mut Debugger) -> Result<()> {
+        let instruction = chip8.peek_instruction();
+        debugger.execute(chip8)?;
+        self.charge(chip8, instruction);
+        if !self.can_run() {
+            chip8.tick_timers();
+            self.start_frame();
+        }
+        Ok(())
+    }
+}
+
+/// Turns real time into the number of 60 Hz frames to emulate, so the emulation speed does
+/// not depend on how fast the frontend renders.
+#[derive(Debug, Clone, Default)]
+pub struct FramePacer {
+    // Real time not emulated yet, in seconds
+    pending: f64,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `seconds` of real time and returns how many frames are now due.
+    pub fn advance(&mut self, seconds: f32) -> u32 {
+        let frame = 1.0 / TIMER_HZ as f64;
+        self.pending += seconds as f64;
+
+        let due = (self.pending / frame) as u32;
+        self.pending -= due as f64 * frame;
+        if due > MAX_CATCH_UP {
+            self.pending = 0.0;
+            return MAX_CATCH_UP;
+        }
+        due
+    }
+}
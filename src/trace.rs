@@ -0,0 +1,313 @@
+use anyhow::{bail, Context, Error, Result};
+use std::{
+    fmt::{self, Write as _},
+    fs::File,
+    io::{BufWriter, ErrorKind, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    conf::REGISTER_COUNT,
+    disasm,
+    extensions::ActiveExtensions,
+    instruction::{Instruction, OpcodePattern},
+    vm::CpuRegisters,
+};
+
+const MAGIC: &[u8; 4] = b"C8TR";
+const VERSION: u8 = 1;
+
+// Bits of a binary record's change mask, V0-VF using bits 0-15
+const I_CHANGED: u32 = 1 << 16;
+const SP_CHANGED: u32 = 1 << 17;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per instruction, easy to diff
+    #[default]
+    Text,
+    /// Variable-length records, see `dump_binary` to turn them into text
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Ok(TraceFormat::Text),
+            "binary" | "bin" => Ok(TraceFormat::Binary),
+            _ => bail!("Unknown trace format: {} (expected text or binary)", name),
+        }
+    }
+}
+
+/// Which instructions get traced. Empty lists match everything.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub ranges: Vec<RangeInclusive<u16>>,
+    pub opcodes: Vec<OpcodePattern>,
+}
+
+impl TraceFilter {
+    /// Parses a hex PC range `start-end` (inclusive) or a single address.
+    pub fn parse_range(s: &str) -> Result<RangeInclusive<u16>> {
+        let parse = |addr: &str| {
+            u16::from_str_radix(addr.trim().trim_start_matches("0x"), 16)
+                .context(format!("Invalid address range: {}", s))
+        };
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if start > end {
+            bail!("Empty address range: {}", s);
+        }
+        Ok(start..=end)
+    }
+
+    pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&pc)))
+            && (self.opcodes.is_empty()
+                || self.opcodes.iter().any(|pattern| pattern.matches(opcode)))
+    }
+}
+
+/// A register changed by an instruction, with its new value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    V(usize, u8),
+    I(u16),
+    Sp(u16),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Change::V(x, value) => write!(f, "V{:X}={:02X}", x, value),
+            Change::I(value) => write!(f, "I={:04X}", value),
+            Change::Sp(value) => write!(f, "SP={}", value),
+        }
+    }
+}
+
+/// One executed instruction and the registers it changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u16,
+    /// Operand word of XO-CHIP's 4-byte `F000 NNNN`
+    pub next: Option<u16>,
+    pub changes: Vec<Change>,
+}
+
+impl TraceRecord {
+    pub fn new(
+        before: &CpuRegisters,
+        after: &CpuRegisters,
+        opcode: u16,
+        next: Option<u16>,
+    ) -> Self {
+        let mut changes: Vec<Change> = (0..REGISTER_COUNT)
+            .filter(|&x| before.registers[x] != after.registers[x])
+            .map(|x| Change::V(x, after.registers[x]))
+            .collect();
+        if before.i_register != after.i_register {
+            changes.push(Change::I(after.i_register));
+        }
+        if before.sp != after.sp {
+            changes.push(Change::Sp(after.sp));
+        }
+
+        TraceRecord {
+            pc: before.pc,
+            opcode,
+            next,
+            changes,
+        }
+    }
+
+    /// Formats the record as a text trace line, e.g. `0202  6108  LD V1, 0x08  V1=08`.
+    pub fn format(&self, ext: &ActiveExtensions) -> String {
+        let (mnemonic, _) = disasm::disassemble_op(self.opcode, self.next, ext);
+        let mut line = format!("{:04X}  {:04X}  {:<20}", self.pc, self.opcode, mnemonic);
+        for change in &self.changes {
+            let _ = write!(line, " {}", change);
+        }
+        line.trim_end().to_string()
+    }
+
+    /// `pc`, `opcode`, the operand word for `F000 NNNN`, a change mask, then the new value of
+    /// each changed register. Everything is little-endian.
+    fn write_binary<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        out.write_all(&self.pc.to_le_bytes())?;
+        out.write_all(&self.opcode.to_le_bytes())?;
+        if let Some(next) = self.next {
+            out.write_all(&next.to_le_bytes())?;
+        }
+
+        let mask = self.changes.iter().fold(0u32, |mask, change| match change {
+            Change::V(x, _) => mask | 1 << x,
+            Change::I(_) => mask | I_CHANGED,
+            Change::Sp(_) => mask | SP_CHANGED,
+        });
+        out.write_all(&mask.to_le_bytes())?;
+        for change in &self.changes {
+            match *change {
+                Change::V(_, value) => out.write_all(&[value])?,
+                Change::I(value) => out.write_all(&value.to_le_bytes())?,
+                Change::Sp(value) => out.write_all(&[value as u8])?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a record written by `write_binary`, `None` at the end of the trace.
+    fn read_binary<R: Read>(input: &mut R, ext: &ActiveExtensions) -> Result<Option<Self>> {
+        let mut word = [0; 2];
+        match input.read_exact(&mut word) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let pc = u16::from_le_bytes(word);
+        let opcode = read_u16(input)?;
+        let next = match Instruction::decode(opcode, ext) {
+            Instruction::LoadILong => Some(read_u16(input)?),
+            _ => None,
+        };
+
+        let mut mask = [0; 4];
+        input.read_exact(&mut mask)?;
+        let mask = u32::from_le_bytes(mask);
+        let mut changes = Vec::new();
+        for x in 0..REGISTER_COUNT {
+            if mask & 1 << x != 0 {
+                changes.push(Change::V(x, read_u8(input)?));
+            }
+        }
+        if mask & I_CHANGED != 0 {
+            changes.push(Change::I(read_u16(input)?));
+        }
+        if mask & SP_CHANGED != 0 {
+            changes.push(Change::Sp(read_u8(input)? as u16));
+        }
+
+        Ok(Some(TraceRecord {
+            pc,
+            opcode,
+            next,
+            changes,
+        }))
+    }
+}
+
+/// Logs executed instructions to a file. Installed with `Chip8VM::set_tracer`.
+pub struct Tracer {
+    out: BufWriter<File>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    ext: ActiveExtensions,
+    records: u64,
+}
+
+impl Tracer {
+    /// Creates the trace file. `ext` selects how opcodes are decoded, it has to match the VM.
+    pub fn create(
+        path: &Path,
+        format: TraceFormat,
+        filter: TraceFilter,
+        ext: ActiveExtensions,
+    ) -> Result<Self> {
+        let file = File::create(path)
+            .context(format!("Failed to create trace file: {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+
+        if format == TraceFormat::Binary {
+            let flags = ext.schip as u8 | (ext.xochip as u8) << 1;
+            out.write_all(MAGIC)?;
+            out.write_all(&[VERSION, flags])?;
+        }
+
+        Ok(Tracer {
+            out,
+            format,
+            filter,
+            ext,
+            records: 0,
+        })
+    }
+
+    /// Logs the instruction that took the machine from `before` to `after`, if the filter
+    /// lets it through. `next` is the word following the opcode.
+    pub fn record(
+        &mut self,
+        before: &CpuRegisters,
+        after: &CpuRegisters,
+        opcode: u16,
+        next: Option<u16>,
+    ) -> Result<()> {
+        if !self.filter.matches(before.pc, opcode) {
+            return Ok(());
+        }
+
+        let next = match Instruction::decode(opcode, &self.ext) {
+            Instruction::LoadILong => next,
+            _ => None,
+        };
+        let record = TraceRecord::new(before, after, opcode, next);
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", record.format(&self.ext)),
+            TraceFormat::Binary => record.write_binary(&mut self.out),
+        }
+        .context("Failed to write trace")?;
+
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Flushes the trace, returning the number of instructions logged.
+    pub fn finish(mut self) -> Result<u64> {
+        self.out.flush().context("Failed to write trace")?;
+        Ok(self.records)
+    }
+}
+
+/// Converts a binary trace into the text format, returning the number of records.
+pub fn dump_binary<R: Read, W: Write>(mut input: R, mut output: W) -> Result<u64> {
+    let mut header = [0; 6];
+    input
+        .read_exact(&mut header)
+        .context("Trace is too short")?;
+    if &header[..4] != MAGIC {
+        bail!("Not a binary trace");
+    }
+    if header[4] != VERSION {
+        bail!("Unsupported trace version: {}", header[4]);
+    }
+    let ext = ActiveExtensions {
+        schip: header[5] & 1 != 0,
+        xochip: header[5] & 2 != 0,
+    };
+
+    let mut records = 0;
+    while let Some(record) = TraceRecord::read_binary(&mut input, &ext)? {
+        writeln!(output, "{}", record.format(&ext))?;
+        records += 1;
+    }
+    Ok(records)
+}
+
+fn read_u8<R: Read>(input: &mut R) -> Result<u8> {
+    let mut byte = [0];
+    input.read_exact(&mut byte).context("Truncated trace")?;
+    Ok(byte[0])
+}
+
+fn read_u16<R: Read>(input: &mut R) -> Result<u16> {
+    let mut word = [0; 2];
+    input.read_exact(&mut word).context("Truncated trace")?;
+    Ok(u16::from_le_bytes(word))
+}
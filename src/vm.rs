@@ -1,19 +1,52 @@
 use crate::{
     conf::{
-        FLAG_COUNT, FONTSET, FONTSET_SIZE, HI_RES_HEIGHT, HI_RES_WIDTH, KEYS_COUNT, RAM_SIZE,
-        REGISTER_COUNT, SCREEN_HEIGHT, SCREEN_WIDTH, STACK_SIZE, START_ADDR,
+        AUDIO_PATTERN_SIZE, DEFAULT_PITCH, FLAG_COUNT, FONTSET, FONTSET_SIZE, HI_RES_HEIGHT,
+        HI_RES_WIDTH, KEYS_COUNT, LARGE_FONTSET, LARGE_FONTSET_SIZE, LARGE_FONT_BASE_ADDR,
+        RAM_SIZE, REGISTER_COUNT, SCREEN_HEIGHT, SCREEN_WIDTH, STACK_SIZE, START_ADDR,
     },
-    extensions::{Extension, VmContext},
+    coverage::Coverage,
+    extensions::{ActiveExtensions, Extension, VmContext},
+    instruction::Instruction,
+    movie::InputEvent,
+    profile::Profiler,
+    quirks::Quirks,
+    rng::Rng,
+    savestate::{StateReader, StateWriter},
+    superchip::SuperChip8,
+    trace::Tracer,
+    xochip::XoChip,
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use rand::random;
 
 const MAX_SCREEN_SIZE: usize = HI_RES_HEIGHT * HI_RES_WIDTH;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A data access made by an instruction (instruction fetches are not reported).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub addr: usize,
+    pub len: usize,
+    pub kind: AccessKind,
+}
+
+impl MemoryAccess {
+    /// Whether the access touches any byte of `start..start + len`.
+    pub fn overlaps(&self, start: usize, len: usize) -> bool {
+        self.addr < start + len && start < self.addr + self.len
+    }
+}
+
+#[derive(Clone)]
 pub struct CpuState {
     pub pc: u16,
-    pub memory: [u8; RAM_SIZE],
-    screen: [bool; MAX_SCREEN_SIZE],
+    pub memory: Vec<u8>,
+    screen: [u8; MAX_SCREEN_SIZE],
     pub current_width: usize,
     pub current_height: usize,
     pub registers: [u8; REGISTER_COUNT],
@@ -25,6 +58,11 @@ pub struct CpuState {
     pub sound_timer: u8,
     // S-CHIP specific
     pub rpl_flags: [u8; FLAG_COUNT],
+    // XO-CHIP specific
+    pub audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: u8,
+    // CXNN
+    pub rng: Rng,
 }
 
 impl Default for CpuState {
@@ -37,8 +75,8 @@ impl CpuState {
     pub fn new() -> Self {
         CpuState {
             pc: START_ADDR,
-            memory: [0; RAM_SIZE],
-            screen: [false; MAX_SCREEN_SIZE],
+            memory: vec![0; RAM_SIZE],
+            screen: [0; MAX_SCREEN_SIZE],
             current_width: SCREEN_WIDTH,
             current_height: SCREEN_HEIGHT,
             registers: [0; REGISTER_COUNT],
@@ -49,9 +87,16 @@ impl CpuState {
             delay_timer: 0,
             sound_timer: 0,
             rpl_flags: [0; FLAG_COUNT],
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            rng: Rng::new(0),
         }
     }
-    fn get_context(&mut self) -> VmContext<'_> {
+    fn get_context<'a>(
+        &'a mut self,
+        quirks: &'a Quirks,
+        accesses: &'a mut Vec<MemoryAccess>,
+    ) -> VmContext<'a> {
         VmContext {
             pc: &mut self.pc,
             registers: &mut self.registers,
@@ -66,12 +111,16 @@ impl CpuState {
             current_width: &mut self.current_width,
             current_height: &mut self.current_height,
             rpl_flags: &mut self.rpl_flags,
+            audio_pattern: &mut self.audio_pattern,
+            pitch: &mut self.pitch,
+            quirks,
+            accesses,
         }
     }
     pub fn reset(&mut self) {
         self.pc = START_ADDR;
         self.memory.fill(0);
-        self.screen.fill(false);
+        self.screen.fill(0);
         self.current_width = SCREEN_WIDTH;
         self.current_height = SCREEN_HEIGHT;
         self.registers.fill(0);
@@ -82,34 +131,131 @@ impl CpuState {
         self.delay_timer = 0;
         self.sound_timer = 0;
         self.memory[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        let large_font = LARGE_FONT_BASE_ADDR as usize;
+        self.memory[large_font..large_font + LARGE_FONTSET_SIZE].copy_from_slice(&LARGE_FONTSET);
         self.rpl_flags.fill(0);
+        self.audio_pattern.fill(0);
+        self.pitch = DEFAULT_PITCH;
+    }
+}
+
+/// Everything an instruction can change besides memory and the framebuffer, saved before each
+/// instruction for reverse debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub pc: u16,
+    pub i_register: u16,
+    pub sp: u16,
+    pub registers: [u8; REGISTER_COUNT],
+    pub stack: [u16; STACK_SIZE],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub current_width: usize,
+    pub current_height: usize,
+    pub rpl_flags: [u8; FLAG_COUNT],
+    pub audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: u8,
+    pub rng: Rng,
+    pub display_ready: bool,
+}
+
+/// Reverts one instruction: the registers before it, plus the previous value of every memory
+/// byte and framebuffer pixel it changed.
+#[derive(Debug, Clone)]
+pub struct UndoDelta {
+    pub registers: CpuRegisters,
+    pub memory: Vec<(usize, u8)>,
+    pub pixels: Vec<(usize, u8)>,
+    /// The extension states before the instruction, only when it changed one of them
+    pub extensions: Option<Vec<Vec<u8>>>,
+}
+
+/// A copy of the whole machine, extension state included, e.g. for the rewind buffer.
+#[derive(Clone)]
+pub struct Snapshot {
+    cpu: CpuState,
+    // Per-extension state, in registration order
+    extensions: Vec<Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Memory taken by the snapshot in bytes, RAM and framebuffer included.
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.cpu.memory.len()
+            + self.extensions.iter().map(Vec::len).sum::<usize>()
     }
 }
 
 pub struct Chip8VM {
     cpu: CpuState,
     extensions: Vec<Box<dyn Extension>>,
+    quirks: Quirks,
+    active_extensions: ActiveExtensions,
+    // Memory accesses made by the last executed instruction
+    accesses: Vec<MemoryAccess>,
+    // Set on every timer tick, cleared by a draw when `display_wait` is on
+    display_ready: bool,
+    // The last tick was a draw held back by `display_wait`
+    display_stalled: bool,
+    // Seed the RNG was last started from
+    seed: u64,
+    // Timer ticks since the VM was created
+    frame: u64,
+    // Keypad changes, logged while recording a movie
+    input_log: Option<Vec<InputEvent>>,
+    tracer: Option<Tracer>,
+    // Why the tracer was dropped, until taken with `take_trace_error`
+    trace_error: Option<anyhow::Error>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
 }
 
 impl Default for Chip8VM {
     fn default() -> Self {
-        Self::new(Vec::new())
+        Self::new(Vec::new(), Quirks::default())
     }
 }
 
 impl Chip8VM {
-    pub fn new(mut extensions: Vec<Box<dyn Extension>>) -> Self {
+    pub fn new(mut extensions: Vec<Box<dyn Extension>>, quirks: Quirks) -> Self {
         let mut chip8vm = Chip8VM {
             cpu: CpuState::new(),
             extensions: Vec::new(),
+            quirks,
+            active_extensions: ActiveExtensions::default(),
+            accesses: Vec::new(),
+            display_ready: true,
+            display_stalled: false,
+            seed: 0,
+            frame: 0,
+            input_log: None,
+            tracer: None,
+            trace_error: None,
+            profiler: None,
+            coverage: None,
         };
+        chip8vm.cpu.reset();
+        chip8vm.set_seed(random());
+
         for mut ext in extensions.drain(..) {
-            let mut ctx = chip8vm.cpu.get_context();
+            let mut ctx = chip8vm
+                .cpu
+                .get_context(&chip8vm.quirks, &mut chip8vm.accesses);
             ext.initialize(&mut ctx);
             chip8vm.extensions.push(ext);
         }
 
-        chip8vm.cpu.reset();
+        let enabled = |name: &str| {
+            chip8vm
+                .extensions
+                .iter()
+                .any(|ext| ext.is_active() && ext.name() == name)
+        };
+        chip8vm.active_extensions = ActiveExtensions {
+            schip: enabled(SuperChip8::NAME),
+            xochip: enabled(XoChip::NAME),
+        };
         chip8vm
     }
 
@@ -117,7 +263,7 @@ impl Chip8VM {
         let start = START_ADDR as usize;
         let end = start + data.len();
 
-        if end >= RAM_SIZE {
+        if end > self.cpu.memory.len() {
             bail!("ROM size exceeds available memory.");
         }
 
@@ -126,26 +272,116 @@ impl Chip8VM {
     }
 
     pub fn tick(&mut self) -> Result<()> {
+        self.accesses.clear();
+        let before = self.tracer.is_some().then(|| self.cpu_registers());
+        let pc = self.cpu.pc;
         let op = self.fetch();
-        self.execute(op)
+        let instruction = Instruction::decode(op, &self.active_extensions);
+        self.execute(op, instruction)?;
+
+        // A draw held back by `display_wait` leaves PC in place and did not execute
+        self.display_stalled = self.cpu.pc == pc && matches!(instruction, Instruction::Draw { .. });
+        if self.display_stalled
+            || self.tracer.is_none() && self.profiler.is_none() && self.coverage.is_none()
+        {
+            return Ok(());
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, instruction);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, instruction, self.cpu.pc);
+        }
+        if let Some(before) = before {
+            let after = self.cpu_registers();
+            let next = self.read_word(pc as usize + 2);
+            // A failed trace write is not the instruction's fault: stop tracing, keep running
+            if let Some(Err(e)) = self
+                .tracer
+                .as_mut()
+                .map(|tracer| tracer.record(&before, &after, op, next))
+            {
+                self.tracer = None;
+                self.trace_error = Some(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts the random number sequence of CXNN from `seed`, making runs reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.cpu.rng = Rng::new(seed);
+    }
+
+    /// The seed passed to `set_seed`, or picked at random when the VM was created.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Whether the last instruction was a draw waiting for the next timer tick, see
+    /// `Quirks::display_wait`. It will be executed again.
+    pub fn waiting_for_display(&self) -> bool {
+        self.display_stalled
+    }
+
+    /// Number of timer ticks (frames) since the VM was created.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Starts logging every keypad change, stamped with the frame it happened in, or stops
+    /// with `None`. Returns the events logged so far, to build a `Movie` from.
+    pub fn set_input_log(&mut self, input_log: Option<Vec<InputEvent>>) -> Option<Vec<InputEvent>> {
+        std::mem::replace(&mut self.input_log, input_log)
+    }
+
+    /// Installs a tracer logging every executed instruction, or removes it with `None`.
+    /// Returns the previous tracer so it can be finished.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// The write error that made the VM drop its tracer, if any. Taking it clears it.
+    pub fn take_trace_error(&mut self) -> Option<anyhow::Error> {
+        self.trace_error.take()
+    }
+
+    /// Installs a profiler counting every executed instruction, or removes it with `None`.
+    /// Returns the previous profiler.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) -> Option<Profiler> {
+        std::mem::replace(&mut self.profiler, profiler)
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Installs a coverage map recording executed addresses and skip outcomes, or removes it
+    /// with `None`. Returns the previous map.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) -> Option<Coverage> {
+        std::mem::replace(&mut self.coverage, coverage)
     }
 
     pub fn tick_timers(&mut self) -> (u8, u8) {
+        self.display_ready = true;
+        self.frame += 1;
+
         if self.cpu.delay_timer > 0 {
             self.cpu.delay_timer -= 1;
         }
 
         if self.cpu.sound_timer > 0 {
-            if self.cpu.sound_timer == 1 {
-                // BEEP
-            }
             self.cpu.sound_timer -= 1;
         }
 
         (self.cpu.delay_timer, self.cpu.sound_timer)
     }
 
-    pub fn get_display_config(&self) -> (usize, usize, &[bool]) {
+    /// Returns the active resolution and the framebuffer, where each pixel holds one bit per
+    /// bitplane (only plane 1 is ever drawn to outside of XO-CHIP).
+    pub fn get_display_config(&self) -> (usize, usize, &[u8]) {
         (
             self.cpu.current_width,
             self.cpu.current_height,
@@ -157,6 +393,15 @@ impl Chip8VM {
         if idx >= KEYS_COUNT {
             bail!("Invalid key index: {}", idx);
         }
+        if self.cpu.keys[idx] != pressed {
+            if let Some(input_log) = &mut self.input_log {
+                input_log.push(InputEvent {
+                    frame: self.frame,
+                    key: idx as u8,
+                    pressed,
+                });
+            }
+        }
         self.cpu.keys[idx] = pressed;
         Ok(())
     }
@@ -165,6 +410,290 @@ impl Chip8VM {
         &self.cpu
     }
 
+    /// Copies the current machine state, e.g. for the rewind buffer.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            extensions: self.extension_states(),
+        }
+    }
+
+    /// Sets VX, e.g. from the debugger.
+    pub fn set_register(&mut self, x: usize, value: u8) -> Result<()> {
+        if x >= REGISTER_COUNT {
+            bail!("Invalid register index: {}", x);
+        }
+        self.cpu.registers[x] = value;
+        Ok(())
+    }
+
+    pub fn set_i(&mut self, value: u16) {
+        self.cpu.i_register = value;
+    }
+
+    /// Moves execution to `addr`, which has to hold a full opcode.
+    pub fn set_pc(&mut self, addr: u16) -> Result<()> {
+        if addr as usize + 1 >= self.cpu.memory.len() {
+            bail!("PC out of memory: 0x{:04X}", addr);
+        }
+        self.cpu.pc = addr;
+        Ok(())
+    }
+
+    pub fn set_sp(&mut self, sp: u16) -> Result<()> {
+        if sp as usize > STACK_SIZE {
+            bail!("Stack pointer out of range: {}", sp);
+        }
+        self.cpu.sp = sp;
+        Ok(())
+    }
+
+    /// Sets the delay and/or sound timer, `None` leaving it untouched.
+    pub fn set_timers(&mut self, delay: Option<u8>, sound: Option<u8>) {
+        if let Some(delay) = delay {
+            self.cpu.delay_timer = delay;
+        }
+        if let Some(sound) = sound {
+            self.cpu.sound_timer = sound;
+        }
+    }
+
+    /// Patches memory at `addr`, e.g. from the debugger. Nothing is written if the range
+    /// does not fit in memory.
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) -> Result<()> {
+        let start = addr as usize;
+        let end = start + data.len();
+        if end > self.cpu.memory.len() {
+            bail!("Memory write out of bounds: 0x{:04X}..0x{:04X}", start, end);
+        }
+        self.cpu.memory[start..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn cpu_registers(&self) -> CpuRegisters {
+        let cpu = &self.cpu;
+        CpuRegisters {
+            pc: cpu.pc,
+            i_register: cpu.i_register,
+            sp: cpu.sp,
+            registers: cpu.registers,
+            stack: cpu.stack,
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+            current_width: cpu.current_width,
+            current_height: cpu.current_height,
+            rpl_flags: cpu.rpl_flags,
+            audio_pattern: cpu.audio_pattern,
+            pitch: cpu.pitch,
+            rng: cpu.rng,
+            display_ready: self.display_ready,
+        }
+    }
+
+    /// The state of every extension, in registration order, as saved in save states.
+    pub fn extension_states(&self) -> Vec<Vec<u8>> {
+        self.extensions.iter().map(|ext| ext.save_state()).collect()
+    }
+
+    /// Steps back over an instruction recorded with `cpu_registers` and the memory and pixel
+    /// changes it made.
+    pub fn apply_undo(&mut self, delta: &UndoDelta) {
+        let cpu = &mut self.cpu;
+        let registers = &delta.registers;
+        cpu.pc = registers.pc;
+        cpu.i_register = registers.i_register;
+        cpu.sp = registers.sp;
+        cpu.registers = registers.registers;
+        cpu.stack = registers.stack;
+        cpu.delay_timer = registers.delay_timer;
+        cpu.sound_timer = registers.sound_timer;
+        cpu.current_width = registers.current_width;
+        cpu.current_height = registers.current_height;
+        cpu.rpl_flags = registers.rpl_flags;
+        cpu.audio_pattern = registers.audio_pattern;
+        cpu.pitch = registers.pitch;
+        cpu.rng = registers.rng;
+        self.display_ready = registers.display_ready;
+
+        for &(addr, value) in &delta.memory {
+            cpu.memory[addr] = value;
+        }
+        for &(idx, value) in &delta.pixels {
+            cpu.screen[idx] = value;
+        }
+        for (ext, state) in self
+            .extensions
+            .iter_mut()
+            .zip(delta.extensions.iter().flatten())
+        {
+            ext.load_state(state)
+                .expect("an extension's own state should load back");
+        }
+    }
+
+    /// Restores a snapshot taken with `snapshot`, keeping the current keypad state.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        let Snapshot {
+            mut cpu,
+            extensions,
+        } = snapshot;
+        cpu.keys = self.cpu.keys;
+        self.cpu = cpu;
+        for (ext, state) in self.extensions.iter_mut().zip(&extensions) {
+            ext.load_state(state)
+                .expect("an extension's own state should load back");
+        }
+    }
+
+    /// Serializes the whole machine, extension state included, into a versioned binary blob.
+    pub fn save_state(&self) -> Vec<u8> {
+        let cpu = &self.cpu;
+        let mut writer = StateWriter::new();
+
+        writer.u16(cpu.pc);
+        writer.u16(cpu.i_register);
+        writer.u16(cpu.sp);
+        writer.bytes(&cpu.registers);
+        for addr in cpu.stack {
+            writer.u16(addr);
+        }
+        writer.u8(cpu.delay_timer);
+        writer.u8(cpu.sound_timer);
+        writer.u16(cpu.current_width as u16);
+        writer.u16(cpu.current_height as u16);
+        writer.bytes(&cpu.rpl_flags);
+        writer.bytes(&cpu.audio_pattern);
+        writer.u8(cpu.pitch);
+        writer.u64(cpu.rng.state());
+        writer.blob(&cpu.memory);
+        writer.blob(&cpu.screen);
+
+        writer.u16(self.extensions.len() as u16);
+        for ext in &self.extensions {
+            writer.u8(ext.name().len() as u8);
+            writer.bytes(ext.name().as_bytes());
+            writer.blob(&ext.save_state());
+        }
+
+        writer.finish()
+    }
+
+    /// Restores a state produced by `save_state`. The VM is left untouched if the state cannot
+    /// be decoded or an extension rejects its part of it.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        let mut reader = StateReader::new(data)?;
+        let mut cpu = CpuState::new();
+
+        cpu.pc = reader.u16()?;
+        cpu.i_register = reader.u16()?;
+        cpu.sp = reader.u16()?;
+        if cpu.sp as usize > STACK_SIZE {
+            bail!("Invalid stack pointer in save state: {}", cpu.sp);
+        }
+        cpu.registers.copy_from_slice(reader.bytes(REGISTER_COUNT)?);
+        for addr in cpu.stack.iter_mut() {
+            *addr = reader.u16()?;
+        }
+        cpu.delay_timer = reader.u8()?;
+        cpu.sound_timer = reader.u8()?;
+        cpu.current_width = reader.u16()? as usize;
+        cpu.current_height = reader.u16()? as usize;
+        match (cpu.current_width, cpu.current_height) {
+            (SCREEN_WIDTH, SCREEN_HEIGHT) | (HI_RES_WIDTH, HI_RES_HEIGHT) => {}
+            (width, height) => bail!("Invalid resolution in save state: {}x{}", width, height),
+        }
+        cpu.rpl_flags.copy_from_slice(reader.bytes(FLAG_COUNT)?);
+        cpu.audio_pattern
+            .copy_from_slice(reader.bytes(AUDIO_PATTERN_SIZE)?);
+        cpu.pitch = reader.u8()?;
+        cpu.rng = Rng::from_state(reader.u64()?).context("Invalid RNG state in save state")?;
+        cpu.memory = reader.blob()?.to_vec();
+        if cpu.memory.len() != self.cpu.memory.len() {
+            bail!("Save state memory size does not match the VM");
+        }
+        if cpu.pc as usize + 1 >= cpu.memory.len() {
+            bail!("PC out of memory in save state: 0x{:04X}", cpu.pc);
+        }
+        // RET jumps to these, so they must be fetchable as well
+        for &addr in &cpu.stack[..cpu.sp as usize] {
+            if addr as usize + 1 >= cpu.memory.len() {
+                bail!("Return address out of memory in save state: 0x{:04X}", addr);
+            }
+        }
+        let screen = reader.blob()?;
+        if screen.len() != MAX_SCREEN_SIZE {
+            bail!("Invalid screen size in save state");
+        }
+        cpu.screen.copy_from_slice(screen);
+
+        let mut extension_states = Vec::new();
+        for _ in 0..reader.u16()? {
+            let name_len = reader.u8()? as usize;
+            let name = String::from_utf8_lossy(reader.bytes(name_len)?).into_owned();
+            let state = reader.blob()?;
+            if !self.extensions.iter().any(|ext| ext.name() == name) {
+                bail!("Save state requires the {} extension", name);
+            }
+            extension_states.push((name, state));
+        }
+        if !reader.is_empty() {
+            bail!("Unexpected trailing data in save state");
+        }
+
+        // Extensions only validate their state as they load it, so put back the ones loaded
+        // before a failing one
+        let previous = self.extension_states();
+        let loaded = extension_states.iter().try_for_each(|(name, state)| {
+            self.extensions
+                .iter_mut()
+                .filter(|ext| ext.name() == name)
+                .try_for_each(|ext| ext.load_state(state))
+        });
+        if let Err(e) = loaded {
+            for (ext, state) in self.extensions.iter_mut().zip(&previous) {
+                ext.load_state(state)
+                    .expect("an extension's own state should load back");
+            }
+            return Err(e);
+        }
+
+        cpu.keys = self.cpu.keys;
+        self.cpu = cpu;
+        Ok(())
+    }
+
+    /// The interpreter quirks the VM emulates, fixed when it is created.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// The extensions used to decode opcodes, fixed when the VM is created.
+    pub fn active_extensions(&self) -> ActiveExtensions {
+        self.active_extensions
+    }
+
+    /// Returns the opcode at PC without executing it.
+    pub fn peek_opcode(&self) -> u16 {
+        self.read_word(self.cpu.pc as usize).unwrap_or(0)
+    }
+
+    /// Memory reads and writes made by the last `tick`, e.g. for debugger watchpoints.
+    pub fn last_accesses(&self) -> &[MemoryAccess] {
+        &self.accesses
+    }
+
+    /// Decodes the instruction at PC without executing it.
+    pub fn peek_instruction(&self) -> Instruction {
+        Instruction::decode(self.peek_opcode(), &self.active_extensions)
+    }
+
+    fn read_word(&self, addr: usize) -> Option<u16> {
+        match self.cpu.memory.get(addr..addr + 2) {
+            Some(&[hi, lo]) => Some(u16::from_be_bytes([hi, lo])),
+            _ => None,
+        }
+    }
+
     fn fetch(&mut self) -> u16 {
         let hi = self.cpu.memory[self.cpu.pc as usize] as u16;
         let lo = self.cpu.memory[(self.cpu.pc + 1) as usize] as u16;
@@ -173,177 +702,193 @@ impl Chip8VM {
         op
     }
 
-    fn execute(&mut self, op: u16) -> Result<()> {
+    fn execute(&mut self, op: u16, instruction: Instruction) -> Result<()> {
+        if matches!(instruction, Instruction::Draw { .. }) && self.quirks.display_wait {
+            if !self.display_ready {
+                // Retry the draw once the next display refresh has happened
+                self.cpu.pc -= 2;
+                return Ok(());
+            }
+            self.display_ready = false;
+        }
+
         {
-            let mut ctx = self.cpu.get_context();
+            let mut ctx = self.cpu.get_context(&self.quirks, &mut self.accesses);
             let extensions = &mut self.extensions;
 
             for extension in extensions.iter_mut() {
-                if extension.is_active() && extension.handle_instruction(&mut ctx, op)? {
+                if extension.is_active() && extension.handle_instruction(&mut ctx, instruction)? {
                     return Ok(());
                 }
             }
         }
-        let d1 = (op & 0xF000) >> 12;
-        let d2 = ((op & 0x0F00) >> 8) as u8;
-        let d3 = ((op & 0x00F0) >> 4) as u8;
-        let d4 = (op & 0x000F) as u8;
-        let x = d2 as usize;
-        let y = d3 as usize;
 
-        match (d1, d2, d3, d4) {
+        match instruction {
             // NOP
-            (0, 0, 0, 0) => (),
+            Instruction::Sys(0) => (),
 
             // CLS: 0x00E0
-            (0, 0, 0xE, 0) => {
+            Instruction::ClearScreen => {
                 let current_w = self.cpu.current_width;
                 let current_h = self.cpu.current_height;
 
                 for y in 0..current_h {
                     for x in 0..current_w {
-                        self.cpu.screen[x + y * HI_RES_WIDTH] = false;
+                        self.cpu.screen[x + y * HI_RES_WIDTH] = 0;
                     }
                 }
             }
 
             // RET: 0x00EE
-            (0, 0, 0xE, 0xE) => self.cpu.pc = self.pop_from_stack()?,
+            Instruction::Return => self.cpu.pc = self.pop_from_stack()?,
 
             // JMP NNN: 0x1NNN
-            (1, _, _, _) => {
-                let nnn = op & 0xFFF;
-                self.cpu.pc = nnn;
-            }
+            Instruction::Jump(nnn) => self.cpu.pc = nnn,
 
             // CALL NNN: 0x2NNN
-            (2, _, _, _) => {
-                let nnn = op & 0xFFF;
+            Instruction::Call(nnn) => {
                 self.push_to_stack(self.cpu.pc)?;
                 self.cpu.pc = nnn;
             }
 
             // SKIP VX == NN: 0x3XNN
-            (3, _, _, _) => {
-                let nn = (op & 0xFF) as u8;
+            Instruction::SkipEqImm { x, nn } => {
                 if self.cpu.registers[x] == nn {
                     self.cpu.pc += 2;
                 }
             }
 
             // SKIP VX != NN: 0x4XNN
-            (4, _, _, _) => {
-                let nn = (op & 0xFF) as u8;
+            Instruction::SkipNeImm { x, nn } => {
                 if self.cpu.registers[x] != nn {
                     self.cpu.pc += 2;
                 }
             }
 
             // SKIP VX == VY: 0x5XY0
-            (5, _, _, 0) => {
+            Instruction::SkipEqReg { x, y } => {
                 if self.cpu.registers[x] == self.cpu.registers[y] {
                     self.cpu.pc += 2;
                 }
             }
 
             // VX = NN: 0x6XNN
-            (6, _, _, _) => {
-                let nn = (op & 0xFF) as u8;
-                self.cpu.registers[x] = nn;
-            }
+            Instruction::LoadImm { x, nn } => self.cpu.registers[x] = nn,
 
             // VX += NN: 0x7XNN
-            (7, _, _, _) => {
-                let nn = (op & 0xFF) as u8;
+            Instruction::AddImm { x, nn } => {
                 self.cpu.registers[x] = self.cpu.registers[x].wrapping_add(nn);
             }
 
             // 8XYN Opcode Group
-            (8, _, _, 0) => self.cpu.registers[x] = self.cpu.registers[y],
-            (8, _, _, 1) => self.cpu.registers[x] |= self.cpu.registers[y],
-            (8, _, _, 2) => self.cpu.registers[x] &= self.cpu.registers[y],
-            (8, _, _, 3) => self.cpu.registers[x] ^= self.cpu.registers[y],
-            (8, _, _, 4) => {
+            Instruction::Move { x, y } => self.cpu.registers[x] = self.cpu.registers[y],
+            Instruction::Or { x, y } => {
+                self.cpu.registers[x] |= self.cpu.registers[y];
+                if self.quirks.vf_reset {
+                    self.cpu.registers[0xF] = 0;
+                }
+            }
+            Instruction::And { x, y } => {
+                self.cpu.registers[x] &= self.cpu.registers[y];
+                if self.quirks.vf_reset {
+                    self.cpu.registers[0xF] = 0;
+                }
+            }
+            Instruction::Xor { x, y } => {
+                self.cpu.registers[x] ^= self.cpu.registers[y];
+                if self.quirks.vf_reset {
+                    self.cpu.registers[0xF] = 0;
+                }
+            }
+            Instruction::AddReg { x, y } => {
                 let (new_vx, carry) = self.cpu.registers[x].overflowing_add(self.cpu.registers[y]);
                 self.cpu.registers[x] = new_vx;
                 self.cpu.registers[0xF] = if carry { 1 } else { 0 };
             }
-            (8, _, _, 5) => {
+            Instruction::Sub { x, y } => {
                 let (new_vx, borrow) = self.cpu.registers[x].overflowing_sub(self.cpu.registers[y]);
                 self.cpu.registers[x] = new_vx;
                 self.cpu.registers[0xF] = if borrow { 0 } else { 1 };
             }
-            (8, _, _, 6) => {
-                self.cpu.registers[0xF] = self.cpu.registers[x] & 0x1;
-                self.cpu.registers[x] >>= 1;
+            Instruction::ShiftRight { x, y } => {
+                let src = if self.quirks.shift_uses_vy { y } else { x };
+                let value = self.cpu.registers[src];
+                self.cpu.registers[x] = value >> 1;
+                self.cpu.registers[0xF] = value & 0x1;
             }
-            (8, _, _, 7) => {
+            Instruction::SubN { x, y } => {
                 let (new_vx, borrow) = self.cpu.registers[y].overflowing_sub(self.cpu.registers[x]);
                 self.cpu.registers[x] = new_vx;
                 self.cpu.registers[0xF] = if borrow { 0 } else { 1 };
             }
-            (8, _, _, 0xE) => {
-                self.cpu.registers[0xF] = (self.cpu.registers[x] >> 7) & 0x1;
-                self.cpu.registers[x] <<= 1;
+            Instruction::ShiftLeft { x, y } => {
+                let src = if self.quirks.shift_uses_vy { y } else { x };
+                let value = self.cpu.registers[src];
+                self.cpu.registers[x] = value << 1;
+                self.cpu.registers[0xF] = (value >> 7) & 0x1;
             }
 
             // SKIP if VX != VY: 0x9XY0
-            (9, _, _, 0) => {
+            Instruction::SkipNeReg { x, y } => {
                 if self.cpu.registers[x] != self.cpu.registers[y] {
                     self.cpu.pc += 2;
                 }
             }
 
             // I = NNN: 0xANNN
-            (0xA, _, _, _) => {
-                self.cpu.i_register = op & 0xFFF;
-            }
+            Instruction::LoadI(nnn) => self.cpu.i_register = nnn,
 
-            // JMP to V0 + NNN: 0xBNNN
-            (0xB, _, _, _) => {
-                let nnn = op & 0xFFF;
-                self.cpu.pc = (self.cpu.registers[0] as u16) + nnn;
+            // JMP to V0 + NNN: 0xBNNN (VX + NNN with the jump quirk)
+            Instruction::JumpOffset { x, nnn } => {
+                let offset_reg = if self.quirks.jump_uses_vx { x } else { 0 };
+                self.cpu.pc = (self.cpu.registers[offset_reg] as u16) + nnn;
             }
 
             // VX = rand() & NN: 0xCXNN
-            (0xC, _, _, _) => {
-                let nn = (op & 0xFF) as u8;
-                let rng: u8 = random();
-                self.cpu.registers[x] = rng & nn;
+            Instruction::Random { x, nn } => {
+                self.cpu.registers[x] = self.cpu.rng.next_u8() & nn;
             }
 
             // DRAW sprite: 0xDNNN
-            (0xD, _, _, n) => {
+            Instruction::Draw { x, y, n } => {
                 self.cpu.registers[0xF] = 0;
-                let x_coord = self.cpu.registers[x] as usize;
-                let y_coord = self.cpu.registers[y] as usize;
                 let screen_width = self.cpu.current_width;
                 let screen_height = self.cpu.current_height;
+                // The starting position always wraps, only the sprite body may be clipped
+                let x_coord = self.cpu.registers[x] as usize % screen_width;
+                let y_coord = self.cpu.registers[y] as usize % screen_height;
+                self.record_access(self.cpu.i_register as usize, n as usize, AccessKind::Read);
 
                 for y_line in 0..n as usize {
                     let addr = self.cpu.i_register as usize + y_line;
 
-                    if addr >= RAM_SIZE {
+                    if addr >= self.cpu.memory.len() {
                         bail!("Memory access out of bounds for sprite draw");
                     }
                     let pixels = self.cpu.memory[addr];
 
                     for x_line in 0..8 {
                         if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                            let px = (x_coord + x_line) % screen_width;
-                            let py = (y_coord + y_line) % screen_height;
+                            let (px, py) = (x_coord + x_line, y_coord + y_line);
+                            if self.quirks.clip_sprites
+                                && (px >= screen_width || py >= screen_height)
+                            {
+                                continue;
+                            }
+                            let px = px % screen_width;
+                            let py = py % screen_height;
                             let idx = px + py * HI_RES_WIDTH;
-                            if self.cpu.screen[idx] {
+                            if self.cpu.screen[idx] & 1 != 0 {
                                 self.cpu.registers[0xF] = 1;
                             }
-                            self.cpu.screen[idx] ^= true;
+                            self.cpu.screen[idx] ^= 1;
                         }
                     }
                 }
             }
 
             // EX9E: Skip if key pressed
-            (0xE, _, 9, 0xE) => {
+            Instruction::SkipKey(x) => {
                 let vx = self.cpu.registers[x] as usize;
                 if vx >= KEYS_COUNT {
                     bail!("Invalid key index in register VX: {}", vx);
@@ -354,7 +899,7 @@ impl Chip8VM {
             }
 
             // EXA1: Skip if key not pressed
-            (0xE, _, 0xA, 1) => {
+            Instruction::SkipNotKey(x) => {
                 let vx = self.cpu.registers[x] as usize;
                 if vx >= KEYS_COUNT {
                     bail!("Invalid key index in register VX: {}", vx);
@@ -365,12 +910,10 @@ impl Chip8VM {
             }
 
             // FX07: VX = DT
-            (0xF, _, 0, 7) => {
-                self.cpu.registers[x] = self.cpu.delay_timer;
-            }
+            Instruction::LoadDelay(x) => self.cpu.registers[x] = self.cpu.delay_timer,
 
             // FX0A: Wait for key press
-            (0xF, _, 0, 0xA) => {
+            Instruction::WaitKey(x) => {
                 let mut pressed_key = None;
                 for i in 0..KEYS_COUNT {
                     if self.cpu.keys[i] {
@@ -387,17 +930,13 @@ impl Chip8VM {
             }
 
             // FX15: DT = VX
-            (0xF, _, 1, 5) => {
-                self.cpu.delay_timer = self.cpu.registers[x];
-            }
+            Instruction::SetDelay(x) => self.cpu.delay_timer = self.cpu.registers[x],
 
             // FX18: ST = VX
-            (0xF, _, 1, 8) => {
-                self.cpu.sound_timer = self.cpu.registers[x];
-            }
+            Instruction::SetSound(x) => self.cpu.sound_timer = self.cpu.registers[x],
 
             // FX1E: I += VX
-            (0xF, _, 1, 0xE) => {
+            Instruction::AddI(x) => {
                 self.cpu.i_register = self
                     .cpu
                     .i_register
@@ -405,40 +944,52 @@ impl Chip8VM {
             }
 
             // FX29: I = font addr for VX
-            (0xF, _, 2, 9) => {
+            Instruction::LoadFont(x) => {
                 let c = self.cpu.registers[x] as u16;
                 self.cpu.i_register = c * 5;
             }
 
             // FX33: Store BCD representation of VX
-            (0xF, _, 3, 3) => {
+            Instruction::Bcd(x) => {
                 let vx = self.cpu.registers[x];
                 let i_usize = self.cpu.i_register as usize;
+                if i_usize + 2 >= self.cpu.memory.len() {
+                    bail!("Memory store out of bounds");
+                }
+                self.record_access(i_usize, 3, AccessKind::Write);
                 self.cpu.memory[i_usize] = vx / 100;
                 self.cpu.memory[i_usize + 1] = (vx / 10) % 10;
                 self.cpu.memory[i_usize + 2] = vx % 10;
             }
 
             // FX55: Store V0..VX in memory
-            (0xF, _, 5, 5) => {
+            Instruction::Store(x) => {
                 let i = self.cpu.i_register as usize;
-                if i + x >= RAM_SIZE {
+                if i + x >= self.cpu.memory.len() {
                     bail!("Memory store out of bounds");
                 }
+                self.record_access(i, x + 1, AccessKind::Write);
                 for idx in 0..=x {
                     self.cpu.memory[i + idx] = self.cpu.registers[idx];
                 }
+                if self.quirks.load_store_increments_i {
+                    self.cpu.i_register = self.cpu.i_register.wrapping_add(x as u16 + 1);
+                }
             }
 
             // FX65: Load V0..VX from memory
-            (0xF, _, 6, 5) => {
+            Instruction::Load(x) => {
                 let i = self.cpu.i_register as usize;
-                if i + x >= RAM_SIZE {
+                if i + x >= self.cpu.memory.len() {
                     bail!("Memory load out of bounds");
                 }
+                self.record_access(i, x + 1, AccessKind::Read);
                 for idx in 0..=x {
                     self.cpu.registers[idx] = self.cpu.memory[i + idx];
                 }
+                if self.quirks.load_store_increments_i {
+                    self.cpu.i_register = self.cpu.i_register.wrapping_add(x as u16 + 1);
+                }
             }
 
             _ => bail!("Unimplemented or unknown opcode: {:#X}", op),
@@ -446,6 +997,10 @@ impl Chip8VM {
         Ok(())
     }
 
+    fn record_access(&mut self, addr: usize, len: usize, kind: AccessKind) {
+        self.accesses.push(MemoryAccess { addr, len, kind });
+    }
+
     fn push_to_stack(&mut self, val: u16) -> Result<()> {
         if self.cpu.sp as usize >= STACK_SIZE {
             bail!("Stack overflow");
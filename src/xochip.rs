@@ -0,0 +1,251 @@
+use anyhow::{bail, Ok, Result};
+
+use crate::{
+    conf::{AUDIO_PATTERN_SIZE, KEYS_COUNT, XO_RAM_SIZE},
+    extensions::{Extension, VmContext},
+    instruction::Instruction,
+    superchip::{draw_sprite, scroll, SuperChip8},
+    vm::AccessKind,
+};
+
+const PLANE_COUNT: usize = 2;
+
+/// XO-CHIP extension (https://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html).
+/// XO-CHIP being a superset of S-CHIP, the S-CHIP opcodes it does not change are passed on to
+/// its own `SuperChip8`.
+pub struct XoChip {
+    active: bool,
+    // Bitmask of the planes affected by drawing, clearing and scrolling
+    selected_planes: u8,
+    schip: SuperChip8,
+}
+
+impl XoChip {
+    pub const NAME: &'static str = "XO-CHIP";
+
+    pub fn new(active: bool) -> Self {
+        XoChip {
+            active,
+            selected_planes: 1,
+            schip: SuperChip8::new(active),
+        }
+    }
+
+    /// Skips the next instruction, taking the 4-byte `F000 NNNN` into account.
+    fn skip(ctx: &mut VmContext) {
+        let pc = *ctx.pc as usize;
+        let long_load =
+            pc + 1 < ctx.memory.len() && ctx.memory[pc] == 0xF0 && ctx.memory[pc + 1] == 0x00;
+        *ctx.pc += if long_load { 4 } else { 2 };
+    }
+
+    fn key_pressed(ctx: &VmContext, x: usize) -> Result<bool> {
+        let vx = ctx.registers[x] as usize;
+        if vx >= KEYS_COUNT {
+            bail!("Invalid key index in register VX: {}", vx);
+        }
+        Ok(ctx.keys[vx])
+    }
+
+    fn register_range(x: usize, y: usize) -> Vec<usize> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+}
+
+impl Extension for XoChip {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn initialize(&mut self, ctx: &mut VmContext) {
+        self.schip.initialize(ctx);
+        ctx.memory.resize(XO_RAM_SIZE, 0);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.selected_planes]
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        match data {
+            [planes] if *planes <= 0b11 => self.selected_planes = *planes,
+            _ => bail!("Invalid XO-CHIP state"),
+        }
+        Ok(())
+    }
+
+    fn handle_instruction(
+        &mut self,
+        ctx: &mut VmContext,
+        instruction: Instruction,
+    ) -> Result<bool> {
+        if !self.active {
+            return Ok(false);
+        }
+
+        match instruction {
+            // 00E0: Clear the selected planes
+            Instruction::ClearScreen => {
+                for pixel in ctx.screen.iter_mut() {
+                    *pixel &= !self.selected_planes;
+                }
+                Ok(true)
+            }
+            // 00CN: scroll down n
+            Instruction::ScrollDown(n) => {
+                scroll(ctx, 0, n as isize, self.selected_planes);
+                Ok(true)
+            }
+            // 00DN: scroll up n
+            Instruction::ScrollUp(n) => {
+                scroll(ctx, 0, -(n as isize), self.selected_planes);
+                Ok(true)
+            }
+            // 00FB: scroll right 4 pixels
+            Instruction::ScrollRight => {
+                scroll(ctx, 4, 0, self.selected_planes);
+                Ok(true)
+            }
+            // 00FC: scroll left 4 pixels
+            Instruction::ScrollLeft => {
+                scroll(ctx, -4, 0, self.selected_planes);
+                Ok(true)
+            }
+
+            // Skips have to step over the 4-byte F000 NNNN
+            // 3XNN: SKIP VX == NN
+            Instruction::SkipEqImm { x, nn } => {
+                if ctx.registers[x] == nn {
+                    Self::skip(ctx);
+                }
+                Ok(true)
+            }
+            // 4XNN: SKIP VX != NN
+            Instruction::SkipNeImm { x, nn } => {
+                if ctx.registers[x] != nn {
+                    Self::skip(ctx);
+                }
+                Ok(true)
+            }
+            // 5XY0: SKIP VX == VY
+            Instruction::SkipEqReg { x, y } => {
+                if ctx.registers[x] == ctx.registers[y] {
+                    Self::skip(ctx);
+                }
+                Ok(true)
+            }
+            // 9XY0: SKIP VX != VY
+            Instruction::SkipNeReg { x, y } => {
+                if ctx.registers[x] != ctx.registers[y] {
+                    Self::skip(ctx);
+                }
+                Ok(true)
+            }
+            // EX9E: Skip if key pressed
+            Instruction::SkipKey(x) => {
+                if Self::key_pressed(ctx, x)? {
+                    Self::skip(ctx);
+                }
+                Ok(true)
+            }
+            // EXA1: Skip if key not pressed
+            Instruction::SkipNotKey(x) => {
+                if !Self::key_pressed(ctx, x)? {
+                    Self::skip(ctx);
+                }
+                Ok(true)
+            }
+
+            // 5XY2: Store VX..VY in memory, leaving I untouched
+            Instruction::StoreRange { x, y } => {
+                let i = *ctx.i_register as usize;
+                let range = Self::register_range(x, y);
+                if i + range.len() > ctx.memory.len() {
+                    bail!("Memory store out of bounds");
+                }
+                ctx.record_access(i, range.len(), AccessKind::Write);
+                for (offset, reg) in range.into_iter().enumerate() {
+                    ctx.memory[i + offset] = ctx.registers[reg];
+                }
+                Ok(true)
+            }
+            // 5XY3: Load VX..VY from memory, leaving I untouched
+            Instruction::LoadRange { x, y } => {
+                let i = *ctx.i_register as usize;
+                let range = Self::register_range(x, y);
+                if i + range.len() > ctx.memory.len() {
+                    bail!("Memory load out of bounds");
+                }
+                ctx.record_access(i, range.len(), AccessKind::Read);
+                for (offset, reg) in range.into_iter().enumerate() {
+                    ctx.registers[reg] = ctx.memory[i + offset];
+                }
+                Ok(true)
+            }
+
+            // DXYN: Draw on every selected plane, DXY0 draws 16x16 sprites in both resolutions
+            Instruction::Draw { x, y, n } => {
+                let coords = (ctx.registers[x] as usize, ctx.registers[y] as usize);
+                let dims = if n == 0 { (16, 16) } else { (8, n as usize) };
+                let sprite_size = dims.0 / 8 * dims.1;
+                let mut addr = *ctx.i_register as usize;
+                let mut collision = false;
+
+                // Each selected plane consumes its own sprite, stored one after the other
+                for plane in 0..PLANE_COUNT {
+                    let mask = 1 << plane;
+                    if self.selected_planes & mask != 0 {
+                        collision |= draw_sprite(ctx, coords, addr, dims, mask)?;
+                        addr += sprite_size;
+                    }
+                }
+                ctx.registers[0xF] = collision as u8;
+                Ok(true)
+            }
+
+            // F000 NNNN: I = NNNN
+            Instruction::LoadILong => {
+                let pc = *ctx.pc as usize;
+                if pc + 1 >= ctx.memory.len() {
+                    bail!("Memory access out of bounds for long I load");
+                }
+                *ctx.i_register = u16::from_be_bytes([ctx.memory[pc], ctx.memory[pc + 1]]);
+                *ctx.pc += 2;
+                Ok(true)
+            }
+            // FN01: Select drawing planes
+            Instruction::SelectPlanes(planes) => {
+                if planes > 0b11 {
+                    bail!("Invalid plane selection: {}", planes);
+                }
+                self.selected_planes = planes;
+                Ok(true)
+            }
+            // F002: Load the 16-byte audio pattern from memory
+            Instruction::LoadAudio => {
+                let i = *ctx.i_register as usize;
+                if i + AUDIO_PATTERN_SIZE > ctx.memory.len() {
+                    bail!("Memory load out of bounds");
+                }
+                ctx.record_access(i, AUDIO_PATTERN_SIZE, AccessKind::Read);
+                ctx.audio_pattern
+                    .copy_from_slice(&ctx.memory[i..i + AUDIO_PATTERN_SIZE]);
+                Ok(true)
+            }
+            // FX3A: Set the audio pattern playback pitch
+            Instruction::SetPitch(x) => {
+                *ctx.pitch = ctx.registers[x];
+                Ok(true)
+            }
+            _ => self.schip.handle_instruction(ctx, instruction),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+; Shared by the test ROMs: draws a check mark at (VC, VD) if V0 and VF hold the expected
+; V8 and V9, a cross otherwise, then moves VC/VD to the next cell of a 6-wide grid.
+; Uses I and VF.
+check:  LD I, cross
+        SE V0, V8
+        JP mark
+        SE VF, V9
+        JP mark
+        LD I, tick
+mark:   DRW VC, VD, 5
+        ADD VC, 10
+        SE VC, 60
+        RET
+        LD VC, 0
+        ADD VD, 7
+        RET
+
+tick:   db 0x01, 0x02, 0x84, 0x48, 0x30
+cross:  db 0x88, 0x50, 0x20, 0x50, 0x88
@@ -0,0 +1,125 @@
+; VF after the 8XY arithmetic instructions, one check mark per case. The results do not
+; depend on any quirk.
+        LD VC, 0
+        LD VD, 0
+
+        ; 8XY4 without carry, with carry, wrapping to zero
+        LD V0, 0x10
+        LD V1, 0x20
+        ADD V0, V1
+        LD V8, 0x30
+        LD V9, 0
+        CALL check
+
+        LD V0, 0xF0
+        LD V1, 0x20
+        ADD V0, V1
+        LD V8, 0x10
+        LD V9, 1
+        CALL check
+
+        LD V0, 0xFF
+        LD V1, 0x01
+        ADD V0, V1
+        LD V8, 0x00
+        LD V9, 1
+        CALL check
+
+        ; 8XY5 without borrow, equal operands, with borrow
+        LD V0, 0x30
+        LD V1, 0x10
+        SUB V0, V1
+        LD V8, 0x20
+        LD V9, 1
+        CALL check
+
+        LD V0, 0x30
+        LD V1, 0x30
+        SUB V0, V1
+        LD V8, 0x00
+        LD V9, 1
+        CALL check
+
+        LD V0, 0x10
+        LD V1, 0x30
+        SUB V0, V1
+        LD V8, 0xE0
+        LD V9, 0
+        CALL check
+
+        ; 8XY7 without and with borrow
+        LD V0, 0x10
+        LD V1, 0x30
+        SUBN V0, V1
+        LD V8, 0x20
+        LD V9, 1
+        CALL check
+
+        LD V0, 0x30
+        LD V1, 0x10
+        SUBN V0, V1
+        LD V8, 0xE0
+        LD V9, 0
+        CALL check
+
+        ; 8XY6 and 8XYE shift the bit out into VF
+        LD V0, 0x81
+        SHR V0
+        LD V8, 0x40
+        LD V9, 1
+        CALL check
+
+        LD V0, 0x80
+        SHR V0
+        LD V8, 0x40
+        LD V9, 0
+        CALL check
+
+        LD V0, 0x81
+        SHL V0
+        LD V8, 0x02
+        LD V9, 1
+        CALL check
+
+        LD V0, 0x41
+        SHL V0
+        LD V8, 0x82
+        LD V9, 0
+        CALL check
+
+        ; With VF as VX, the flag overwrites the result
+        LD VF, 0xF0
+        LD V1, 0x20
+        ADD VF, V1
+        LD V0, VF
+        LD V8, 1
+        LD V9, 1
+        CALL check
+
+        LD VF, 0x10
+        LD V1, 0x30
+        SUB VF, V1
+        LD V0, VF
+        LD V8, 0
+        LD V9, 0
+        CALL check
+
+        ; VF as VY is read before the flag is written
+        LD V0, 0x10
+        LD VF, 0x20
+        ADD V0, VF
+        LD V8, 0x30
+        LD V9, 0
+        CALL check
+
+        ; 7XNN leaves VF alone
+        LD VF, 5
+        LD V0, 0xFF
+        ADD V0, 2
+        LD V8, 0x01
+        LD V9, 5
+        CALL check
+
+end:    JP end
+
+        include "check.asm"
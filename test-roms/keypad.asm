@@ -0,0 +1,40 @@
+; FX0A, EX9E and EXA1 against the input script in tests/conformance.rs: key 5 is held during
+; frames 5-9, key A during frames 20-24.
+        LD VC, 0
+        LD VD, 0
+
+        ; FX0A waits for key 5
+        LD V0, K
+        LD V8, 5
+        LD V9, VF
+        CALL check
+
+        ; EX9E skips while it is held, EXA1 does not
+        LD V1, 5
+        LD V0, 1
+        SKP V1
+        LD V0, 0
+        LD V8, 1
+        LD V9, VF
+        CALL check
+
+        LD V0, 1
+        SKNP V1
+        LD V0, 0
+        LD V8, 0
+        LD V9, VF
+        CALL check
+
+        ; EXA1 skips once it is released
+up:     SKNP V1
+        JP up
+
+        ; FX0A ignores the released key and waits for key A
+        LD V0, K
+        LD V8, 0xA
+        LD V9, VF
+        CALL check
+
+end:    JP end
+
+        include "check.asm"
@@ -0,0 +1,79 @@
+; Shows one digit per quirk, 1 when the interpreter behaves as the quirk describes:
+; vf_reset, load_store_increments_i, shift_uses_vy, jump_uses_vx, clip_sprites and
+; display_wait, from left to right.
+        LD VC, 4
+        LD VD, 4
+
+        ; vf_reset: OR clears VF
+        LD VF, 1
+        OR V0, V1
+        LD V0, 1
+        SUB V0, VF
+        CALL show
+
+        ; load_store_increments_i: the second load reads the next byte
+        LD I, data
+        LD V0, [I]
+        LD V0, [I]
+        CALL show
+
+        ; shift_uses_vy: V0 = V1 >> 1
+        LD V0, 0
+        LD V1, 2
+        SHR V0, V1
+        CALL show
+
+        ; jump_uses_vx: BXNN adds VX, X being the high nibble of the table address
+        LD V0, 0
+        LD V2, 2
+        LD V3, 2
+        JP V0, table
+table:  JP vx_off
+        JP vx_on
+vx_off: LD V0, 0
+        JP jumped
+vx_on:  LD V0, 1
+jumped: CALL show
+
+        ; clip_sprites: a line drawn across the right edge does not reach x=0
+        LD I, line
+        LD V0, 60
+        LD V1, 30
+        LD V2, 0
+        DRW V0, V1, 1
+        DRW V2, V1, 1
+        LD V3, VF
+        DRW V2, V1, 1
+        DRW V0, V1, 1
+        LD V0, 1
+        SUB V0, V3
+        CALL show
+
+        ; display_wait: three draws right after a timer tick span three frames
+        LD V0, 1
+        LD DT, V0
+sync:   LD V0, DT
+        SE V0, 0
+        JP sync
+        LD V0, 5
+        LD DT, V0
+        LD I, blank
+        DRW VE, VE, 1
+        DRW VE, VE, 1
+        DRW VE, VE, 1
+        LD V1, DT
+        LD V0, 3
+        SUB V0, V1
+        LD V0, VF
+        CALL show
+
+end:    JP end
+
+show:   LD F, V0
+        DRW VC, VD, 5
+        ADD VC, 8
+        RET
+
+data:   db 0, 1
+line:   db 0xFF
+blank:  db 0
@@ -0,0 +1,63 @@
+; S-CHIP instructions in the 128x64 mode: big font, 16x16 sprites, RPL flags and scrolling.
+; Everything but the last check mark is scrolled 4 pixels down and right.
+        HIGH
+        LD VC, 0
+        LD VD, 0
+
+        ; FX30: big 5 and C
+        LD V2, 0
+        LD V0, 5
+        LD HF, V0
+        LD V1, 40
+        DRW V1, V2, 10
+        LD V0, 0xC
+        LD HF, V0
+        LD V1, 50
+        DRW V1, V2, 10
+
+        ; DXY0: a 16x16 sprite, drawn a second time to collide with itself
+        LD I, box
+        LD V1, 64
+        DRW V1, V2, 0
+        DRW V1, V2, 0
+        LD V0, 0
+        LD V8, 0
+        LD V9, 1
+        CALL check
+        LD I, box
+        DRW V1, V2, 0
+
+        ; FX75/FX85: registers survive a round trip through the RPL flags
+        LD V0, 0x12
+        LD V1, 0x34
+        LD R, V1
+        LD V0, 0
+        LD V1, 0
+        LD V1, R
+        LD V9, VF
+        LD V8, 0x12
+        CALL check
+        LD V0, V1
+        LD V8, 0x34
+        LD V9, VF
+        CALL check
+
+        ; 00CN, 00FB, 00FC
+        SCD 4
+        SCR
+        SCR
+        SCL
+
+        LD V0, 0
+        LD V8, 0
+        LD V9, VF
+        CALL check
+
+end:    JP end
+
+box:    dw 0xFFFF
+        dw 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001
+        dw 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001
+        dw 0xFFFF
+
+        include "check.asm"
@@ -0,0 +1,153 @@
+//! The assembler emits the opcodes and data the source describes, and locates its errors.
+
+use std::{env, fs, path::PathBuf};
+
+use chip8::asm::{self, Assembly};
+
+fn assemble(source: &str) -> Assembly {
+    asm::assemble_str(source, "test.asm").expect("source should assemble")
+}
+
+fn error(source: &str) -> String {
+    asm::assemble_str(source, "test.asm")
+        .expect_err("source should not assemble")
+        .to_string()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("chip8-{}-{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn constants() {
+    const SOURCE: &str = "\
+BASE    equ 0x300
+SPEED   equ 2
+OFFSET  equ BASE + SPEED - 1
+        LD I, OFFSET
+        ADD V0, SPEED
+";
+
+    assert_eq!(assemble(SOURCE).rom, [0xA3, 0x01, 0x70, 0x02]);
+    assert!(error("X equ 1\nX equ 2\n").starts_with("test.asm:2: Duplicate symbol: X"));
+    assert!(error("X equ Y\nY equ 1\n").starts_with("test.asm:1: Undefined symbol: Y"));
+}
+
+#[test]
+fn data_expressions() {
+    const SOURCE: &str = "\
+start:  JP end
+bytes:  db 1, 0x10 + 2, 0b101, -1 + 2
+words:  dw start, end - 2, 0xBEEF
+end:    JP start
+";
+
+    let assembly = assemble(SOURCE);
+    assert_eq!(
+        assembly.rom,
+        [0x12, 0x0C, 0x01, 0x12, 0x05, 0x01, 0x02, 0x00, 0x02, 0x0A, 0xBE, 0xEF, 0x12, 0x00]
+    );
+    assert_eq!(
+        assembly.labels,
+        [
+            ("start".to_string(), 0x200),
+            ("bytes".to_string(), 0x202),
+            ("words".to_string(), 0x206),
+            ("end".to_string(), 0x20C),
+        ]
+    );
+    assert!(assembly.source_map[1].data && !assembly.source_map[3].data);
+}
+
+#[test]
+fn superchip_opcodes() {
+    const SOURCE: &str = "\
+        scd 4
+        SCR
+        SCL
+        EXIT
+        LOW
+        HIGH
+        LD HF, V3
+        LD R, V7
+        LD V7, R
+        DRW V1, V2, 0
+";
+
+    let words: Vec<u16> = assemble(SOURCE)
+        .rom
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    assert_eq!(
+        words,
+        [0x00C4, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0xF330, 0xF775, 0xF785, 0xD120]
+    );
+}
+
+#[test]
+fn includes() {
+    let dir = temp_dir("asm-include");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(
+        dir.join("main.asm"),
+        "start:  LD I, sprite\n        JP start\n        include \"lib/sprites.asm\"\n",
+    )
+    .unwrap();
+    // Nested includes are relative to the including file
+    fs::write(
+        dir.join("lib/sprites.asm"),
+        "sprite: db 0xFF\n        include \"more.asm\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("lib/more.asm"), "        db 0x81, bad_symbol\n").unwrap();
+
+    let err = asm::assemble_file(&dir.join("main.asm")).unwrap_err();
+    let message = err.to_string();
+
+    fs::write(dir.join("lib/more.asm"), "        db 0x81\n").unwrap();
+    let assembly = asm::assemble_file(&dir.join("main.asm"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(
+        message.contains("more.asm:1: Undefined symbol: bad_symbol"),
+        "{}",
+        message
+    );
+    let assembly = assembly.expect("source should assemble");
+    assert_eq!(assembly.rom, [0xA2, 0x04, 0x12, 0x00, 0xFF, 0x81]);
+    assert!(assembly.source_map[3].file.ends_with("lib/more.asm"));
+}
+
+#[test]
+fn recursive_include() {
+    let dir = temp_dir("asm-recursive");
+    fs::write(dir.join("loop.asm"), "include \"loop.asm\"\n").unwrap();
+    let err = asm::assemble_file(&dir.join("loop.asm")).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(err.to_string().contains("Recursive include"), "{}", err);
+}
+
+#[test]
+fn errors_report_file_and_line() {
+    assert!(error("CLS\nFOO V0\n").starts_with("test.asm:2: Unknown mnemonic: FOO"));
+    assert!(error("\n\nLD I, V0\n").starts_with("test.asm:3: Invalid operands for LD"));
+    assert!(error("LD V0, 0x100\n").starts_with("test.asm:1: Byte out of range"));
+    assert!(error("DRW V0, V1, 16\n").starts_with("test.asm:1: Nibble out of range"));
+    assert!(error("JP nowhere\n").starts_with("test.asm:1: Undefined symbol: nowhere"));
+    assert!(error("a: CLS\na: CLS\n").starts_with("test.asm:2: Duplicate symbol: a"));
+    assert!(error("db 1 +\n").starts_with("test.asm:1: Invalid expression"));
+}
+
+#[test]
+fn program_must_fit_in_memory() {
+    // 0xE00 bytes are free above 0x200
+    let fits = "db 0\n".repeat(0xE00);
+    assert_eq!(assemble(&fits).rom.len(), 0xE00);
+
+    let too_big = "db 0\n".repeat(0xE01);
+    assert!(error(&too_big).contains(&format!("test.asm:{}: Program does not fit", 0xE01)));
+}
@@ -0,0 +1,150 @@
+//! The synthesized tone sustains with the requested pitch, volume and waveform, and XO-CHIP
+//! audio patterns play at their pitch.
+
+mod common;
+
+use chip8::audio::{self, Sound, Synth, Tone, Waveform};
+use chip8::quirks::Quirks;
+use common::{BASE, XOCHIP};
+
+const SAMPLE_RATE: u32 = 8000;
+
+fn tone(waveform: Waveform) -> Tone {
+    Tone {
+        frequency: 1000.0,
+        volume: 0.5,
+        waveform,
+    }
+}
+
+#[test]
+fn square_wave() {
+    let mut synth = Synth::new(tone(Waveform::Square), SAMPLE_RATE);
+    let mut buffer = [0; 16];
+    synth.fill(&mut buffer, Sound::Tone);
+
+    // 8 samples per period, half high and half low
+    let high = i16::MAX / 2;
+    assert_eq!(
+        buffer[..8],
+        [high, high, high, high, -high, -high, -high, -high]
+    );
+    assert_eq!(buffer[8..], buffer[..8]);
+
+    // The phase carries over to the next buffer
+    let mut next = [0; 4];
+    synth.fill(&mut next, Sound::Tone);
+    assert_eq!(next, [high; 4]);
+}
+
+#[test]
+fn silence_when_stopped() {
+    let mut synth = Synth::new(tone(Waveform::Sine), SAMPLE_RATE);
+    let mut buffer = [1; 16];
+    synth.fill(&mut buffer, Sound::Silent);
+    assert_eq!(buffer, [0; 16]);
+
+    synth.fill(&mut buffer, Sound::Tone);
+    assert_eq!(buffer[0], 0);
+    assert!(buffer[2] > 16000 && buffer[6] < -16000);
+}
+
+#[test]
+fn waveforms() {
+    assert_eq!("saw".parse::<Waveform>().unwrap(), Waveform::Sawtooth);
+    assert!("noise".parse::<Waveform>().is_err());
+
+    for waveform in [Waveform::Triangle, Waveform::Sawtooth] {
+        let mut synth = Synth::new(tone(waveform), SAMPLE_RATE);
+        let mut buffer = [0; 8];
+        synth.fill(&mut buffer, Sound::Tone);
+        assert!(buffer.iter().all(|&s| s.abs() <= i16::MAX / 2));
+        assert!(buffer.iter().any(|&s| s > 0) && buffer.iter().any(|&s| s < 0));
+    }
+}
+
+#[test]
+fn pattern_bits() {
+    // At the default pitch, 4000 bits per second make 2 samples per bit
+    let pattern = [0b1010_0000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+    let mut synth = Synth::new(tone(Waveform::Sine), SAMPLE_RATE);
+    let mut buffer = [0; 256];
+    synth.fill(&mut buffer, Sound::Pattern { pattern, pitch: 64 });
+
+    let high = i16::MAX / 2;
+    assert_eq!(
+        buffer[..8],
+        [high, high, -high, -high, high, high, -high, -high]
+    );
+    assert!(buffer[8..254].iter().all(|&s| s == -high));
+    assert_eq!(buffer[254..], [high, high]);
+
+    // The pattern loops
+    let mut next = [0; 2];
+    synth.fill(&mut next, Sound::Pattern { pattern, pitch: 64 });
+    assert_eq!(next, [high; 2]);
+}
+
+#[test]
+fn pattern_pitch() {
+    assert_eq!(audio::pattern_rate(64), 4000.0);
+    assert_eq!(audio::pattern_rate(112), 8000.0);
+    assert_eq!(audio::pattern_rate(16), 2000.0);
+
+    // An octave up, every bit lasts one sample
+    let pattern = [0b1010_1010; 16];
+    let mut synth = Synth::new(tone(Waveform::Square), SAMPLE_RATE);
+    let mut buffer = [0; 4];
+    synth.fill(
+        &mut buffer,
+        Sound::Pattern {
+            pattern,
+            pitch: 112,
+        },
+    );
+    let high = i16::MAX / 2;
+    assert_eq!(buffer, [high, -high, high, -high]);
+}
+
+#[test]
+fn sound_of_the_vm() {
+    const SOURCE: &str = "\
+        LD V0, 2
+        LD ST, V0
+        LD I, pattern
+        db 0xF0, 0x02   ; AUDIO (XO-CHIP)
+        LD V1, 100
+        db 0xF1, 0x3A   ; PITCH V1 (XO-CHIP)
+pattern:
+        db 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0
+        db 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0
+";
+    let rom = common::assemble(SOURCE).rom;
+    let mut chip8 = common::load(&rom, XOCHIP, Quirks::xochip());
+    assert_eq!(Sound::of(&chip8), Sound::Silent);
+
+    // The tone plays until a pattern is loaded
+    chip8.tick().unwrap();
+    chip8.tick().unwrap();
+    assert_eq!(Sound::of(&chip8), Sound::Tone);
+    for _ in 0..4 {
+        chip8.tick().unwrap();
+    }
+    assert_eq!(
+        Sound::of(&chip8),
+        Sound::Pattern {
+            pattern: [0xF0; 16],
+            pitch: 100
+        }
+    );
+
+    chip8.tick_timers();
+    chip8.tick_timers();
+    assert_eq!(Sound::of(&chip8), Sound::Silent);
+
+    // Without XO-CHIP, the same state plays the tone
+    let mut chip8 = common::load(&rom[..4], BASE, Quirks::vip());
+    chip8.tick().unwrap();
+    chip8.tick().unwrap();
+    assert_eq!(Sound::of(&chip8), Sound::Tone);
+}
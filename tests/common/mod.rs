@@ -0,0 +1,52 @@
+//! Fixtures shared by the integration tests: assembling a source or reading a test ROM, and
+//! loading it on a VM.
+
+// Every test crate compiles this module, using only some of it
+#![allow(dead_code)]
+
+use std::{fs, path::PathBuf};
+
+use chip8::asm::{self, Assembly};
+use chip8::extensions::ActiveExtensions;
+use chip8::quirks::Quirks;
+use chip8::vm::Chip8VM;
+
+pub const BASE: ActiveExtensions = ActiveExtensions {
+    schip: false,
+    xochip: false,
+};
+pub const SCHIP: ActiveExtensions = ActiveExtensions {
+    schip: true,
+    xochip: false,
+};
+pub const XOCHIP: ActiveExtensions = ActiveExtensions {
+    schip: false,
+    xochip: true,
+};
+
+pub fn assemble(source: &str) -> Assembly {
+    asm::assemble_str(source, "test.asm").expect("source should assemble")
+}
+
+/// Path of `name` in the test-roms directory.
+pub fn test_rom_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test-roms")
+        .join(name)
+}
+
+pub fn read_test_rom(name: &str) -> Vec<u8> {
+    fs::read(test_rom_path(name)).expect("ROM should be readable")
+}
+
+/// A VM with `extensions` and `quirks`, `rom` loaded.
+pub fn load(rom: &[u8], extensions: ActiveExtensions, quirks: Quirks) -> Chip8VM {
+    let mut chip8 = Chip8VM::new(extensions.create(), quirks);
+    chip8.load(rom).expect("ROM should fit in memory");
+    chip8
+}
+
+/// A VM with `extensions` and `quirks`, `source` assembled and loaded.
+pub fn vm(source: &str, extensions: ActiveExtensions, quirks: Quirks) -> Chip8VM {
+    load(&assemble(source).rom, extensions, quirks)
+}
@@ -0,0 +1,121 @@
+//! Runs the bundled test ROMs headlessly and compares the final framebuffer against the
+//! golden images in `tests/golden`. Set `UPDATE_GOLDEN=1` to regenerate them after an
+//! intentional rendering change. ROMs given as `.asm` sources are assembled first.
+
+mod common;
+
+use std::{env, fs, path::PathBuf};
+
+use chip8::asm;
+use chip8::extensions::ActiveExtensions;
+use chip8::headless::{self, StopReason};
+use chip8::movie::{self, InputEvent};
+use chip8::quirks::Quirks;
+use chip8::timing::Timing;
+use chip8::vm::Chip8VM;
+use common::{BASE, SCHIP};
+
+fn manifest_path(relative: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn run_rom(rom: &str, extensions: ActiveExtensions, quirks: Quirks, frames: u64) -> Chip8VM {
+    run_rom_with_input(rom, extensions, quirks, frames, &[])
+}
+
+fn run_rom_with_input(
+    rom: &str,
+    extensions: ActiveExtensions,
+    quirks: Quirks,
+    frames: u64,
+    input: &[InputEvent],
+) -> Chip8VM {
+    let data = if rom.ends_with(".asm") {
+        asm::assemble_file(&common::test_rom_path(rom))
+            .expect("ROM should assemble")
+            .rom
+    } else {
+        common::read_test_rom(rom)
+    };
+    let mut chip8 = common::load(&data, extensions, quirks);
+
+    let outcome = headless::run(&mut chip8, frames, Timing::default(), &[], input);
+    assert!(
+        matches!(outcome.reason, StopReason::FrameLimit),
+        "{} stopped early: {:?}",
+        rom,
+        outcome.reason
+    );
+    chip8
+}
+
+fn assert_golden(name: &str, chip8: &Chip8VM) {
+    let path = manifest_path("tests/golden").join(format!("{}.pbm", name));
+    let actual = headless::render_pbm(chip8);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).expect("golden image should be writable");
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing golden image {}", path.display()));
+    assert!(
+        actual == expected,
+        "{} does not match {}, actual framebuffer:\n{}",
+        name,
+        path.display(),
+        headless::render_text(chip8)
+    );
+}
+
+#[test]
+fn chip8_logo() {
+    let chip8 = run_rom("1-chip8-logo.ch8", BASE, Quirks::vip(), 60);
+    assert_golden("1-chip8-logo", &chip8);
+}
+
+#[test]
+fn ibm_logo() {
+    let chip8 = run_rom("2-ibm-logo.ch8", BASE, Quirks::vip(), 60);
+    assert_golden("2-ibm-logo", &chip8);
+}
+
+#[test]
+fn corax_plus() {
+    let chip8 = run_rom("3-corax+.ch8", BASE, Quirks::vip(), 300);
+    assert_golden("3-corax+", &chip8);
+}
+
+#[test]
+fn test_rom_with_audio() {
+    let chip8 = run_rom("chip8-test-rom-with-audio.ch8", BASE, Quirks::vip(), 300);
+    assert_golden("chip8-test-rom-with-audio", &chip8);
+}
+
+#[test]
+fn flags() {
+    let chip8 = run_rom("flags.asm", BASE, Quirks::vip(), 60);
+    assert_golden("flags", &chip8);
+}
+
+#[test]
+fn quirks() {
+    for (name, quirks) in [("vip", Quirks::vip()), ("schip", Quirks::schip())] {
+        let chip8 = run_rom("quirks.asm", BASE, quirks, 60);
+        assert_golden(&format!("quirks-{}", name), &chip8);
+    }
+}
+
+#[test]
+fn keypad() {
+    let input = movie::parse_input_script("5 5 down\n10 5 up\n20 a down\n25 a up\n").unwrap();
+    let chip8 = run_rom_with_input("keypad.asm", BASE, Quirks::vip(), 40, &input);
+    assert_golden("keypad", &chip8);
+}
+
+#[test]
+fn superchip() {
+    let chip8 = run_rom("schip.asm", SCHIP, Quirks::schip(), 60);
+    assert_golden("schip", &chip8);
+}
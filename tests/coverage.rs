@@ -0,0 +1,108 @@
+//! Records coverage of an assembled program and renders it as a listing and as lcov.
+
+mod common;
+
+use chip8::asm::Assembly;
+use chip8::conf::START_ADDR;
+use chip8::coverage::{Coverage, SkipOutcomes};
+use chip8::extensions::ActiveExtensions;
+use chip8::headless;
+use chip8::quirks::Quirks;
+use common::BASE;
+
+// The second skip is always taken, so `LD V1, 1` never runs
+const SOURCE: &str = "\
+start:  LD V0, 2
+loop:   ADD V0, 0xFF
+        SE V0, 0
+        JP loop
+        SE V0, 0
+        LD V1, 1
+end:    JP end
+data:   db 1, 2, 3
+";
+
+fn run() -> (Coverage, Assembly) {
+    let assembly = common::assemble(SOURCE);
+    let mut chip8 = common::load(&assembly.rom, BASE, Quirks::vip());
+
+    chip8.set_coverage(Some(Coverage::new()));
+    for _ in 0..12 {
+        chip8.tick().expect("program should run");
+    }
+    (chip8.set_coverage(None).unwrap(), assembly)
+}
+
+#[test]
+fn records_executions_and_skips() {
+    let (coverage, _) = run();
+    assert_eq!(coverage.hits(0x202), 2);
+    assert_eq!(coverage.hits(0x20A), 0);
+    assert_eq!(coverage.hits(0x20C), 5);
+    assert_eq!(coverage.executed(), 6);
+
+    assert_eq!(
+        coverage.skip(0x204),
+        Some(SkipOutcomes {
+            taken: 1,
+            not_taken: 1
+        })
+    );
+    assert_eq!(
+        coverage.skip(0x208),
+        Some(SkipOutcomes {
+            taken: 1,
+            not_taken: 0
+        })
+    );
+    assert_eq!(coverage.skip(0x206), None);
+    assert_eq!(coverage.skip_outcomes(), (3, 4));
+}
+
+#[test]
+fn listing() {
+    let (coverage, assembly) = run();
+    let listing = headless::render_coverage_listing(
+        &coverage,
+        &assembly.rom,
+        START_ADDR,
+        &ActiveExtensions::default(),
+    );
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(
+        lines[2],
+        "       2  0204: 30 00        SE V0, 0x00  [taken 1, not taken 1]"
+    );
+    assert_eq!(lines[5], "   #####  020A: 61 01        LD V1, 0x01");
+    assert_eq!(lines[6], "       5  020C: 12 0C        JP 0x20C");
+    assert_eq!(lines.last(), Some(&"       -  0210: 03           DB 0x03"));
+}
+
+#[test]
+fn lcov() {
+    let (coverage, assembly) = run();
+    let lcov = headless::render_lcov(&coverage, &assembly, &ActiveExtensions::default());
+    assert_eq!(
+        lcov,
+        "\
+TN:
+SF:test.asm
+DA:1,1
+DA:2,2
+DA:3,2
+BRDA:3,0,0,1
+BRDA:3,0,1,1
+DA:4,1
+DA:5,1
+BRDA:5,0,0,1
+BRDA:5,0,1,0
+DA:6,0
+DA:7,5
+BRF:4
+BRH:3
+LF:7
+LH:6
+end_of_record
+"
+    );
+}
@@ -0,0 +1,288 @@
+//! Drives the DAP server over a local TCP socket, with source breakpoints mapped through the
+//! assembler's source map.
+
+mod common;
+
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use chip8::dap::DapServer;
+use chip8::debugger::Debugger;
+use chip8::quirks::Quirks;
+use chip8::timing::Timing;
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+const SOURCE: &str = "\
+start:  LD V0, 1
+        CALL sub
+        ADD V0, 1
+loop:   JP loop
+sub:    LD V1, 5
+        RET
+";
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: u64,
+    events: VecDeque<Value>,
+}
+
+impl Client {
+    fn read_message(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Sends a request and returns its response, queueing the events sent before it.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        loop {
+            let message = self.read_message();
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                assert_eq!(message["command"], command);
+                return message;
+            }
+            self.events.push_back(message);
+        }
+    }
+
+    fn event(&mut self, event: &str) -> Value {
+        let message = match self.events.pop_front() {
+            Some(message) => message,
+            None => self.read_message(),
+        };
+        assert_eq!(message["event"], event, "unexpected message {}", message);
+        message["body"].clone()
+    }
+
+    fn stopped(&mut self, reason: &str) {
+        assert_eq!(self.event("stopped")["reason"], reason);
+    }
+
+    /// Top frame name and source line.
+    fn location(&mut self) -> (Value, Value) {
+        let trace = &self.request("stackTrace", json!({ "threadId": 1 }))["body"];
+        let top = &trace["stackFrames"][0];
+        (top["name"].clone(), top["line"].clone())
+    }
+}
+
+/// Serves the assembled `SOURCE` to a client running `script` on its own thread, and returns the VM
+/// once the session ends.
+fn session(script: impl FnOnce(&mut Client) + Send + 'static) -> Chip8VM {
+    let assembly = common::assemble(SOURCE);
+    let mut chip8 = common::load(&assembly.rom, BASE, Quirks::vip());
+    let mut debugger = Debugger::new();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            seq: 0,
+            events: VecDeque::new(),
+        };
+        script(&mut client);
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nodelay(true).unwrap();
+    let result = DapServer::new(&mut chip8, &mut debugger, Some(assembly), Timing::default())
+        .serve(stream.try_clone().unwrap(), stream);
+    if let Err(panic) = client.join() {
+        std::panic::resume_unwind(panic);
+    }
+    result.expect("session should end cleanly");
+    chip8
+}
+
+/// Runs the handshake, stopping on entry.
+fn launch(client: &mut Client, breakpoints: Value) -> Value {
+    let init = client.request("initialize", json!({ "adapterID": "chip8" }));
+    assert_eq!(init["body"]["supportsReadMemoryRequest"], true);
+    client.event("initialized");
+
+    assert_eq!(
+        client.request("launch", json!({ "stopOnEntry": true }))["success"],
+        true
+    );
+    let reply = client.request(
+        "setBreakpoints",
+        json!({ "source": { "path": "test.asm" }, "breakpoints": breakpoints }),
+    );
+    client.request("configurationDone", json!({}));
+    client.stopped("entry");
+    reply["body"]["breakpoints"].clone()
+}
+
+#[test]
+fn source_breakpoints_and_stepping() {
+    session(|client| {
+        let breakpoints = launch(client, json!([{ "line": 5 }, { "line": 9 }]));
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[0]["instructionReference"], "0x0208");
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        client.request("continue", json!({ "threadId": 1 }));
+        client.stopped("breakpoint");
+        assert_eq!(client.location(), (json!("sub"), json!(5)));
+        let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(trace["body"]["stackFrames"][1]["name"], "main");
+        assert_eq!(trace["body"]["stackFrames"][1]["line"], 2);
+
+        client.request("stepOut", json!({ "threadId": 1 }));
+        client.stopped("step");
+        assert_eq!(client.location(), (json!("main"), json!(3)));
+
+        client.request("next", json!({ "threadId": 1 }));
+        client.stopped("step");
+        assert_eq!(client.location().1, json!(4));
+
+        client.request("disconnect", json!({}));
+    });
+}
+
+#[test]
+fn next_steps_over_calls() {
+    session(|client| {
+        launch(client, json!([]));
+
+        client.request("next", json!({ "threadId": 1 }));
+        client.stopped("step");
+        client.request("next", json!({ "threadId": 1 }));
+        client.stopped("step");
+        assert_eq!(client.location(), (json!("main"), json!(3)));
+
+        let reply = client.request("stepOut", json!({ "threadId": 1 }));
+        assert_eq!(reply["success"], false);
+
+        client.request("disconnect", json!({}));
+    });
+}
+
+#[test]
+fn variables_and_memory() {
+    let chip8 = session(|client| {
+        launch(client, json!([{ "line": 3, "condition": "V1 == 5" }]));
+        client.request("continue", json!({ "threadId": 1 }));
+        client.stopped("breakpoint");
+
+        let scopes = client.request("scopes", json!({ "frameId": 0 }));
+        let registers = scopes["body"]["scopes"][0]["variablesReference"].clone();
+        let variables = client.request("variables", json!({ "variablesReference": registers }));
+        let variables = &variables["body"]["variables"];
+        assert_eq!(
+            variables[0],
+            json!({ "name": "V0", "value": "0x01", "variablesReference": 0 })
+        );
+        assert_eq!(variables[1]["value"], "0x05");
+        assert_eq!(variables[17]["name"], "PC");
+        assert_eq!(variables[17]["value"], "0x0204");
+
+        let reply = client.request(
+            "setVariable",
+            json!({ "variablesReference": registers, "name": "V3", "value": "0x2A" }),
+        );
+        assert_eq!(reply["body"]["value"], "0x2A");
+        let reply = client.request(
+            "setVariable",
+            json!({ "variablesReference": registers, "name": "VG", "value": "1" }),
+        );
+        assert_eq!(reply["success"], false);
+
+        let reply = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0200", "count": 4 }),
+        );
+        assert_eq!(reply["body"]["data"], "YAEiCA==");
+        let reply = client.request(
+            "readMemory",
+            json!({ "memoryReference": "0x0FFE", "count": 4 }),
+        );
+        assert_eq!(reply["body"]["unreadableBytes"], 2);
+        for arguments in [
+            json!({ "memoryReference": "0x0200", "count": u64::MAX }),
+            json!({ "memoryReference": "0x0200", "offset": i64::MAX, "count": 1 }),
+        ] {
+            assert_eq!(client.request("readMemory", arguments)["success"], false);
+        }
+        let reply = client.request(
+            "writeMemory",
+            json!({ "memoryReference": "0x0300", "offset": 1, "data": "3q2+7w==" }),
+        );
+        assert_eq!(reply["body"]["bytesWritten"], 4);
+
+        let reply = client.request(
+            "disassemble",
+            json!({ "memoryReference": "0x0202", "instructionCount": 2 }),
+        );
+        let instructions = &reply["body"]["instructions"];
+        assert_eq!(instructions[0]["instruction"], "CALL 0x208");
+        assert_eq!(instructions[1]["line"], 3);
+
+        client.request("continue", json!({ "threadId": 1 }));
+        client.request("pause", json!({ "threadId": 1 }));
+        client.stopped("pause");
+
+        client.request("disconnect", json!({}));
+    });
+
+    let state = chip8.get_state();
+    assert_eq!(state.registers[3], 0x2A);
+    assert_eq!(state.memory[0x301..0x305], [0xDE, 0xAD, 0xBE, 0xEF]);
+}
+
+#[test]
+fn oversized_messages_are_skipped() {
+    session(|client| {
+        let body = " ".repeat(2 << 20);
+        write!(
+            client.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        launch(client, json!([]));
+        client.request("disconnect", json!({}));
+    });
+}
@@ -0,0 +1,524 @@
+//! The debugger commands, driven the way the emulator's main loop drives them.
+
+mod common;
+
+use chip8::debugger::{Condition, DebugAction, Debugger};
+use chip8::quirks::Quirks;
+use chip8::symbols::SymbolTable;
+use chip8::vm::Chip8VM;
+use common::{BASE, XOCHIP};
+
+// Runs forever, storing, loading and drawing V3 through memory: buffer is at 0x212, digits
+// at 0x216
+const LOOP: &str = "\
+start:  LD V3, 0
+loop:   ADD V3, 8
+        LD I, buffer
+        LD [I], V3
+        LD V0, [I]
+        LD I, digits
+        LD B, V3
+        DRW V0, V0, 3
+        JP loop
+buffer: db 0, 0, 0, 0
+digits: db 0, 0, 0
+";
+
+// Bounds `resume` so a missed stop fails instead of hanging
+const MAX_STEPS: usize = 1000;
+
+/// Loads `source` with its labels known to the debugger.
+fn setup(source: &str) -> (Chip8VM, Debugger) {
+    let assembly = common::assemble(source);
+    let chip8 = common::load(&assembly.rom, BASE, Quirks::schip());
+    let mut debugger = Debugger::new();
+    debugger.set_symbols(SymbolTable::from_labels(&assembly.labels));
+    (chip8, debugger)
+}
+
+fn command(debugger: &mut Debugger, chip8: &Chip8VM, line: &str) -> DebugAction {
+    debugger
+        .parse_and_execute(line, chip8.get_state())
+        .unwrap_or_else(|e| panic!("`{}` failed: {}", line, e))
+}
+
+/// Runs like the main loop after `continue`: stops on a breakpoint or `next`/`finish`/`until`
+/// target before an instruction, except the one it resumes from, or on a watchpoint hit after
+/// it. Returns why it stopped.
+fn resume(debugger: &mut Debugger, chip8: &mut Chip8VM) -> String {
+    for step in 0..MAX_STEPS {
+        if step > 0 {
+            if debugger.should_break(chip8.get_state()) {
+                debugger.clear_target();
+                return "Breakpoint".to_string();
+            }
+            if debugger.target_reached(chip8.get_state()) {
+                return "Target".to_string();
+            }
+        }
+        debugger.execute(chip8).expect("instruction should execute");
+        if let Some(hit) = debugger.check_watchpoints(chip8.get_state(), chip8.last_accesses()) {
+            debugger.clear_target();
+            return hit;
+        }
+    }
+    panic!("no stop after {} steps", MAX_STEPS);
+}
+
+fn pc(chip8: &Chip8VM) -> u16 {
+    chip8.get_state().pc
+}
+
+#[test]
+fn conditions() {
+    let mut chip8 = Chip8VM::new(Vec::new(), Quirks::default());
+    chip8.set_register(3, 0x10).unwrap();
+    chip8.set_i(0x300);
+    let cpu = chip8.get_state();
+
+    let holds = |condition: &str| Condition::parse(condition).unwrap().eval(cpu);
+    assert!(holds("V3 == 0x10") && holds("v3==16"));
+    assert!(holds("I >= 0x300") && holds("I <= 768") && !holds("I > 0x300"));
+    assert!(holds("PC != 0x202") && holds("DT < 1") && holds("SP == ST"));
+    assert!(holds("0x0F < V3") && !holds("V2 == V3"));
+
+    assert_eq!(
+        Condition::parse("v3==16").unwrap().to_string(),
+        "V3 == 0x10"
+    );
+    assert!(Condition::parse("V3 0x10").is_err());
+    assert!(Condition::parse("VG == 1").is_err());
+    assert!(Condition::parse("V3 ==").is_err());
+}
+
+#[test]
+fn conditional_breakpoint() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+    command(&mut debugger, &chip8, "break loop if V3 == 0x18");
+
+    assert_eq!(resume(&mut debugger, &mut chip8), "Breakpoint");
+    assert_eq!(chip8.get_state().registers[3], 0x18);
+
+    // `clear` drops the breakpoint, condition included
+    command(&mut debugger, &chip8, "clear loop");
+    command(&mut debugger, &chip8, "until loop");
+    assert_eq!(resume(&mut debugger, &mut chip8), "Target");
+    assert_eq!(chip8.get_state().registers[3], 0x20);
+}
+
+#[test]
+fn memory_watchpoints() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+    // FX55 stores V0-V3, the watched byte being the last one
+    command(&mut debugger, &chip8, "watch 0x0215");
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: 0x0212..0x0215 written"
+    );
+    assert_eq!(pc(&chip8), 0x208);
+    command(&mut debugger, &chip8, "unwatch 0");
+
+    // FX65 reads V0 back, DXYN the digits
+    command(&mut debugger, &chip8, "rwatch buffer");
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: 0x0212..0x0212 read"
+    );
+    command(&mut debugger, &chip8, "unwatch 0");
+    command(&mut debugger, &chip8, "rwatch digits 3");
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: 0x0216..0x0218 read"
+    );
+    assert_eq!(pc(&chip8), 0x210);
+    command(&mut debugger, &chip8, "unwatch 0");
+
+    // FX33 writes the digits, which `awatch` catches before DXYN reads them
+    command(&mut debugger, &chip8, "awatch digits");
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: 0x0216..0x0218 written"
+    );
+    assert_eq!(pc(&chip8), 0x20E);
+
+    assert!(debugger
+        .parse_and_execute("watch buffer 0", chip8.get_state())
+        .is_err());
+    assert!(debugger
+        .parse_and_execute("unwatch 1", chip8.get_state())
+        .is_err());
+}
+
+#[test]
+fn bcd_out_of_memory() {
+    let (mut chip8, mut debugger) = setup("LD I, 0xFFE\nLD B, V3\n");
+    command(&mut debugger, &chip8, "watch 0xFFE 2");
+    debugger.execute(&mut chip8).unwrap();
+
+    let err = debugger.execute(&mut chip8).unwrap_err();
+    assert_eq!(err.to_string(), "Memory store out of bounds");
+    assert!(debugger
+        .check_watchpoints(chip8.get_state(), chip8.last_accesses())
+        .is_none());
+}
+
+#[test]
+fn register_watchpoints() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+
+    command(&mut debugger, &chip8, "watch V3");
+    // LD V3, 0 leaves V3 unchanged
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: V3 changed 0x0 -> 0x8"
+    );
+    command(&mut debugger, &chip8, "unwatch 0");
+
+    command(&mut debugger, &chip8, "watch I");
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: I changed 0x0 -> 0x212"
+    );
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: I changed 0x212 -> 0x216"
+    );
+
+    // Only a collision changes VF, DXYN writing 0 the first times
+    command(&mut debugger, &chip8, "unwatch 0");
+    command(&mut debugger, &chip8, "watch vf");
+    assert_eq!(
+        resume(&mut debugger, &mut chip8),
+        "Watchpoint: VF changed 0x0 -> 0x1"
+    );
+    assert_eq!(pc(&chip8), 0x210);
+}
+
+// Nested subroutines, each bumping a register
+const CALLS: &str = "\
+start:  CALL outer
+        LD V0, 1
+end:    JP end
+outer:  CALL inner
+        ADD V1, 1
+        RET
+inner:  ADD V2, 1
+        RET
+";
+
+#[test]
+fn next_steps_over_calls() {
+    let (mut chip8, mut debugger) = setup(CALLS);
+
+    assert!(matches!(
+        command(&mut debugger, &chip8, "next"),
+        DebugAction::Continue
+    ));
+    assert_eq!(resume(&mut debugger, &mut chip8), "Target");
+    assert_eq!(pc(&chip8), 0x202);
+    assert_eq!(chip8.get_state().sp, 0);
+    assert_eq!(chip8.get_state().registers[1..3], [1, 1]);
+
+    // Anything but a CALL is a single step
+    assert!(matches!(
+        command(&mut debugger, &chip8, "n"),
+        DebugAction::Step
+    ));
+}
+
+#[test]
+fn next_stops_at_a_breakpoint_in_the_call() {
+    let (mut chip8, mut debugger) = setup(CALLS);
+
+    command(&mut debugger, &chip8, "break inner");
+    command(&mut debugger, &chip8, "next");
+    assert_eq!(resume(&mut debugger, &mut chip8), "Breakpoint");
+    assert_eq!(pc(&chip8), 0x20C);
+
+    // The breakpoint dropped the pending `next`
+    command(&mut debugger, &chip8, "clear inner");
+    command(&mut debugger, &chip8, "until end");
+    assert_eq!(resume(&mut debugger, &mut chip8), "Target");
+    assert_eq!(pc(&chip8), 0x204);
+}
+
+#[test]
+fn finish_returns_from_the_current_frame() {
+    let (mut chip8, mut debugger) = setup(CALLS);
+    assert!(debugger
+        .parse_and_execute("finish", chip8.get_state())
+        .is_err());
+
+    // Into inner, two frames deep
+    debugger.execute(&mut chip8).unwrap();
+    debugger.execute(&mut chip8).unwrap();
+    assert_eq!(chip8.get_state().sp, 2);
+
+    command(&mut debugger, &chip8, "finish");
+    assert_eq!(resume(&mut debugger, &mut chip8), "Target");
+    assert_eq!((pc(&chip8), chip8.get_state().sp), (0x208, 1));
+    assert_eq!(chip8.get_state().registers[1..3], [0, 1]);
+
+    command(&mut debugger, &chip8, "f");
+    assert_eq!(resume(&mut debugger, &mut chip8), "Target");
+    assert_eq!((pc(&chip8), chip8.get_state().sp), (0x202, 0));
+}
+
+#[test]
+fn until_runs_to_an_address() {
+    let (mut chip8, mut debugger) = setup(CALLS);
+
+    command(&mut debugger, &chip8, "until 0x20E");
+    assert_eq!(resume(&mut debugger, &mut chip8), "Target");
+    assert_eq!(pc(&chip8), 0x20E);
+    assert_eq!(chip8.get_state().registers[2], 1);
+
+    assert!(debugger
+        .parse_and_execute("until", chip8.get_state())
+        .is_err());
+    assert!(debugger
+        .parse_and_execute("until nowhere", chip8.get_state())
+        .is_err());
+}
+
+#[test]
+fn frames() {
+    let (mut chip8, debugger) = setup(CALLS);
+    chip8.tick().unwrap();
+    chip8.tick().unwrap();
+
+    let frames = Debugger::frames(chip8.get_state());
+    let summary: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.pc, frame.entry, frame.ret))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (0x20C, Some(0x20C), None),
+            (0x206, Some(0x206), Some(0x208)),
+            (0x200, None, Some(0x202)),
+        ]
+    );
+    debugger.show_backtrace(chip8.get_state(), &chip8.active_extensions());
+}
+
+#[test]
+fn show_frame_at_the_end_of_xochip_memory() {
+    let mut chip8 = Chip8VM::new(XOCHIP.create(), Quirks::xochip());
+    chip8.set_pc(0xFFFE).unwrap();
+
+    let debugger = Debugger::new();
+    debugger.show_frame(chip8.get_state(), 0, &chip8.active_extensions());
+}
+
+#[test]
+fn set_registers() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+
+    for (line, register, value) in [
+        ("set V3 0x20", "V3", 0x20),
+        ("set vf 255", "VF", 0xFF),
+        ("set I 0x300", "I", 0x300),
+        ("set pc 0x202", "PC", 0x202),
+    ] {
+        let DebugAction::SetRegister(reg, val) = command(&mut debugger, &chip8, line) else {
+            panic!("`{}` should set a register", line);
+        };
+        assert_eq!((reg.to_string().as_str(), val), (register, value));
+    }
+
+    chip8.set_register(3, 0x20).unwrap();
+    chip8.set_i(0x300);
+    chip8.set_pc(0x202).unwrap();
+    chip8.tick().unwrap();
+    assert_eq!(chip8.get_state().registers[3], 0x28);
+    assert_eq!(chip8.get_state().i_register, 0x300);
+
+    for line in ["set V3 0x100", "set VG 1", "set SP 1", "set I"] {
+        assert!(
+            debugger.parse_and_execute(line, chip8.get_state()).is_err(),
+            "{}",
+            line
+        );
+    }
+    assert!(chip8.set_pc(0x0FFF).is_err());
+    assert_eq!(chip8.get_state().pc, 0x204);
+}
+
+#[test]
+fn poke_and_fill() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+
+    let DebugAction::Poke(addr, bytes) = command(&mut debugger, &chip8, "poke buffer 1 0x02 3")
+    else {
+        panic!("poke should write memory");
+    };
+    assert_eq!((addr, bytes.as_slice()), (0x212, &[1, 2, 3][..]));
+    chip8.write_memory(addr, &bytes).unwrap();
+
+    let DebugAction::Fill(addr, len, byte) = command(&mut debugger, &chip8, "fill 0x300 0x10 0xAA")
+    else {
+        panic!("fill should write memory");
+    };
+    assert_eq!((addr, len, byte), (0x300, 16, 0xAA));
+    chip8.write_memory(addr, &vec![byte; len]).unwrap();
+
+    let memory = &chip8.get_state().memory;
+    assert_eq!(memory[0x212..0x216], [1, 2, 3, 0]);
+    assert!(memory[0x300..0x310].iter().all(|&byte| byte == 0xAA));
+    assert_eq!(memory[0x310], 0);
+
+    for line in [
+        "poke buffer",
+        "poke buffer 0x100",
+        "fill 0x300 16",
+        "fill 0x300 1 256",
+    ] {
+        assert!(
+            debugger.parse_and_execute(line, chip8.get_state()).is_err(),
+            "{}",
+            line
+        );
+    }
+
+    // Nothing is written past the end of memory
+    assert!(chip8.write_memory(0xFFE, &[1, 2, 3]).is_err());
+    assert_eq!(chip8.get_state().memory[0xFFE..], [0, 0]);
+}
+
+// Changes memory, both planes, the RPL flags, the plane selection, the audio pattern and
+// pitch, and the random generator
+const STATEFUL: &str = "\
+        LD V0, 5
+        LD I, data
+        LD [I], V0
+        LD F, V0
+        DRW V1, V1, 5
+        LD R, V0
+        db 0xF2, 0x01   ; PLANE 2
+        DRW V1, V1, 5
+        LD I, pattern
+        db 0xF0, 0x02   ; AUDIO
+        LD V2, 100
+        db 0xF2, 0x3A   ; PITCH V2
+        RND V3, 0xFF
+end:    JP end
+data:   db 0
+pattern: db 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0
+        db 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0, 0xF0
+";
+
+#[test]
+fn rstep_undoes_every_change() {
+    let mut chip8 = common::vm(STATEFUL, XOCHIP, Quirks::xochip());
+    let mut debugger = Debugger::new();
+
+    let mut states = Vec::new();
+    while chip8.get_state().pc != 0x21A {
+        states.push(chip8.save_state());
+        debugger.execute(&mut chip8).unwrap();
+    }
+    assert_eq!(states.len(), 13);
+
+    while let Some(state) = states.pop() {
+        assert!(matches!(
+            command(&mut debugger, &chip8, "rstep"),
+            DebugAction::ReverseStep
+        ));
+        assert_eq!(debugger.reverse_step(&mut chip8), Ok(None));
+        assert!(
+            chip8.save_state() == state,
+            "state differs after undoing the instruction at 0x{:03X}",
+            chip8.get_state().pc
+        );
+    }
+    assert!(debugger.reverse_step(&mut chip8).is_err());
+
+    // The undone instructions run the same way again
+    for _ in 0..13 {
+        debugger.execute(&mut chip8).unwrap();
+    }
+    let (_, _, screen) = chip8.get_display_config();
+    assert!(screen.contains(&0b11));
+    assert_eq!(chip8.get_state().pitch, 100);
+}
+
+#[test]
+fn rstep_reports_watchpoints() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+    command(&mut debugger, &chip8, "until loop");
+    resume(&mut debugger, &mut chip8);
+    command(&mut debugger, &chip8, "until loop");
+    resume(&mut debugger, &mut chip8);
+
+    // Back over JP, DXYN, FX33, LD I and FX65, which reads the buffer without writing it
+    command(&mut debugger, &chip8, "watch buffer");
+    for _ in 0..5 {
+        assert_eq!(debugger.reverse_step(&mut chip8), Ok(None));
+    }
+    assert_eq!(chip8.get_state().memory[0x215], 0x08);
+
+    assert_eq!(
+        debugger.reverse_step(&mut chip8),
+        Ok(Some("Watchpoint: 0x0212..0x0215 written".to_string()))
+    );
+    assert_eq!(pc(&chip8), 0x206);
+    assert_eq!(chip8.get_state().memory[0x215], 0);
+}
+
+#[test]
+fn rcontinue_stops_at_breakpoints_and_watchpoints() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+    command(&mut debugger, &chip8, "break loop if V3 == 0x20");
+    resume(&mut debugger, &mut chip8);
+    command(&mut debugger, &chip8, "clear loop");
+
+    // Back before the last store into the buffer, which held the previous V3 then
+    command(&mut debugger, &chip8, "watch buffer 4");
+    assert!(matches!(
+        command(&mut debugger, &chip8, "rcontinue"),
+        DebugAction::ReverseContinue
+    ));
+    assert_eq!(
+        debugger.reverse_continue(&mut chip8),
+        "Watchpoint: 0x0212..0x0215 written"
+    );
+    assert_eq!(pc(&chip8), 0x206);
+    assert_eq!(chip8.get_state().memory[0x212..0x216], [0, 0, 0, 0x18]);
+    command(&mut debugger, &chip8, "unwatch 0");
+
+    command(&mut debugger, &chip8, "break loop if V3 == 0x08");
+    assert_eq!(
+        debugger.reverse_continue(&mut chip8),
+        "Breakpoint hit at 0x0202 <loop>"
+    );
+    assert_eq!(chip8.get_state().registers[3], 0x08);
+
+    command(&mut debugger, &chip8, "clear loop");
+    assert_eq!(
+        debugger.reverse_continue(&mut chip8),
+        "Reached the start of the history after 9 steps"
+    );
+    assert_eq!(pc(&chip8), 0x200);
+    // Every draw was undone
+    let (_, _, screen) = chip8.get_display_config();
+    assert!(screen.iter().all(|&pixel| pixel == 0));
+    assert_eq!(
+        debugger.reverse_continue(&mut chip8),
+        "No more reverse-execution history"
+    );
+}
+
+#[test]
+fn history_only_while_recording() {
+    let (mut chip8, mut debugger) = setup(LOOP);
+    debugger.set_recording(false);
+    debugger.execute(&mut chip8).unwrap();
+    assert!(debugger.reverse_step(&mut chip8).is_err());
+
+    debugger.set_recording(true);
+    debugger.execute(&mut chip8).unwrap();
+    assert!(debugger.reverse_step(&mut chip8).is_ok());
+    assert_eq!(pc(&chip8), 0x202);
+}
@@ -0,0 +1,60 @@
+//! CXNN results are reproducible from the seed and survive save states.
+
+mod common;
+
+use chip8::quirks::Quirks;
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+const SOURCE: &str = "\
+loop:   RND V0, 0xFF
+        JP loop
+";
+
+fn vm(seed: u64) -> Chip8VM {
+    let mut chip8 = common::vm(SOURCE, BASE, Quirks::vip());
+    chip8.set_seed(seed);
+    chip8
+}
+
+/// The next `count` random numbers drawn by the program.
+fn draw(chip8: &mut Chip8VM, count: usize) -> Vec<u8> {
+    (0..count)
+        .map(|_| {
+            chip8.tick().unwrap();
+            chip8.tick().unwrap();
+            chip8.get_state().registers[0]
+        })
+        .collect()
+}
+
+#[test]
+fn same_seed_same_numbers() {
+    let numbers = draw(&mut vm(42), 32);
+    assert_eq!(draw(&mut vm(42), 32), numbers);
+    assert_ne!(draw(&mut vm(43), 32), numbers);
+    assert_eq!(vm(42).seed(), 42);
+
+    // Not stuck on a few values
+    let mut distinct = numbers.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert!(distinct.len() > 16);
+
+    let mut chip8 = vm(42);
+    draw(&mut chip8, 10);
+    chip8.set_seed(42);
+    assert_eq!(draw(&mut chip8, 32), numbers);
+}
+
+#[test]
+fn save_states_keep_the_sequence() {
+    let mut chip8 = vm(7);
+    draw(&mut chip8, 5);
+    let state = chip8.save_state();
+    let expected = draw(&mut chip8, 16);
+
+    let mut other = vm(1234);
+    other.load_state(&state).unwrap();
+    assert_eq!(draw(&mut other, 16), expected);
+}
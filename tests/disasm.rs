@@ -0,0 +1,118 @@
+//! Listings of base and extension ROMs, checked against the source they were assembled from.
+
+mod common;
+
+use chip8::conf::START_ADDR;
+use chip8::disasm;
+use chip8::extensions::ActiveExtensions;
+use common::{BASE, SCHIP, XOCHIP};
+
+/// The mnemonics of the disassembly of `rom`.
+fn texts(rom: &[u8], ext: &ActiveExtensions) -> Vec<String> {
+    disasm::disassemble(rom, START_ADDR, ext)
+        .into_iter()
+        .map(|line| line.text)
+        .collect()
+}
+
+#[test]
+fn round_trip() {
+    // Written the way the disassembler prints it
+    const SOURCE: &str = "\
+CLS
+LD V0, 0x0A
+ADD V0, 0x01
+LD V1, V0
+SE V1, 0x0B
+SNE V1, V0
+LD I, 0x300
+DRW V0, V1, 5
+LD B, V1
+LD [I], V2
+LD V2, [I]
+SHR V3, V4
+SKNP VE
+LD F, V3
+RND VF, 0xFF
+JP V0, 0x300
+CALL 0x202
+RET
+JP 0x200
+";
+    let rom = common::assemble(SOURCE).rom;
+    assert_eq!(texts(&rom, &BASE), SOURCE.lines().collect::<Vec<_>>());
+
+    const SCHIP_SOURCE: &str = "\
+HIGH
+SCD 4
+SCR
+SCL
+LD HF, V1
+LD R, V7
+LD V7, R
+DRW V0, V1, 0
+LOW
+EXIT
+";
+    let rom = common::assemble(SCHIP_SOURCE).rom;
+    assert_eq!(
+        texts(&rom, &SCHIP),
+        SCHIP_SOURCE.lines().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn extension_opcodes() {
+    let rom = [0x00, 0xFF, 0x00, 0xD2, 0x51, 0x32];
+    assert_eq!(texts(&rom, &BASE), ["SYS 0x0FF", "SYS 0x0D2", "DW 0x5132"]);
+    assert_eq!(texts(&rom, &SCHIP), ["HIGH", "SYS 0x0D2", "DW 0x5132"]);
+    // XO-CHIP implies S-CHIP
+    assert_eq!(texts(&rom, &XOCHIP), ["HIGH", "SCU 2", "SAVE V1, V3"]);
+}
+
+#[test]
+fn long_load_and_trailing_byte() {
+    let rom = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0, 0xAB];
+    let lines = disasm::disassemble(&rom, START_ADDR, &XOCHIP);
+    assert_eq!(
+        disasm::format_listing(&lines),
+        "\
+0200: F0 00 12 34  LD I, 0x1234
+0204: 00 E0        CLS
+0206: AB           DB 0xAB
+"
+    );
+
+    // Without the address word, F000 is data
+    assert_eq!(texts(&rom[..2], &XOCHIP), ["DW 0xF000"]);
+    assert_eq!(texts(&rom[..3], &XOCHIP), ["DW 0xF000", "DB 0x12"]);
+    // Not an XO-CHIP ROM, F000 is unknown and the word after it decoded on its own
+    assert_eq!(texts(&rom[..4], &SCHIP), ["DW 0xF000", "JP 0x234"]);
+}
+
+#[test]
+fn single_instructions() {
+    assert_eq!(
+        disasm::disassemble_op(0xD015, None, &BASE),
+        ("DRW V0, V1, 5".to_string(), 2)
+    );
+    assert_eq!(
+        disasm::disassemble_op(0xF000, Some(0xABCD), &XOCHIP),
+        ("LD I, 0xABCD".to_string(), 4)
+    );
+
+    let memory = [0x00, 0xE0, 0xF0, 0x00, 0x02, 0x00];
+    assert_eq!(
+        disasm::disassemble_at(&memory, 2, &XOCHIP),
+        ("LD I, 0x0200".to_string(), 4)
+    );
+    assert_eq!(
+        disasm::disassemble_at(&memory, 0, &BASE),
+        ("CLS".to_string(), 2)
+    );
+    // Past the end of memory
+    assert_eq!(
+        disasm::disassemble_at(&memory, 5, &BASE),
+        ("??".to_string(), 2)
+    );
+}
@@ -0,0 +1,117 @@
+//! A VM runs against any `Frontend`, without raylib.
+
+mod common;
+
+use chip8::audio::Sound;
+use chip8::frontend::Frontend;
+use chip8::movie::InputEvent;
+use chip8::quirks::Quirks;
+use chip8::timing::{Clock, Timing};
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+// Beeps for about as many frames as the value of the key pressed, and shows its digit
+const SOURCE: &str = "\
+loop:   LD V1, K
+        LD ST, V1
+        CLS
+        LD F, V1
+        DRW V2, V2, 5
+        JP loop
+";
+
+const FRAMES: u64 = 30;
+
+/// Holds key 6 during frames 2-4 and records what the VM shows and plays.
+#[derive(Default)]
+struct ScriptedFrontend {
+    frame: u64,
+    presented: Vec<bool>,
+    playing: Vec<bool>,
+}
+
+impl Frontend for ScriptedFrontend {
+    fn should_close(&self) -> bool {
+        self.frame >= FRAMES
+    }
+
+    fn frame_time(&self) -> f32 {
+        1.0 / 60.0
+    }
+
+    fn is_key_down(&self, key: u8) -> bool {
+        key == 6 && (2..5).contains(&self.frame)
+    }
+
+    fn present(&mut self, chip8: &Chip8VM) {
+        let (_, _, screen) = chip8.get_display_config();
+        self.presented.push(screen.iter().any(|&pixel| pixel != 0));
+        self.frame += 1;
+    }
+
+    fn update_audio(&mut self, sound: Sound) {
+        self.playing.push(sound != Sound::Silent);
+    }
+}
+
+fn run(frontend: &mut impl Frontend) -> Chip8VM {
+    let mut chip8 = common::vm(SOURCE, BASE, Quirks::vip());
+    chip8.set_input_log(Some(Vec::new()));
+
+    let mut clock = Clock::new(Timing::default());
+    while !frontend.should_close() {
+        frontend.update_audio(Sound::of(&chip8));
+        frontend.update_keypad(&mut chip8).unwrap();
+
+        clock.start_frame();
+        while clock.can_run() {
+            let instruction = chip8.peek_instruction();
+            chip8.tick().unwrap();
+            clock.charge(&chip8, instruction);
+        }
+        chip8.tick_timers();
+
+        frontend.present(&chip8);
+    }
+    chip8
+}
+
+#[test]
+fn keypad_follows_the_frontend() {
+    let mut chip8 = run(&mut ScriptedFrontend::default());
+
+    let events = chip8.set_input_log(None).unwrap();
+    assert_eq!(
+        events,
+        [
+            InputEvent {
+                frame: 2,
+                key: 6,
+                pressed: true
+            },
+            InputEvent {
+                frame: 5,
+                key: 6,
+                pressed: false
+            },
+        ]
+    );
+}
+
+#[test]
+fn presents_and_plays_every_frame() {
+    let mut frontend = ScriptedFrontend::default();
+    run(&mut frontend);
+
+    assert_eq!(frontend.presented.len(), FRAMES as usize);
+    assert_eq!(frontend.playing.len(), FRAMES as usize);
+    assert!(!frontend.presented[0] && *frontend.presented.last().unwrap());
+
+    // A single beep starts once the key is handled and stops before the end
+    let beep: Vec<usize> = (0..FRAMES as usize)
+        .filter(|&frame| frontend.playing[frame])
+        .collect();
+    assert!(beep.len() >= 6 && beep[0] > 2);
+    assert!(beep.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    assert!(!frontend.playing.last().unwrap());
+}
@@ -0,0 +1,189 @@
+//! Drives the GDB stub over a local TCP socket with a minimal remote protocol client.
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use chip8::debugger::Debugger;
+use chip8::gdbstub::GdbStub;
+use chip8::quirks::Quirks;
+use chip8::timing::Timing;
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream
+            .read_exact(&mut byte)
+            .expect("stub should answer");
+        byte[0]
+    }
+
+    /// Sends a packet and returns the stub's reply.
+    fn request(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |acc, byte| acc.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+', "packet {} not acknowledged", data);
+        self.read_reply()
+    }
+
+    fn read_reply(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let expected = data.iter().fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", expected)
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+}
+
+/// Serves the IBM logo ROM to `client`, which runs on its own thread, and returns the VM once
+/// the session ends.
+fn session(client: impl FnOnce(&mut Client) + Send + 'static) -> Chip8VM {
+    let mut chip8 = common::load(
+        &common::read_test_rom("2-ibm-logo.ch8"),
+        BASE,
+        Quirks::vip(),
+    );
+    let mut debugger = Debugger::new();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        client(&mut Client { stream });
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    let result = GdbStub::new(&mut chip8, &mut debugger, Timing::default()).serve(stream);
+    if let Err(panic) = client.join() {
+        std::panic::resume_unwind(panic);
+    }
+    result.expect("session should end cleanly");
+    chip8
+}
+
+fn pc(client: &mut Client) -> String {
+    client.request("p11")
+}
+
+#[test]
+fn registers() {
+    let chip8 = session(|client| {
+        assert_eq!(client.request("?"), "S05");
+        // V0-VF, then I, PC (0x200 little-endian), SP, DT and ST
+        let regs = client.request("g");
+        assert_eq!(
+            regs,
+            "00".repeat(16) + "0000" + "0002" + "0000" + "00" + "00"
+        );
+
+        assert_eq!(client.request("P3=2a"), "OK");
+        assert_eq!(client.request("p3"), "2a");
+        assert_eq!(client.request("P10=3412"), "OK");
+        assert_eq!(client.request("p10"), "3412");
+        assert_eq!(client.request("P13=3c"), "OK");
+        assert_eq!(client.request("p13"), "3c");
+        assert_eq!(client.request("P12=1100"), "E01");
+        assert_eq!(client.request("p15"), "E01");
+
+        let mut regs = client.request("g");
+        regs.replace_range(0..2, "07");
+        assert_eq!(client.request(&format!("G{}", regs)), "OK");
+        assert_eq!(client.request("p0"), "07");
+
+        assert_eq!(client.request("D"), "OK");
+    });
+    let state = chip8.get_state();
+    assert_eq!(state.registers[0], 7);
+    assert_eq!(state.registers[3], 0x2A);
+    assert_eq!(state.i_register, 0x1234);
+    assert_eq!(state.delay_timer, 0x3C);
+}
+
+#[test]
+fn memory() {
+    let chip8 = session(|client| {
+        assert_eq!(client.request("m200,4"), "00e0a22a");
+        assert_eq!(client.request("M300,3:deadbe"), "OK");
+        assert_eq!(client.request("m300,3"), "deadbe");
+        assert_eq!(client.request("mfff,2"), "E01");
+        assert_eq!(client.request("mffffffffffffffff,1"), "E01");
+        assert_eq!(
+            client.request("qXfer:features:read:target.xml:1,ffffffffffffffff"),
+            "E01"
+        );
+        assert!(client
+            .request("qXfer:features:read:target.xml:0,10")
+            .starts_with("m<?xml"));
+        assert_eq!(client.request("M300,2:de"), "E01");
+
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(chip8.get_state().memory[0x300..0x303], [0xDE, 0xAD, 0xBE]);
+}
+
+#[test]
+fn step_and_breakpoints() {
+    let chip8 = session(|client| {
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(pc(client), "0202");
+
+        assert_eq!(client.request("Z0,20a,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(pc(client), "0a02");
+        assert_eq!(client.request("p0"), "0c");
+
+        // Resuming from a breakpoint does not hit it again
+        assert_eq!(client.request("Z0,20e,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(pc(client), "0e02");
+
+        assert_eq!(client.request("z0,20e,2"), "OK");
+        assert_eq!(client.request("Z0,228,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(pc(client), "2802");
+
+        // Unsupported breakpoint kinds and packets get an empty reply
+        assert_eq!(client.request("Z2,300,1"), "");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+
+        client.stream.write_all(b"$k#6b").unwrap();
+    });
+    assert_eq!(chip8.get_state().pc, 0x228);
+}
+
+#[test]
+fn interrupt() {
+    let chip8 = session(|client| {
+        // The ROM ends in an endless loop, only an interrupt stops it
+        client.stream.write_all(b"$c#63").unwrap();
+        assert_eq!(client.read_byte(), b'+');
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.read_reply(), "S02");
+        assert_eq!(pc(client), "2802");
+
+        assert_eq!(client.request("D"), "OK");
+    });
+    assert_eq!(chip8.get_state().pc, 0x228);
+}
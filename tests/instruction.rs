@@ -0,0 +1,108 @@
+//! Every opcode decodes to the instruction of the active extensions, printed as the assembler
+//! reads it.
+
+mod common;
+
+use chip8::instruction::{Instruction, OpcodePattern};
+use common::{BASE, SCHIP, XOCHIP};
+
+/// Opcode, then its mnemonic without extensions, with S-CHIP and with XO-CHIP.
+const TABLE: &[(u16, [&str; 3])] = &[
+    (0x00E0, ["CLS", "CLS", "CLS"]),
+    (0x00EE, ["RET", "RET", "RET"]),
+    (0x0123, ["SYS 0x123", "SYS 0x123", "SYS 0x123"]),
+    (0x00C4, ["SYS 0x0C4", "SCD 4", "SCD 4"]),
+    (0x00D4, ["SYS 0x0D4", "SYS 0x0D4", "SCU 4"]),
+    (0x00FB, ["SYS 0x0FB", "SCR", "SCR"]),
+    (0x00FC, ["SYS 0x0FC", "SCL", "SCL"]),
+    (0x00FD, ["SYS 0x0FD", "EXIT", "EXIT"]),
+    (0x00FE, ["SYS 0x0FE", "LOW", "LOW"]),
+    (0x00FF, ["SYS 0x0FF", "HIGH", "HIGH"]),
+    (0x1ABC, ["JP 0xABC", "JP 0xABC", "JP 0xABC"]),
+    (0x2ABC, ["CALL 0xABC", "CALL 0xABC", "CALL 0xABC"]),
+    (0x3A12, ["SE VA, 0x12", "SE VA, 0x12", "SE VA, 0x12"]),
+    (0x4A12, ["SNE VA, 0x12", "SNE VA, 0x12", "SNE VA, 0x12"]),
+    (0x5AB0, ["SE VA, VB", "SE VA, VB", "SE VA, VB"]),
+    (0x5AB1, ["DW 0x5AB1", "DW 0x5AB1", "DW 0x5AB1"]),
+    (0x5AB2, ["DW 0x5AB2", "DW 0x5AB2", "SAVE VA, VB"]),
+    (0x5AB3, ["DW 0x5AB3", "DW 0x5AB3", "LOAD VA, VB"]),
+    (0x6A12, ["LD VA, 0x12", "LD VA, 0x12", "LD VA, 0x12"]),
+    (0x7A12, ["ADD VA, 0x12", "ADD VA, 0x12", "ADD VA, 0x12"]),
+    (0x8AB0, ["LD VA, VB", "LD VA, VB", "LD VA, VB"]),
+    (0x8AB1, ["OR VA, VB", "OR VA, VB", "OR VA, VB"]),
+    (0x8AB2, ["AND VA, VB", "AND VA, VB", "AND VA, VB"]),
+    (0x8AB3, ["XOR VA, VB", "XOR VA, VB", "XOR VA, VB"]),
+    (0x8AB4, ["ADD VA, VB", "ADD VA, VB", "ADD VA, VB"]),
+    (0x8AB5, ["SUB VA, VB", "SUB VA, VB", "SUB VA, VB"]),
+    (0x8AB6, ["SHR VA, VB", "SHR VA, VB", "SHR VA, VB"]),
+    (0x8AB7, ["SUBN VA, VB", "SUBN VA, VB", "SUBN VA, VB"]),
+    (0x8ABE, ["SHL VA, VB", "SHL VA, VB", "SHL VA, VB"]),
+    (0x8AB8, ["DW 0x8AB8", "DW 0x8AB8", "DW 0x8AB8"]),
+    (0x9AB0, ["SNE VA, VB", "SNE VA, VB", "SNE VA, VB"]),
+    (0x9AB1, ["DW 0x9AB1", "DW 0x9AB1", "DW 0x9AB1"]),
+    (0xAABC, ["LD I, 0xABC", "LD I, 0xABC", "LD I, 0xABC"]),
+    (0xBABC, ["JP V0, 0xABC", "JP V0, 0xABC", "JP V0, 0xABC"]),
+    (0xCA12, ["RND VA, 0x12", "RND VA, 0x12", "RND VA, 0x12"]),
+    (0xDAB5, ["DRW VA, VB, 5", "DRW VA, VB, 5", "DRW VA, VB, 5"]),
+    (0xDAB0, ["DRW VA, VB, 0", "DRW VA, VB, 0", "DRW VA, VB, 0"]),
+    (0xEA9E, ["SKP VA", "SKP VA", "SKP VA"]),
+    (0xEAA1, ["SKNP VA", "SKNP VA", "SKNP VA"]),
+    (0xEA00, ["DW 0xEA00", "DW 0xEA00", "DW 0xEA00"]),
+    (0xF000, ["DW 0xF000", "DW 0xF000", "LD I, LONG"]),
+    (0xF201, ["DW 0xF201", "DW 0xF201", "PLANE 2"]),
+    (0xF002, ["DW 0xF002", "DW 0xF002", "AUDIO"]),
+    (0xFA07, ["LD VA, DT", "LD VA, DT", "LD VA, DT"]),
+    (0xFA0A, ["LD VA, K", "LD VA, K", "LD VA, K"]),
+    (0xFA15, ["LD DT, VA", "LD DT, VA", "LD DT, VA"]),
+    (0xFA18, ["LD ST, VA", "LD ST, VA", "LD ST, VA"]),
+    (0xFA1E, ["ADD I, VA", "ADD I, VA", "ADD I, VA"]),
+    (0xFA29, ["LD F, VA", "LD F, VA", "LD F, VA"]),
+    (0xFA30, ["DW 0xFA30", "LD HF, VA", "LD HF, VA"]),
+    (0xFA33, ["LD B, VA", "LD B, VA", "LD B, VA"]),
+    (0xFA3A, ["DW 0xFA3A", "DW 0xFA3A", "PITCH VA"]),
+    (0xFA55, ["LD [I], VA", "LD [I], VA", "LD [I], VA"]),
+    (0xFA65, ["LD VA, [I]", "LD VA, [I]", "LD VA, [I]"]),
+    (0xFA75, ["DW 0xFA75", "LD R, VA", "LD R, VA"]),
+    (0xFA85, ["DW 0xFA85", "LD VA, R", "LD VA, R"]),
+    (0xFAFF, ["DW 0xFAFF", "DW 0xFAFF", "DW 0xFAFF"]),
+];
+
+#[test]
+fn decode_and_display() {
+    for &(opcode, expected) in TABLE {
+        for (ext, expected) in [BASE, SCHIP, XOCHIP].iter().zip(expected) {
+            assert_eq!(
+                Instruction::decode(opcode, ext).to_string(),
+                expected,
+                "{:04X} with {:?}",
+                opcode,
+                ext
+            );
+        }
+    }
+}
+
+#[test]
+fn decoded_fields() {
+    assert_eq!(
+        Instruction::decode(0xBA12, &BASE),
+        Instruction::JumpOffset { x: 0xA, nnn: 0xA12 }
+    );
+    assert_eq!(
+        Instruction::decode(0x5AB1, &XOCHIP),
+        Instruction::Unknown(0x5AB1)
+    );
+    assert_eq!(Instruction::decode(0x00FF, &BASE), Instruction::Sys(0x0FF));
+    assert_eq!(Instruction::decode(0xD120, &SCHIP).pattern(), "DXYN");
+    assert!(Instruction::decode(0xEA9E, &BASE).is_skip());
+    assert!(!Instruction::decode(0xF000, &XOCHIP).is_skip());
+}
+
+#[test]
+fn opcode_patterns() {
+    let pattern = OpcodePattern::parse("D??F").unwrap();
+    assert!(pattern.matches(0xD12F) && !pattern.matches(0xD12E));
+    assert!(OpcodePattern::parse("0x00FD").unwrap().matches(0x00FD));
+    assert!(OpcodePattern::parse("D??").is_err());
+    assert!(OpcodePattern::parse("G???").is_err());
+}
@@ -0,0 +1,128 @@
+//! Movies record the keypad input of a session and replay it to the same final state.
+
+mod common;
+
+use chip8::extensions::ActiveExtensions;
+use chip8::headless;
+use chip8::movie::{self, InputEvent, Movie, Replay};
+use chip8::quirks::Quirks;
+use chip8::timing::{Clock, Timing};
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+const SOURCE: &str = "\
+loop:   LD V1, K
+        RND V0, 0xFF
+        ADD V2, V0
+        ADD V3, V1
+        JP loop
+";
+
+const SCRIPT: &str = "\
+3 5 down
+5 5 up
+# Already released, not a change
+6 5 up
+10 A down
+12 A up
+20 1 down
+21 1 up
+";
+
+const FRAMES: u64 = 40;
+const TIMING: Timing = Timing::Fixed {
+    instructions_per_second: 700,
+};
+
+fn rom() -> Vec<u8> {
+    common::assemble(SOURCE).rom
+}
+
+fn vm(quirks: Quirks, seed: u64) -> Chip8VM {
+    let mut chip8 = common::load(&rom(), BASE, quirks);
+    chip8.set_seed(seed);
+    chip8
+}
+
+fn record() -> (Chip8VM, Movie) {
+    let mut chip8 = vm(Quirks::vip(), 99);
+    chip8.set_input_log(Some(Vec::new()));
+    let input = movie::parse_input_script(SCRIPT).unwrap();
+    headless::run(&mut chip8, FRAMES, TIMING, &[], &input);
+
+    let events = chip8.set_input_log(None).unwrap();
+    let movie = Movie::new(&rom(), &chip8, TIMING, events);
+    (chip8, movie)
+}
+
+#[test]
+fn records_key_changes() {
+    let (_, movie) = record();
+
+    assert_eq!(movie.frames, FRAMES);
+    assert_eq!(movie.seed, 99);
+    assert_eq!(movie.quirks, Quirks::vip());
+    assert_eq!(movie.timing, TIMING);
+    assert_eq!(movie.events.len(), 6);
+    assert_eq!(
+        movie.events[2],
+        InputEvent {
+            frame: 10,
+            key: 0xA,
+            pressed: true
+        }
+    );
+
+    let parsed = Movie::parse(&movie.to_string()).unwrap();
+    assert_eq!(parsed, movie);
+    assert!(movie.check_rom(&rom()).is_ok());
+    assert!(movie.check_rom(&[0x12, 0x00]).is_err());
+}
+
+#[test]
+fn replays_to_the_same_state() {
+    let (recorded, movie) = record();
+    assert_ne!(recorded.get_state().registers[2], 0);
+
+    // Headless
+    let mut chip8 = vm(movie.quirks, 1);
+    chip8.set_seed(movie.seed);
+    headless::run(&mut chip8, movie.frames, movie.timing, &[], &movie.events);
+    assert_eq!(chip8.get_state().registers, recorded.get_state().registers);
+
+    // Frame by frame, as the window does
+    let mut chip8 = vm(movie.quirks, movie.seed);
+    let mut replay = Replay::new(&movie);
+    let mut clock = Clock::new(movie.timing);
+    while !replay.is_finished(&chip8) {
+        replay.apply(&mut chip8).unwrap();
+        clock.start_frame();
+        while clock.can_run() {
+            let instruction = chip8.peek_instruction();
+            chip8.tick().unwrap();
+            clock.charge(&chip8, instruction);
+        }
+        chip8.tick_timers();
+    }
+    assert_eq!(chip8.get_state().registers, recorded.get_state().registers);
+}
+
+#[test]
+fn parse_errors() {
+    let (_, movie) = record();
+    let text = movie.to_string();
+
+    assert!(Movie::parse(&text.replace("seed 99\n", "")).is_err());
+    let err = Movie::parse(&text.replace("quirks", "quirks turbo")).unwrap_err();
+    assert_eq!(err.to_string(), "Line 5: Unknown quirk: turbo");
+
+    let schip = Movie {
+        extensions: ActiveExtensions {
+            schip: true,
+            xochip: false,
+        },
+        ..movie
+    };
+    assert!(schip.to_string().contains("extensions schip\n"));
+    assert_eq!(Movie::parse(&schip.to_string()).unwrap(), schip);
+}
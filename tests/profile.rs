@@ -0,0 +1,125 @@
+//! Profiles an assembled program with nested subroutine calls.
+
+mod common;
+
+use chip8::asm;
+use chip8::extensions::ActiveExtensions;
+use chip8::profile::{Profiler, SubroutineStats};
+use chip8::quirks::Quirks;
+use chip8::symbols::SymbolTable;
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+const SOURCE: &str = "\
+start:  LD V0, 3
+loop:   CALL sub
+        ADD V0, 0xFF
+        SE V0, 0
+        JP loop
+end:    JP end
+sub:    LD V1, 5
+        CALL leaf
+        RET
+leaf:   ADD V1, 1
+        RET
+";
+
+/// Runs `ticks` instructions of `SOURCE` with a profiler, returning it and the VM.
+fn profile(ticks: usize) -> (Profiler, Chip8VM, asm::Assembly) {
+    let assembly = common::assemble(SOURCE);
+    let mut chip8 = common::load(&assembly.rom, BASE, Quirks::vip());
+
+    chip8.set_profiler(Some(Profiler::new()));
+    for _ in 0..ticks {
+        chip8.tick().expect("program should run");
+    }
+    (chip8.set_profiler(None).unwrap(), chip8, assembly)
+}
+
+#[test]
+fn counts() {
+    // 27 instructions for the three loop iterations, then 13 jumps in place
+    let (profiler, _, _) = profile(40);
+    assert_eq!(profiler.instructions(), 40);
+
+    let hot_spots = profiler.hot_spots();
+    assert_eq!(hot_spots[0], (0x20A, 13));
+    assert_eq!(hot_spots[1], (0x202, 3));
+    assert_eq!(
+        hot_spots.iter().find(|(pc, _)| *pc == 0x208),
+        Some(&(0x208, 2))
+    );
+
+    let classes = profiler.classes();
+    assert_eq!(classes[0], ("1NNN", 15));
+    assert!(classes.contains(&("00EE", 6)));
+    assert!(classes.contains(&("7XNN", 6)));
+}
+
+#[test]
+fn subroutines() {
+    let (profiler, _, _) = profile(40);
+    assert_eq!(
+        profiler.subroutines(),
+        [
+            (
+                0x20C,
+                SubroutineStats {
+                    calls: 3,
+                    inclusive: 15
+                }
+            ),
+            (
+                0x212,
+                SubroutineStats {
+                    calls: 3,
+                    inclusive: 6
+                }
+            ),
+        ]
+    );
+
+    // Stopped inside `leaf`, both subroutines are timed up to now
+    let (profiler, _, _) = profile(5);
+    assert_eq!(
+        profiler.subroutines(),
+        [
+            (
+                0x20C,
+                SubroutineStats {
+                    calls: 1,
+                    inclusive: 3
+                }
+            ),
+            (
+                0x212,
+                SubroutineStats {
+                    calls: 1,
+                    inclusive: 1
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn report() {
+    let (profiler, chip8, assembly) = profile(40);
+    let symbols = SymbolTable::from_labels(&assembly.labels);
+    let report = profiler.report(
+        &chip8.get_state().memory,
+        &ActiveExtensions::default(),
+        &symbols,
+        2,
+    );
+
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "Profiled 40 instructions");
+    assert_eq!(
+        lines[5],
+        "         3   7.50%  0x0202 <loop>            CALL 0x20C"
+    );
+    assert!(report.contains("\nSubroutines:\n"));
+    assert!(report.contains("         3         15  37.50%  0x020C <sub>\n"));
+    assert!(report.contains("        15  37.50%  1NNN\n"));
+}
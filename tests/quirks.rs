@@ -0,0 +1,177 @@
+//! Every quirk switches between the behaviors of the interpreters it stands for.
+
+mod common;
+
+use chip8::conf::HI_RES_WIDTH;
+use chip8::quirks::Quirks;
+use chip8::vm::Chip8VM;
+use common::{BASE, XOCHIP};
+
+/// No quirk enabled, the behavior of the interpreter before quirks existed.
+fn none() -> Quirks {
+    Quirks::from_flags([]).unwrap()
+}
+
+fn with(name: &str) -> Quirks {
+    Quirks::from_flags([name]).unwrap()
+}
+
+/// Assembles `source` and executes its first `steps` instructions.
+fn run(source: &str, quirks: Quirks, steps: usize) -> Chip8VM {
+    let mut chip8 = common::vm(source, BASE, quirks);
+    for _ in 0..steps {
+        chip8.tick().expect("instruction should execute");
+    }
+    chip8
+}
+
+fn pixel(chip8: &Chip8VM, x: usize, y: usize) -> bool {
+    let (_, _, screen) = chip8.get_display_config();
+    screen[x + y * HI_RES_WIDTH] & 1 != 0
+}
+
+#[test]
+fn shift_uses_vy() {
+    const SOURCE: &str = "\
+        LD V1, 0x81
+        LD V2, 0x06
+        SHR V1, V2
+        LD V3, 0x81
+        SHL V3, V2
+";
+
+    let chip8 = run(SOURCE, with("shift_uses_vy"), 5);
+    let registers = chip8.get_state().registers;
+    assert_eq!(
+        (registers[1], registers[3], registers[0xF]),
+        (0x03, 0x0C, 0)
+    );
+
+    let chip8 = run(SOURCE, none(), 5);
+    let registers = chip8.get_state().registers;
+    assert_eq!(
+        (registers[1], registers[3], registers[0xF]),
+        (0x40, 0x02, 1)
+    );
+}
+
+#[test]
+fn load_store_increments_i() {
+    const SOURCE: &str = "\
+        LD I, 0x300
+        LD [I], V2
+        LD V0, [I]
+";
+
+    let chip8 = run(SOURCE, with("load_store_increments_i"), 3);
+    assert_eq!(chip8.get_state().i_register, 0x304);
+
+    let chip8 = run(SOURCE, none(), 3);
+    assert_eq!(chip8.get_state().i_register, 0x300);
+}
+
+#[test]
+fn load_store_increments_i_wraps() {
+    // XO-CHIP's 64KB memory makes I reach 0xFFFF
+    const SOURCE: &str = "\
+        db 0xF0, 0x00, 0xFF, 0xF0   ; LD I, 0xFFF0 (XO-CHIP)
+        LD VF, [I]
+        LD [I], VF
+";
+    let mut chip8 = common::vm(SOURCE, XOCHIP, Quirks::xochip());
+
+    chip8.tick().unwrap();
+    chip8.tick().unwrap();
+    assert_eq!(chip8.get_state().i_register, 0);
+    chip8.tick().unwrap();
+    assert_eq!(chip8.get_state().i_register, 0x10);
+}
+
+#[test]
+fn jump_uses_vx() {
+    const SOURCE: &str = "\
+        LD V0, 4
+        LD V2, 8
+        JP V0, 0x220
+";
+
+    let chip8 = run(SOURCE, with("jump_uses_vx"), 3);
+    assert_eq!(chip8.get_state().pc, 0x228);
+
+    let chip8 = run(SOURCE, none(), 3);
+    assert_eq!(chip8.get_state().pc, 0x224);
+}
+
+#[test]
+fn vf_reset() {
+    for op in ["OR", "AND", "XOR"] {
+        let source = format!("LD VF, 5\n{} V0, V1\n", op);
+
+        let chip8 = run(&source, with("vf_reset"), 2);
+        assert_eq!(chip8.get_state().registers[0xF], 0, "{}", op);
+
+        let chip8 = run(&source, none(), 2);
+        assert_eq!(chip8.get_state().registers[0xF], 5, "{}", op);
+    }
+}
+
+#[test]
+fn clip_sprites() {
+    // The top row of the 0 glyph is 4 pixels wide, starting 2 pixels from the right edge
+    const SOURCE: &str = "\
+        LD V0, 62
+        LD V1, 0
+        LD F, V1
+        DRW V0, V1, 1
+";
+
+    let chip8 = run(SOURCE, with("clip_sprites"), 4);
+    assert!(pixel(&chip8, 62, 0) && pixel(&chip8, 63, 0));
+    assert!(!pixel(&chip8, 0, 0) && !pixel(&chip8, 1, 0));
+
+    let chip8 = run(SOURCE, none(), 4);
+    assert!(pixel(&chip8, 62, 0) && pixel(&chip8, 63, 0));
+    assert!(pixel(&chip8, 0, 0) && pixel(&chip8, 1, 0));
+
+    // The starting position wraps either way
+    let wrapped = "LD V0, 66\nLD V1, 0\nLD F, V1\nDRW V0, V1, 1\n";
+    let chip8 = run(wrapped, with("clip_sprites"), 4);
+    assert!(pixel(&chip8, 2, 0) && pixel(&chip8, 5, 0));
+}
+
+#[test]
+fn display_wait() {
+    const SOURCE: &str = "\
+        DRW V0, V0, 1
+        DRW V0, V0, 1
+";
+
+    let mut chip8 = run(SOURCE, with("display_wait"), 2);
+    assert_eq!(chip8.get_state().pc, 0x202);
+    assert!(chip8.waiting_for_display());
+
+    // The next timer tick lets the second draw through
+    chip8.tick_timers();
+    chip8.tick().unwrap();
+    assert_eq!(chip8.get_state().pc, 0x204);
+    assert!(!chip8.waiting_for_display());
+
+    let chip8 = run(SOURCE, none(), 2);
+    assert_eq!(chip8.get_state().pc, 0x204);
+}
+
+#[test]
+fn presets() {
+    assert_eq!(Quirks::default(), Quirks::vip());
+    assert_eq!("SCHIP".parse::<Quirks>().unwrap(), Quirks::schip());
+    assert!("chip48".parse::<Quirks>().is_err());
+
+    for quirks in [Quirks::vip(), Quirks::schip(), Quirks::xochip()] {
+        let enabled = quirks
+            .flags()
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name));
+        assert_eq!(Quirks::from_flags(enabled).unwrap(), quirks);
+    }
+    assert!(Quirks::from_flags(["wrap_sprites"]).is_err());
+}
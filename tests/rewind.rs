@@ -0,0 +1,71 @@
+//! Rewinding restores whole frames, extension state included, within a memory budget.
+
+mod common;
+
+use chip8::quirks::Quirks;
+use chip8::rewind::RewindBuffer;
+use chip8::vm::Chip8VM;
+use common::XOCHIP;
+
+// Draws the 0 glyph on plane 1, then on plane 2 once the planes are switched
+const SOURCE: &str = "\
+        LD I, 0
+        DRW V0, V0, 5
+        db 0xF2, 0x01   ; PLANE 2 (XO-CHIP)
+        DRW V0, V0, 5
+";
+
+fn vm() -> Chip8VM {
+    common::vm(SOURCE, XOCHIP, Quirks::xochip())
+}
+
+fn planes(chip8: &Chip8VM) -> u8 {
+    let (_, _, screen) = chip8.get_display_config();
+    screen.iter().fold(0, |acc, pixel| acc | pixel)
+}
+
+#[test]
+fn restores_extension_state() {
+    let mut chip8 = vm();
+    let mut rewind = RewindBuffer::new(10, usize::MAX);
+    chip8.tick().unwrap();
+    chip8.tick().unwrap();
+    rewind.push(chip8.snapshot());
+    assert_eq!(planes(&chip8), 0b01);
+
+    chip8.tick().unwrap();
+    chip8.restore(rewind.pop().unwrap());
+    assert_eq!(chip8.get_state().pc, 0x204);
+
+    // Plane 1 is selected again, so drawing the glyph once more erases it
+    chip8.set_pc(0x206).unwrap();
+    chip8.tick().unwrap();
+    assert_eq!(planes(&chip8), 0);
+}
+
+#[test]
+fn bounded_by_frames_and_bytes() {
+    let mut chip8 = vm();
+    let size = chip8.snapshot().size();
+
+    let mut rewind = RewindBuffer::new(3, usize::MAX);
+    for _ in 0..5 {
+        rewind.push(chip8.snapshot());
+    }
+    assert_eq!(rewind.len(), 3);
+    assert_eq!(rewind.bytes(), 3 * size);
+
+    let mut rewind = RewindBuffer::new(600, 2 * size + size / 2);
+    for _ in 0..4 {
+        chip8.tick().unwrap();
+        rewind.push(chip8.snapshot());
+    }
+    assert_eq!(rewind.len(), 2);
+    // The newest frames are kept
+    chip8.restore(rewind.pop().unwrap());
+    assert_eq!(chip8.get_state().pc, 0x208);
+    assert_eq!(rewind.bytes(), size);
+
+    rewind.clear();
+    assert!(rewind.is_empty() && rewind.bytes() == 0);
+}
@@ -0,0 +1,103 @@
+//! Save states restore the whole machine, and invalid ones leave it untouched.
+
+mod common;
+
+use chip8::quirks::Quirks;
+use chip8::vm::Chip8VM;
+use common::XOCHIP;
+
+const SOURCE: &str = "\
+        LD V0, 1
+        LD V1, 2
+        db 0xF2, 0x01   ; PLANE 2 (XO-CHIP)
+        LD V2, 3
+";
+
+// PC follows the magic and version, then I, SP, the registers, the stack and the timers
+const PC_OFFSET: usize = 6;
+const SP_OFFSET: usize = 10;
+const STACK_OFFSET: usize = 28;
+const WIDTH_OFFSET: usize = 62;
+
+fn vm() -> Chip8VM {
+    common::vm(SOURCE, XOCHIP, Quirks::xochip())
+}
+
+#[test]
+fn round_trip() {
+    let mut chip8 = vm();
+    chip8.tick().unwrap();
+    let state = chip8.save_state();
+
+    chip8.tick().unwrap();
+    chip8.tick().unwrap();
+    chip8.load_state(&state).unwrap();
+    assert_eq!(chip8.get_state().pc, 0x202);
+    assert_eq!(chip8.get_state().registers[..2], [1, 0]);
+    assert_eq!(chip8.save_state(), state);
+}
+
+#[test]
+fn rejects_pc_out_of_memory() {
+    let mut chip8 = vm();
+    let mut state = chip8.save_state();
+    state[PC_OFFSET..PC_OFFSET + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+
+    let err = chip8.load_state(&state).unwrap_err();
+    assert!(err.to_string().contains("PC out of memory"), "{}", err);
+    assert_eq!(chip8.get_state().pc, 0x200);
+    chip8.tick().unwrap();
+}
+
+#[test]
+fn rejects_invalid_resolutions() {
+    let mut chip8 = vm();
+    for (width, height) in [(0u16, 32u16), (64, 0), (100, 50), (128, 32)] {
+        let mut state = chip8.save_state();
+        state[WIDTH_OFFSET..WIDTH_OFFSET + 2].copy_from_slice(&width.to_le_bytes());
+        state[WIDTH_OFFSET + 2..WIDTH_OFFSET + 4].copy_from_slice(&height.to_le_bytes());
+
+        let err = chip8.load_state(&state).unwrap_err();
+        assert!(err.to_string().contains("Invalid resolution"), "{}", err);
+    }
+
+    let mut state = chip8.save_state();
+    state[WIDTH_OFFSET..WIDTH_OFFSET + 2].copy_from_slice(&128u16.to_le_bytes());
+    state[WIDTH_OFFSET + 2..WIDTH_OFFSET + 4].copy_from_slice(&64u16.to_le_bytes());
+    chip8.load_state(&state).unwrap();
+    assert_eq!(chip8.get_display_config().0, 128);
+}
+
+#[test]
+fn rejects_return_addresses_out_of_memory() {
+    let mut chip8 = vm();
+    let mut state = chip8.save_state();
+    state[STACK_OFFSET..STACK_OFFSET + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    // Entries above SP are unused and not checked
+    chip8.load_state(&state).unwrap();
+
+    state[SP_OFFSET..SP_OFFSET + 2].copy_from_slice(&1u16.to_le_bytes());
+    let err = chip8.load_state(&state).unwrap_err();
+    assert!(
+        err.to_string().contains("Return address out of memory"),
+        "{}",
+        err
+    );
+
+    state[STACK_OFFSET..STACK_OFFSET + 2].copy_from_slice(&0x0FFEu16.to_le_bytes());
+    chip8.load_state(&state).unwrap();
+    assert_eq!(chip8.get_state().stack[0], 0x0FFE);
+}
+
+#[test]
+fn invalid_extension_state_leaves_the_vm_untouched() {
+    let mut chip8 = vm();
+    let mut state = chip8.save_state();
+    // The XO-CHIP plane selection is the last byte
+    *state.last_mut().unwrap() = 0xFF;
+
+    chip8.tick().unwrap();
+    let before = chip8.save_state();
+    assert!(chip8.load_state(&state).is_err());
+    assert_eq!(chip8.save_state(), before);
+}
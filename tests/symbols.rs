@@ -0,0 +1,75 @@
+//! Symbol files in both formats, and addresses described relative to their labels.
+
+use chip8::symbols::SymbolTable;
+
+#[test]
+fn addr_name_lines() {
+    let symbols = SymbolTable::parse(
+        "\
+# Generated by hand
+0x200 start
+2A4 draw_player
+
+; sprites
+0x0300 sprites
+",
+    )
+    .unwrap();
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.resolve("start"), Some(0x200));
+    assert_eq!(symbols.resolve("draw_player"), Some(0x2A4));
+    assert_eq!(symbols.name(0x300), Some("sprites"));
+    assert_eq!(symbols.resolve("missing"), None);
+}
+
+#[test]
+fn octo_definitions() {
+    let symbols = SymbolTable::parse(
+        "\
+: main 0x200
+:const SPEED 12
+: mask 0b1010
+",
+    )
+    .unwrap();
+    assert_eq!(symbols.resolve("main"), Some(0x200));
+    // Octo numbers are decimal unless prefixed
+    assert_eq!(symbols.resolve("SPEED"), Some(12));
+    assert_eq!(symbols.resolve("mask"), Some(0b1010));
+}
+
+#[test]
+fn errors_report_the_line() {
+    let err = SymbolTable::parse("0x200 start\n\nstart\n").unwrap_err();
+    assert!(err.to_string().starts_with("line 3:"), "{}", err);
+
+    let err = SymbolTable::parse("0x200 start\nzz oops\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 2: Invalid address: zz");
+
+    let err = SymbolTable::parse(": main twelve\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 1: Invalid address: twelve");
+
+    assert!(SymbolTable::parse("0x10000 too_far\n").is_err());
+}
+
+#[test]
+fn first_name_of_an_address_wins() {
+    let symbols = SymbolTable::parse("0x200 start\n0x200 main\n").unwrap();
+    assert_eq!(symbols.name(0x200), Some("start"));
+    // Both names resolve
+    assert_eq!(symbols.resolve("main"), Some(0x200));
+    assert_eq!(symbols.to_string(), "0x0200 main\n0x0200 start\n");
+}
+
+#[test]
+fn describe() {
+    let symbols = SymbolTable::parse("0x200 start\n0x210 loop\n").unwrap();
+    assert_eq!(symbols.describe(0x1FE), None);
+    assert_eq!(symbols.describe(0x200).as_deref(), Some("start"));
+    assert_eq!(symbols.describe(0x20E).as_deref(), Some("start+0xE"));
+    assert_eq!(symbols.describe(0x210).as_deref(), Some("loop"));
+    assert_eq!(symbols.describe(0xFFF).as_deref(), Some("loop+0xDEF"));
+
+    let names: Vec<(u16, &str)> = symbols.names_in(0x201..).collect();
+    assert_eq!(names, [(0x210, "loop")]);
+}
@@ -0,0 +1,100 @@
+//! The clock runs instructions at the configured rate against 60Hz timer frames.
+
+mod common;
+
+use chip8::debugger::Debugger;
+use chip8::instruction::Instruction;
+use chip8::quirks::Quirks;
+use chip8::timing::{self, Clock, FramePacer, Timing};
+use chip8::vm::Chip8VM;
+use common::BASE;
+
+/// Instructions executed in each of `frames` frames.
+fn run_frames(chip8: &mut Chip8VM, clock: &mut Clock, frames: usize) -> Vec<u64> {
+    (0..frames)
+        .map(|_| {
+            let mut executed = 0;
+            clock.start_frame();
+            while clock.can_run() {
+                let instruction = chip8.peek_instruction();
+                chip8.tick().unwrap();
+                clock.charge(chip8, instruction);
+                executed += 1;
+            }
+            chip8.tick_timers();
+            executed
+        })
+        .collect()
+}
+
+#[test]
+fn fixed_rate() {
+    let mut chip8 = common::vm("loop: ADD V0, 1\n JP loop\n", BASE, Quirks::vip());
+
+    let mut clock = Clock::new(Timing::default());
+    assert_eq!(run_frames(&mut chip8, &mut clock, 3), [10, 10, 10]);
+
+    // Fractional rates even out over a second
+    let mut clock = Clock::new("650".parse().unwrap());
+    let frames = run_frames(&mut chip8, &mut clock, 60);
+    assert_eq!(frames.iter().sum::<u64>(), 650);
+    assert!(frames.iter().all(|&n| n == 10 || n == 11));
+}
+
+#[test]
+fn vip_costs() {
+    let mut chip8 = common::vm("loop: LD V0, 1\n JP loop\n", BASE, Quirks::vip());
+    let mut clock = Clock::new(Timing::Vip);
+    let frames = run_frames(&mut chip8, &mut clock, 60);
+
+    // 27µs + 105µs per iteration
+    let per_second = frames.iter().sum::<u64>();
+    assert!((1_000_000 / 132 * 2..=1_000_000 / 132 * 2 + 2).contains(&per_second));
+
+    assert!(
+        timing::vip_cost(Instruction::Draw { x: 0, y: 0, n: 15 })
+            > timing::vip_cost(Instruction::Draw { x: 0, y: 0, n: 1 })
+    );
+    assert_eq!("vip".parse::<Timing>().unwrap(), Timing::Vip);
+    assert!("0".parse::<Timing>().is_err());
+}
+
+#[test]
+fn display_wait_ends_the_frame() {
+    let source = "loop: DRW V0, V1, 1\n JP loop\n";
+    let mut chip8 = common::vm(source, BASE, Quirks::vip());
+    let mut clock = Clock::new(Timing::default());
+    // Draw, jump, then the second draw waits for the next frame
+    assert_eq!(run_frames(&mut chip8, &mut clock, 2), [3, 3]);
+
+    let mut chip8 = common::vm(source, BASE, Quirks::schip());
+    let mut clock = Clock::new(Timing::default());
+    assert_eq!(run_frames(&mut chip8, &mut clock, 2), [10, 10]);
+}
+
+#[test]
+fn stepping_keeps_the_frame_rate() {
+    let source = "LD V0, 30\n LD DT, V0\n loop: JP loop\n";
+    let mut chip8 = common::vm(source, BASE, Quirks::vip());
+    let mut debugger = Debugger::new();
+    let mut clock = Clock::new(Timing::default());
+    clock.start_frame();
+
+    // The timers tick once every 10 steps, not once per step
+    for _ in 0..22 {
+        clock.step(&mut chip8, &mut debugger).unwrap();
+    }
+    assert_eq!(chip8.frame(), 2);
+    assert_eq!(chip8.get_state().delay_timer, 28);
+}
+
+#[test]
+fn pacer() {
+    let mut pacer = FramePacer::new();
+    assert_eq!(pacer.advance(1.0 / 120.0), 0);
+    assert_eq!(pacer.advance(1.0 / 120.0), 1);
+    assert_eq!(pacer.advance(0.05), 3);
+    // Long stalls are not caught up
+    assert_eq!(pacer.advance(2.0), 4);
+    assert_eq!(pacer.advance(0.001), 0);
+}
@@ -0,0 +1,128 @@
+//! Traces the IBM logo ROM in both formats and checks the binary trace decodes to the text one.
+
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use chip8::instruction::OpcodePattern;
+use chip8::quirks::Quirks;
+use chip8::trace::{self, TraceFilter, TraceFormat, Tracer};
+use common::BASE;
+
+/// Runs the IBM logo ROM up to its final loop with a tracer writing to `path`.
+fn trace_ibm_logo(path: &Path, format: TraceFormat, filter: TraceFilter) -> u64 {
+    let mut chip8 = common::load(
+        &common::read_test_rom("2-ibm-logo.ch8"),
+        BASE,
+        Quirks::vip(),
+    );
+
+    let tracer = Tracer::create(path, format, filter, chip8.active_extensions())
+        .expect("trace file should be writable");
+    chip8.set_tracer(Some(tracer));
+    for _ in 0..21 {
+        chip8.tick().expect("ROM should run");
+        // Keep `display_wait` from holding back the draws
+        chip8.tick_timers();
+    }
+    chip8.set_tracer(None).unwrap().finish().unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("chip8-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn text_trace() {
+    let path = temp_path("text.trace");
+    assert_eq!(
+        trace_ibm_logo(&path, TraceFormat::Text, TraceFilter::default()),
+        21
+    );
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 21);
+    assert_eq!(lines[0], "0200  00E0  CLS");
+    assert_eq!(lines[1], "0202  A22A  LD I, 0x22A          I=022A");
+    assert_eq!(lines[4], "0208  D01F  DRW V0, V1, 15");
+    // The ROM ends in a jump to itself
+    assert_eq!(lines[20], "0228  1228  JP 0x228");
+}
+
+#[test]
+fn filters() {
+    let path = temp_path("filtered.trace");
+    let filter = TraceFilter {
+        ranges: vec![TraceFilter::parse_range("200-20F").unwrap()],
+        opcodes: vec![OpcodePattern::parse("D???").unwrap()],
+    };
+    assert_eq!(trace_ibm_logo(&path, TraceFormat::Text, filter), 2);
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let pcs: Vec<&str> = text.lines().map(|line| &line[..4]).collect();
+    assert_eq!(pcs, ["0208", "020E"]);
+
+    assert!(TraceFilter::parse_range("2FF-200").is_err());
+    assert_eq!(TraceFilter::parse_range("0x228").unwrap(), 0x228..=0x228);
+}
+
+#[test]
+fn binary_trace_matches_text() {
+    let text_path = temp_path("roundtrip.trace");
+    let binary_path = temp_path("roundtrip.bin");
+    trace_ibm_logo(&text_path, TraceFormat::Text, TraceFilter::default());
+    trace_ibm_logo(&binary_path, TraceFormat::Binary, TraceFilter::default());
+    let text = fs::read_to_string(&text_path).unwrap();
+    let binary = fs::read(&binary_path).unwrap();
+    fs::remove_file(&text_path).unwrap();
+    fs::remove_file(&binary_path).unwrap();
+
+    assert!(binary.len() < text.len() / 2);
+    let mut decoded = Vec::new();
+    assert_eq!(trace::dump_binary(&binary[..], &mut decoded).unwrap(), 21);
+    assert_eq!(String::from_utf8(decoded).unwrap(), text);
+
+    assert!(trace::dump_binary(&b"not a trace"[..], Vec::new()).is_err());
+    assert!(trace::dump_binary(&binary[..binary.len() - 1], Vec::new()).is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn write_error_stops_tracing() {
+    let mut chip8 = common::load(
+        &common::read_test_rom("2-ibm-logo.ch8"),
+        BASE,
+        Quirks::vip(),
+    );
+
+    // Every write to /dev/full fails, once the buffered lines are flushed
+    let tracer = Tracer::create(
+        Path::new("/dev/full"),
+        TraceFormat::Text,
+        TraceFilter::default(),
+        chip8.active_extensions(),
+    )
+    .expect("/dev/full should open");
+    chip8.set_tracer(Some(tracer));
+    for _ in 0..2000 {
+        chip8.tick().expect("a trace error should not stop the ROM");
+        chip8.tick_timers();
+    }
+
+    let err = chip8
+        .take_trace_error()
+        .expect("the write should have failed");
+    assert!(
+        format!("{:#}", err).contains("Failed to write trace"),
+        "{:#}",
+        err
+    );
+    assert!(chip8.set_tracer(None).is_none());
+    assert!(chip8.take_trace_error().is_none());
+}
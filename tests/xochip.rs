@@ -0,0 +1,166 @@
+//! The XO-CHIP opcodes, and the S-CHIP ones XO-CHIP keeps.
+
+mod common;
+
+use chip8::conf::{HI_RES_WIDTH, LARGE_FONT_BASE_ADDR};
+use chip8::quirks::Quirks;
+use chip8::vm::Chip8VM;
+use chip8::xochip::XoChip;
+use common::XOCHIP;
+
+/// Assembles `source` for XO-CHIP and executes its first `steps` instructions.
+fn run(source: &str, steps: usize) -> Chip8VM {
+    let mut chip8 = common::vm(source, XOCHIP, Quirks::xochip());
+    for _ in 0..steps {
+        chip8.tick().expect("instruction should execute");
+    }
+    chip8
+}
+
+#[test]
+fn schip_opcodes() {
+    const SOURCE: &str = "\
+        HIGH
+        LD V0, 0xAB
+        LD V1, 0xCD
+        LD R, V1
+        LD V0, 0
+        LD V1, 0
+        LD V1, R
+        LD V2, 3
+        LD HF, V2
+        LOW
+";
+
+    let chip8 = run(SOURCE, 1);
+    assert_eq!(chip8.get_display_config().0, HI_RES_WIDTH);
+
+    let chip8 = run(SOURCE, 10);
+    let state = chip8.get_state();
+    assert_eq!(state.registers[..2], [0xAB, 0xCD]);
+    assert_eq!(state.i_register, LARGE_FONT_BASE_ADDR + 30);
+    assert_eq!(chip8.get_display_config().0, 64);
+
+    // The extension set for XO-CHIP is XoChip on its own
+    let extensions = XOCHIP.create();
+    assert_eq!(extensions.len(), 1);
+    assert_eq!(extensions[0].name(), XoChip::NAME);
+}
+
+/// The planes lit at (`x`, `y`), plane 1 being the least significant bit.
+fn planes(chip8: &Chip8VM, x: usize, y: usize) -> u8 {
+    let (_, _, screen) = chip8.get_display_config();
+    screen[x + y * HI_RES_WIDTH]
+}
+
+#[test]
+fn select_planes() {
+    // The top rows of the 0 glyph are 0xF0 and 0x90
+    const SOURCE: &str = "\
+        db 0xF3, 0x01   ; PLANE 3
+        DRW V0, V0, 1
+        db 0xF1, 0x01   ; PLANE 1
+        CLS
+        db 0xF4, 0x01   ; PLANE 4
+";
+
+    // With both planes selected, each one gets its own sprite
+    let chip8 = run(SOURCE, 2);
+    assert_eq!((planes(&chip8, 0, 0), planes(&chip8, 1, 0)), (0b11, 0b01));
+
+    // Clearing only affects the selected planes
+    let mut chip8 = run(SOURCE, 4);
+    assert_eq!((planes(&chip8, 0, 0), planes(&chip8, 1, 0)), (0b10, 0));
+
+    let err = chip8.tick().unwrap_err();
+    assert!(
+        err.to_string().contains("Invalid plane selection"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn store_and_load_ranges() {
+    const SOURCE: &str = "\
+        LD V1, 1
+        LD V2, 2
+        LD V3, 3
+        LD I, 0x300
+        db 0x51, 0x32   ; SAVE V1 - V3
+        LD I, 0x310
+        db 0x53, 0x12   ; SAVE V3 - V1
+        LD I, 0x300
+        db 0x54, 0x63   ; LOAD V4 - V6
+        db 0x5C, 0xA3   ; LOAD VC - VA
+";
+
+    let chip8 = run(SOURCE, 10);
+    let state = chip8.get_state();
+    assert_eq!(state.memory[0x300..0x303], [1, 2, 3]);
+    assert_eq!(state.memory[0x310..0x313], [3, 2, 1]);
+    assert_eq!(state.registers[4..7], [1, 2, 3]);
+    assert_eq!(state.registers[0xA..0xD], [3, 2, 1]);
+    // I is left untouched
+    assert_eq!(state.i_register, 0x300);
+}
+
+#[test]
+fn scroll_up() {
+    const SOURCE: &str = "\
+        LD V1, 2
+        DRW V0, V1, 5
+        db 0x00, 0xD2   ; SCROLL-UP 2
+";
+
+    let chip8 = run(SOURCE, 2);
+    assert_eq!((planes(&chip8, 1, 0), planes(&chip8, 1, 2)), (0, 1));
+
+    let chip8 = run(SOURCE, 3);
+    assert_eq!((planes(&chip8, 1, 0), planes(&chip8, 1, 2)), (1, 0));
+    assert_eq!(planes(&chip8, 1, 6), 0);
+}
+
+#[test]
+fn long_load_i() {
+    const SOURCE: &str = "\
+        db 0xF0, 0x00, 0x12, 0x34   ; LD I, 0x1234
+        SE V0, 0
+        db 0xF0, 0x00, 0x56, 0x78   ; LD I, 0x5678
+        LD V1, 1
+";
+
+    let chip8 = run(SOURCE, 1);
+    assert_eq!(chip8.get_state().i_register, 0x1234);
+    assert_eq!(chip8.get_state().pc, 0x204);
+
+    // Skips step over all 4 bytes
+    let chip8 = run(SOURCE, 3);
+    assert_eq!(chip8.get_state().i_register, 0x1234);
+    assert_eq!(chip8.get_state().registers[1], 1);
+    assert_eq!(chip8.get_state().pc, 0x20C);
+}
+
+#[test]
+fn audio_pattern_and_pitch() {
+    const SOURCE: &str = "\
+        LD I, pattern
+        db 0xF0, 0x02   ; AUDIO
+        LD V5, 0x70
+        db 0xF5, 0x3A   ; PITCH V5
+        db 0xF0, 0x00, 0xFF, 0xF8   ; LD I, 0xFFF8
+        db 0xF0, 0x02   ; AUDIO
+pattern:
+        db 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+";
+
+    let mut chip8 = run(SOURCE, 5);
+    let state = chip8.get_state();
+    let pattern: Vec<u8> = (0..16).collect();
+    assert_eq!(state.audio_pattern[..], pattern[..]);
+    assert_eq!(state.pitch, 0x70);
+
+    // The pattern must fit in memory
+    let err = chip8.tick().unwrap_err();
+    assert!(err.to_string().contains("out of bounds"), "{}", err);
+}